@@ -0,0 +1,234 @@
+use super::error::Error;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/*
+ * annotation.rs
+ * =============
+ * This file contains readers for reference CDS annotations (GFF3, GenBank and PTT), these are used when training
+ * new models from an annotated genome and when evaluating predictions (see evaluation.rs).
+ */
+
+/**
+ * A single CDS feature, coordinates are 1-based and inclusive (like in GFF3), start is always the leftmost
+ * coordinate regardless of the strand.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct CdsFeature {
+  pub seqid: String,
+  pub start: usize,
+  pub end: usize,
+  pub forward: bool,
+}
+
+/**
+ * This method will read all CDS features from the specified annotation file, the format (GFF3, GenBank or PTT) is
+ * detected from the contents of the file.
+ */
+pub fn read_annotation(path: &str) -> Result<Vec<CdsFeature>, Error> {
+  let io_error = |source| Error::Io {
+    path: path.to_string(),
+    source,
+  };
+  let file = File::open(path).map_err(io_error)?;
+  let lines: Vec<String> = BufReader::new(file)
+    .lines()
+    .collect::<Result<Vec<String>, _>>()
+    .map_err(io_error)?;
+
+  let first_line = lines
+    .iter()
+    .find(|line| !line.trim().is_empty())
+    .map(|line| line.as_str())
+    .unwrap_or("");
+  if first_line.starts_with("LOCUS") {
+    parse_genbank(&lines, path)
+  } else if lines.iter().take(3).any(|line| line.starts_with("Location\t")) {
+    let seqid = Path::new(path).file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    parse_ptt(&lines, &seqid, path)
+  } else {
+    parse_gff(&lines, path)
+  }
+}
+
+/**
+ * This method will check the coordinates of a CDS feature read from the specified line (1-based) of an annotation
+ * file, the coordinates are 1-based and inclusive so a coordinate of 0 or a start after the end is an InputFormat
+ * error.
+ */
+pub fn check_coordinates(start: usize, end: usize, line: usize, name: &str) -> Result<(), Error> {
+  if start == 0 || start > end {
+    return Err(Error::InputFormat {
+      record: format!("at line {} of {}", line, name),
+      message: format!("invalid CDS coordinates {}..{}, expected 1-based coordinates with start <= end", start, end),
+    });
+  }
+  Ok(())
+}
+
+/**
+ * Parses the CDS features of a GFF3 file, CDS lines sharing the same ID (e.g. because of a programmed frameshift)
+ * are merged into a single feature spanning all of them. The name of the file is used in the InputFormat error of a
+ * CDS line with invalid coordinates.
+ */
+pub fn parse_gff(lines: &[String], name: &str) -> Result<Vec<CdsFeature>, Error> {
+  let mut features: Vec<CdsFeature> = Vec::new();
+  let mut by_id: HashMap<(String, String), usize> = HashMap::new();
+
+  for (line_number, line) in lines.iter().enumerate() {
+    if line.starts_with("##FASTA") {
+      break;
+    }
+    if line.starts_with('#') || line.trim().is_empty() {
+      continue;
+    }
+    let columns: Vec<&str> = line.split('\t').collect();
+    if columns.len() < 9 || columns[2] != "CDS" {
+      continue;
+    }
+    let (start, end) = match (columns[3].parse::<usize>(), columns[4].parse::<usize>()) {
+      (Ok(start), Ok(end)) => (start, end),
+      _ => (0, 0),
+    };
+    check_coordinates(start, end, line_number + 1, name)?;
+    let feature = CdsFeature {
+      seqid: columns[0].to_string(),
+      start,
+      end,
+      forward: columns[6] != "-",
+    };
+    let id = columns[8]
+      .split(';')
+      .find_map(|attribute| attribute.strip_prefix("ID="))
+      .map(|id| id.to_string());
+
+    match id {
+      Some(id) => match by_id.get(&(feature.seqid.clone(), id.clone())) {
+        Some(&index) => {
+          features[index].start = features[index].start.min(feature.start);
+          features[index].end = features[index].end.max(feature.end);
+        }
+        None => {
+          by_id.insert((feature.seqid.clone(), id), features.len());
+          features.push(feature);
+        }
+      },
+      None => features.push(feature),
+    }
+  }
+  Ok(features)
+}
+
+/**
+ * Parses the CDS features of a GenBank flat file, the sequence id of each feature is the accession.version of
+ * its record (or the LOCUS name when no VERSION line is present). The name of the file is used in the InputFormat
+ * error of a CDS location with invalid coordinates.
+ */
+pub fn parse_genbank(lines: &[String], name: &str) -> Result<Vec<CdsFeature>, Error> {
+  let mut features = Vec::new();
+  let mut seqid = String::new();
+  let mut in_features = false;
+  /* the location of the current CDS feature and the line (1-based) it starts on */
+  let mut location: Option<(String, usize)> = None;
+
+  for (line_number, line) in lines.iter().enumerate() {
+    if line.starts_with("LOCUS") {
+      seqid = line.split_whitespace().nth(1).unwrap_or("").to_string();
+      in_features = false;
+    } else if line.starts_with("VERSION") {
+      if let Some(version) = line.split_whitespace().nth(1) {
+        seqid = version.to_string();
+      }
+    } else if line.starts_with("FEATURES") {
+      in_features = true;
+    } else if in_features && !line.starts_with(' ') {
+      // end of the FEATURES table (ORIGIN, CONTIG, //, ...)
+      if let Some((loc, loc_line)) = location.take() {
+        features.extend(parse_genbank_location(&seqid, &loc, loc_line, name)?);
+      }
+      in_features = false;
+    } else if in_features {
+      let key = line.get(5..21).unwrap_or("").trim();
+      let value = line.get(21..).unwrap_or("").trim();
+      if !key.is_empty() {
+        // a new feature starts, so the previous CDS location is complete
+        if let Some((loc, loc_line)) = location.take() {
+          features.extend(parse_genbank_location(&seqid, &loc, loc_line, name)?);
+        }
+        if key == "CDS" {
+          location = Some((value.to_string(), line_number + 1));
+        }
+      } else if let Some((loc, loc_line)) = location.as_mut() {
+        if value.starts_with('/') {
+          features.extend(parse_genbank_location(&seqid, loc, *loc_line, name)?);
+          location = None;
+        } else {
+          loc.push_str(value);
+        }
+      }
+    }
+  }
+  if let Some((loc, loc_line)) = location.take() {
+    features.extend(parse_genbank_location(&seqid, &loc, loc_line, name)?);
+  }
+  Ok(features)
+}
+
+/**
+ * Parses the CDS features of a PTT (protein table) file of NCBI, which starts with a title, a protein count and a
+ * header line. PTT files have no sequence ids, so all features get the specified seqid (read_annotation uses the
+ * file name without extension, which is the accession for the PTT files of NCBI). The name of the file is used in
+ * the InputFormat error of a location with invalid coordinates.
+ */
+pub fn parse_ptt(lines: &[String], seqid: &str, name: &str) -> Result<Vec<CdsFeature>, Error> {
+  let mut features = Vec::new();
+  for (line_number, line) in lines.iter().enumerate() {
+    let columns: Vec<&str> = line.split('\t').collect();
+    let (start, end) = match columns[0].split_once("..") {
+      Some(location) if columns.len() > 1 => location,
+      _ => continue,
+    };
+    let (start, end) = match (start.trim().parse::<usize>(), end.trim().parse::<usize>()) {
+      (Ok(start), Ok(end)) => (start, end),
+      _ => continue,
+    };
+    check_coordinates(start, end, line_number + 1, name)?;
+    features.push(CdsFeature {
+      seqid: seqid.to_string(),
+      start,
+      end,
+      forward: columns[1].trim() != "-",
+    });
+  }
+  Ok(features)
+}
+
+/**
+ * Converts a GenBank location string such as complement(join(<1..200,300..>410)) into a single CdsFeature
+ * spanning all of its parts, remote locations (e.g. J00194.1:100..202) are not supported and are skipped. The
+ * location starts on the specified line of the file, for the InputFormat error of a location with a coordinate of 0.
+ */
+fn parse_genbank_location(seqid: &str, location: &str, line: usize, name: &str) -> Result<Option<CdsFeature>, Error> {
+  if location.contains(':') {
+    return Ok(None);
+  }
+  let forward = !location.contains("complement");
+  let coordinates: Vec<usize> = location
+    .split(|c: char| !c.is_ascii_digit())
+    .filter(|part| !part.is_empty())
+    .filter_map(|part| part.parse::<usize>().ok())
+    .collect();
+  let (start, end) = match (coordinates.iter().min(), coordinates.iter().max()) {
+    (Some(&start), Some(&end)) => (start, end),
+    _ => return Ok(None),
+  };
+  check_coordinates(start, end, line, name)?;
+  Ok(Some(CdsFeature {
+    seqid: seqid.to_string(),
+    start,
+    end,
+    forward,
+  }))
+}
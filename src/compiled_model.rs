@@ -0,0 +1,143 @@
+use super::error::Error;
+use super::train::{Train, HMM};
+use memmap2::Mmap;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+
+/*
+ * compiled_model.rs
+ * =================
+ * This file contains the compact binary model format written by `fgsrs model compile`. A compiled model stores
+ * a parsed Train and HMM with all values already log-transformed, so it can be memory-mapped and loaded without
+ * any text parsing. All numbers are stored little-endian:
+ *
+ *   magic     8 bytes    "FGSRSMDL"
+ *   version   u32        FORMAT_VERSION
+ *   checksum  u32        CRC32 of the payload
+ *   length    u64        number of values in the payload
+ *   payload   f64 * length
+ */
+
+const MAGIC: &[u8; 8] = b"FGSRSMDL";
+const HEADER_LEN: usize = 24;
+
+/**
+ * Version of the binary model format, this should be increased whenever the layout of the payload changes.
+ */
+pub const FORMAT_VERSION: u32 = 1;
+
+/**
+ * This method will write the Train and HMM to the specified path in the binary model format.
+ */
+pub fn write_compiled_model(path: &Path, train: &Train, hmm: &HMM) -> Result<(), Error> {
+  let mut values = Vec::new();
+  visit_values(&mut train.clone(), &mut hmm.clone(), &mut |v| values.push(*v));
+  let payload: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+
+  let target = path.display().to_string();
+  let mut out = BufWriter::new(File::create(path).map_err(|e| Error::output(&target, e))?);
+  out
+    .write_all(MAGIC)
+    .and_then(|_| out.write_all(&FORMAT_VERSION.to_le_bytes()))
+    .and_then(|_| out.write_all(&crc32fast::hash(&payload).to_le_bytes()))
+    .and_then(|_| out.write_all(&(values.len() as u64).to_le_bytes()))
+    .and_then(|_| out.write_all(&payload))
+    .and_then(|_| out.flush())
+    .map_err(|e| Error::output(&target, e))
+}
+
+/**
+ * This method will read a Train and HMM from a file in the binary model format, the file is memory-mapped.
+ */
+pub fn read_compiled_model(path: &Path) -> Result<(Train, HMM), Error> {
+  let io_error = |source| Error::Io {
+    path: path.display().to_string(),
+    source,
+  };
+  let file = File::open(path).map_err(io_error)?;
+  // The mapping is only read while parsing and dropped before returning
+  let mmap = unsafe { Mmap::map(&file).map_err(io_error)? };
+  parse_compiled_model(&mmap, &path.display().to_string())
+}
+
+/**
+ * This method will parse a Train and HMM from the contents of a file in the binary model format, the file name
+ * is only used in error messages.
+ */
+pub fn parse_compiled_model(bytes: &[u8], file: &str) -> Result<(Train, HMM), Error> {
+  let invalid_data = |message: &str| Error::model_format(file, None, message);
+  if bytes.len() < HEADER_LEN || &bytes[0..8] != MAGIC {
+    return Err(invalid_data("not a compiled fgsrs model"));
+  }
+  let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+  if version != FORMAT_VERSION {
+    return Err(invalid_data(&format!(
+      "unsupported model format version {} (expected {})",
+      version, FORMAT_VERSION
+    )));
+  }
+  let checksum = u32::from_le_bytes(bytes[12..16].try_into().unwrap());
+  let length = u64::from_le_bytes(bytes[16..24].try_into().unwrap()) as usize;
+
+  let mut train = Train::empty();
+  let mut hmm = HMM::empty();
+  let mut expected = 0;
+  visit_values(&mut train, &mut hmm, &mut |_| expected += 1);
+  let payload = &bytes[HEADER_LEN..];
+  if length != expected || payload.len() != expected * 8 {
+    return Err(invalid_data("unexpected model size"));
+  }
+  if crc32fast::hash(payload) != checksum {
+    return Err(invalid_data("checksum mismatch"));
+  }
+
+  let mut values = payload
+    .chunks_exact(8)
+    .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()));
+  visit_values(&mut train, &mut hmm, &mut |v| *v = values.next().unwrap());
+  Ok((train, hmm))
+}
+
+/**
+ * This method will check whether the file at the specified path starts with the magic bytes of a compiled model.
+ */
+pub fn is_compiled_model(path: &Path) -> bool {
+  let mut magic = [0; 8];
+  match File::open(path) {
+    Ok(mut file) => file.read_exact(&mut magic).is_ok() && &magic == MAGIC,
+    Err(_) => false,
+  }
+}
+
+/**
+ * Helper method to visit every value stored in a compiled model, in the order of the payload. Only the HMM fields
+ * read from the train file are stored, the remaining fields are filled in per sequence by load_cg_bin.
+ */
+fn visit_values(train: &mut Train, hmm: &mut HMM, f: &mut dyn FnMut(&mut f64)) {
+  for table in [&mut train.trans, &mut train.rtrans] {
+    table.iter_mut().flatten().flatten().flatten().for_each(&mut *f);
+  }
+  for table in [
+    &mut train.noncoding,
+    &mut train.start,
+    &mut train.stop,
+    &mut train.start1,
+    &mut train.stop1,
+  ] {
+    table.iter_mut().flatten().flatten().for_each(&mut *f);
+  }
+  for table in [
+    &mut train.s_dist,
+    &mut train.e_dist,
+    &mut train.s1_dist,
+    &mut train.e1_dist,
+    &mut hmm.tr_m_i,
+    &mut hmm.tr_i_i,
+  ] {
+    table.iter_mut().flatten().for_each(&mut *f);
+  }
+  hmm.initial_state.iter_mut().for_each(&mut *f);
+  hmm.tr.iter_mut().for_each(&mut *f);
+}
@@ -0,0 +1,333 @@
+use super::error::Error;
+use super::genetic_code::{codon_index, GeneticCode};
+use super::train::NUM_CG_BINS;
+
+/*
+ * config.rs
+ * =========
+ * This file contains the PredictionConfig struct, which bundles the settings of a single run of the gene caller
+ * that are not part of the trained model: the type of input, the genetic code, the start codons recognized by the
+ * model, the window used for refining the start codons of whole genome predictions, the length thresholds for
+ * sequences and genes and the choice of the CG bin of the model.
+ */
+
+/**
+ * Minimum length of a gene (in bp) predicted on a sequence read by FragGeneScan 1.31, only longer genes are reported.
+ */
+pub const READ_MIN_GENE_LENGTH: usize = 60;
+
+/**
+ * Minimum length of a gene (in bp) predicted on a whole genome sequence by FragGeneScan 1.31.
+ */
+pub const GENOME_MIN_GENE_LENGTH: usize = 120;
+
+/**
+ * Minimum length of an input sequence (in bp) used by FragGeneScan 1.31, only longer sequences are processed.
+ */
+pub const MIN_SEQUENCE_LENGTH: usize = 70;
+
+/**
+ * Maximum length of a sequence (in bp) for which the full viterbi matrices are kept in memory, longer sequences are
+ * decoded with checkpoints (see viterbi::viterbi).
+ */
+pub const DEFAULT_MAX_MATRIX_LENGTH: usize = 1_000_000;
+
+/**
+ * Default overlap (in bp) between consecutive windows of a long sequence, see PredictionConfig::with_windows.
+ */
+pub const DEFAULT_WINDOW_OVERLAP: usize = 20_000;
+
+/**
+ * Specifies which posterior probabilities are computed with the forward-backward algorithm (see posterior.rs), on
+ * top of the most likely path found by viterbi::viterbi.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PosteriorMode {
+  /* only the viterbi path (default) */
+  Off,
+  /* the coding, start and stop posterior of every predicted gene */
+  Genes,
+  /* the gene posteriors and the coding posterior of every base */
+  Bases,
+}
+
+/**
+ * Specifies how the CG bin of the trained model (the CG percentage minus 26, clamped to 0..NUM_CG_BINS) is chosen
+ * for a sequence.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CgBin {
+  /* the CG content of every sequence, like FragGeneScan 1.31 (default) */
+  Sequence,
+  /* the CG content of the input record (see input::Record), or of the sequence when the record has none */
+  Record,
+  /* the same bin for every sequence */
+  Fixed(usize),
+}
+
+/**
+ * The PredictionConfig is passed to viterbi::viterbi, the start codons default to the start codons of the genetic
+ * code which are modeled by FragGeneScan (ATG, GTG and TTG).
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct PredictionConfig {
+  wholegenome: bool,
+  genetic_code: GeneticCode,
+  start_codons: Option<Vec<String>>,
+  refine_window: Option<usize>,
+  min_gene_length: Option<usize>,
+  min_sequence_length: usize,
+  posteriors: PosteriorMode,
+  max_matrix_length: usize,
+  window_length: Option<usize>,
+  window_overlap: usize,
+  circular: bool,
+  cg_bin: CgBin,
+  starts: [bool; 65],
+  reverse_starts: [bool; 65],
+}
+
+impl PredictionConfig {
+  /**
+   * Builds the default configuration, the parameter wholegenome specifies whether the input consists of full
+   * genome sequences (true) or sequence reads (false).
+   */
+  pub fn new(wholegenome: bool) -> PredictionConfig {
+    let mut config = PredictionConfig {
+      wholegenome,
+      genetic_code: GeneticCode::default(),
+      start_codons: None,
+      refine_window: None,
+      min_gene_length: None,
+      min_sequence_length: MIN_SEQUENCE_LENGTH,
+      posteriors: PosteriorMode::Off,
+      max_matrix_length: DEFAULT_MAX_MATRIX_LENGTH,
+      window_length: None,
+      window_overlap: DEFAULT_WINDOW_OVERLAP,
+      circular: false,
+      cg_bin: CgBin::Sequence,
+      starts: [false; 65],
+      reverse_starts: [false; 65],
+    };
+    config.update_starts();
+    config
+  }
+
+  /**
+   * Sets the genetic code used for recognizing stop codons and translating the predicted genes.
+   */
+  pub fn with_genetic_code(mut self, genetic_code: GeneticCode) -> PredictionConfig {
+    self.genetic_code = genetic_code;
+    self.update_starts();
+    self
+  }
+
+  /**
+   * Sets the start codons recognized by the model (e.g. ["ATG", "GTG", "TTG", "CTG"]), start codons other than
+   * ATG, GTG and TTG get the start probability of TTG.
+   */
+  pub fn with_start_codons(mut self, start_codons: &[&str]) -> Result<PredictionConfig, Error> {
+    let mut codons = Vec::new();
+    for codon in start_codons {
+      let codon = codon.trim().to_ascii_uppercase();
+      if codon.len() != 3 || !codon.chars().all(|c| "ACGT".contains(c)) {
+        return Err(Error::InvalidArgument(format!(
+          "Invalid start codon '{}', start codons should consist of three nucleotides (A, C, G or T).",
+          codon
+        )));
+      }
+      codons.push(codon);
+    }
+    self.start_codons = Some(codons);
+    self.update_starts();
+    Ok(self)
+  }
+
+  /**
+   * Enables the refinement of the start codons of whole genome predictions, which moves a start codon at most the
+   * specified distance (in bp) upstream, never past the first in-frame stop codon (usize::MAX only stops there).
+   * Without a window (the default) the start codons are not refined, like FragGeneScan 1.31.
+   */
+  pub fn with_refine_window(mut self, refine_window: Option<usize>) -> PredictionConfig {
+    self.refine_window = refine_window;
+    self
+  }
+
+  /**
+   * Sets the minimum length of a gene (in bp), only longer genes are reported. Defaults to READ_MIN_GENE_LENGTH
+   * for sequence reads and GENOME_MIN_GENE_LENGTH for whole genome sequences.
+   */
+  pub fn with_min_gene_length(mut self, min_gene_length: usize) -> PredictionConfig {
+    self.min_gene_length = Some(min_gene_length);
+    self
+  }
+
+  /**
+   * Sets the minimum length of an input sequence (in bp), only longer sequences should be passed to the gene caller
+   * (default: MIN_SEQUENCE_LENGTH).
+   */
+  pub fn with_min_sequence_length(mut self, min_sequence_length: usize) -> PredictionConfig {
+    self.min_sequence_length = min_sequence_length;
+    self
+  }
+
+  /**
+   * Sets which posterior probabilities are computed, computing posteriors roughly triples the running time.
+   */
+  pub fn with_posteriors(mut self, posteriors: PosteriorMode) -> PredictionConfig {
+    self.posteriors = posteriors;
+    self
+  }
+
+  /**
+   * Sets the maximum length of a sequence (in bp) for which the full viterbi matrices are kept in memory (default:
+   * DEFAULT_MAX_MATRIX_LENGTH). Longer sequences take about 70% longer but only use O(sqrt(length)) memory for the
+   * matrices, the predictions are identical. The posteriors always need the full matrices.
+   */
+  pub fn with_max_matrix_length(mut self, max_matrix_length: usize) -> PredictionConfig {
+    self.max_matrix_length = max_matrix_length;
+    self
+  }
+
+  /**
+   * Splits sequences longer than window_length (in bp) into windows which overlap by overlap bp, the windows are
+   * predicted in parallel and their predictions are stitched together (see windows.rs). Consecutive windows are
+   * cut in the middle of their overlap and every gene is taken from the window on the side of the cut where its stop
+   * codon lies, so only genes longer than half of the overlap which span a cut may differ from a single pass.
+   * Without a window length (the default), every sequence is predicted in a single pass.
+   */
+  pub fn with_windows(
+    mut self,
+    window_length: Option<usize>,
+    overlap: usize,
+  ) -> Result<PredictionConfig, Error> {
+    if window_length.is_some_and(|length| length <= overlap) {
+      return Err(Error::InvalidArgument(String::from(
+        "The window length should be larger than the window overlap.",
+      )));
+    }
+    self.window_length = window_length;
+    self.window_overlap = overlap;
+    Ok(self)
+  }
+
+  /**
+   * Sets whether all sequences are circular (e.g. complete chromosomes and plasmids), genes spanning the origin of
+   * a circular sequence are predicted across the junction (see GenePredictor::predict_circular).
+   */
+  pub fn with_circular(mut self, circular: bool) -> PredictionConfig {
+    self.circular = circular;
+    self
+  }
+
+  /**
+   * Sets how the CG bin of the model is chosen for every sequence, fixed bins should be below NUM_CG_BINS.
+   */
+  pub fn with_cg_bin(mut self, cg_bin: CgBin) -> Result<PredictionConfig, Error> {
+    if let CgBin::Fixed(bin) = cg_bin {
+      if bin >= NUM_CG_BINS {
+        return Err(Error::InvalidArgument(format!(
+          "Invalid CG bin {}, the bin should be between 0 and {}.",
+          bin,
+          NUM_CG_BINS - 1
+        )));
+      }
+    }
+    self.cg_bin = cg_bin;
+    Ok(self)
+  }
+
+  pub fn wholegenome(&self) -> bool {
+    self.wholegenome
+  }
+
+  pub fn genetic_code(&self) -> &GeneticCode {
+    &self.genetic_code
+  }
+
+  pub fn refine_window(&self) -> Option<usize> {
+    self.refine_window
+  }
+
+  pub fn min_gene_length(&self) -> usize {
+    self.min_gene_length.unwrap_or_else(|| self.classic_min_gene_length())
+  }
+
+  pub fn min_sequence_length(&self) -> usize {
+    self.min_sequence_length
+  }
+
+  pub fn posteriors(&self) -> PosteriorMode {
+    self.posteriors
+  }
+
+  pub fn max_matrix_length(&self) -> usize {
+    self.max_matrix_length
+  }
+
+  pub fn window_length(&self) -> Option<usize> {
+    self.window_length
+  }
+
+  pub fn window_overlap(&self) -> usize {
+    self.window_overlap
+  }
+
+  pub fn circular(&self) -> bool {
+    self.circular
+  }
+
+  pub fn cg_bin(&self) -> CgBin {
+    self.cg_bin
+  }
+
+  /**
+   * Returns the minimum gene length used by FragGeneScan 1.31 for the type of input of this config.
+   */
+  pub fn classic_min_gene_length(&self) -> usize {
+    if self.wholegenome {
+      GENOME_MIN_GENE_LENGTH
+    } else {
+      READ_MIN_GENE_LENGTH
+    }
+  }
+
+  /**
+   * Checks whether the codon with the specified index (see genetic_code::codon_index) is a start codon.
+   */
+  #[inline]
+  pub fn is_start(&self, codon: usize) -> bool {
+    self.starts[codon]
+  }
+
+  /**
+   * Checks whether the reverse complement of the codon with the specified index is a start codon, i.e. whether
+   * the codon is a start codon on the minus strand.
+   */
+  #[inline]
+  pub fn is_reverse_start(&self, codon: usize) -> bool {
+    self.reverse_starts[codon]
+  }
+
+  /**
+   * Helper method to fill in the start codon lookup tables from the start codons and the genetic code.
+   */
+  fn update_starts(&mut self) {
+    for a in 0..4 {
+      for b in 0..4 {
+        for c in 0..4 {
+          let codon = codon_index(a, b, c);
+          let start = match &self.start_codons {
+            Some(start_codons) => {
+              let name: String = [a, b, c].iter().map(|&n| ['A', 'C', 'G', 'T'][n]).collect();
+              start_codons.contains(&name)
+            }
+            None => self.genetic_code.is_start(codon),
+          };
+          self.starts[codon] = start;
+          self.reverse_starts[codon_index(3 - c, 3 - b, 3 - a)] = start;
+        }
+      }
+    }
+  }
+}
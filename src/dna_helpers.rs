@@ -1,7 +1,8 @@
-use super::train::{Train, HMM};
+use super::config::PredictionConfig;
+use super::train::{Train, HMM, NUM_CG_BINS};
 use rayon::prelude::*;
 
-/**
+/*
  * dna_helpers.rs
  * ==============
  * This file contains a number of helper methods related to processing DNA/AA values
@@ -9,54 +10,52 @@ use rayon::prelude::*;
  * correct training values based on CG-score.
  */
 
-const CODON: &'static [char] = &['A', 'C', 'G', 'T', 'N'];
-
-const CODON_CODE: &'static [char] = &[
-    'K', 'N', 'K', 'N', 'T', 'T', 'T', 'T', 'R', 'S', 'R', 'S', 'I', 'I', 'M', 'I', 'Q', 'H', 'Q',
-    'H', 'P', 'P', 'P', 'P', 'R', 'R', 'R', 'R', 'L', 'L', 'L', 'L', 'E', 'D', 'E', 'D', 'A', 'A',
-    'A', 'A', 'G', 'G', 'G', 'G', 'V', 'V', 'V', 'V', '*', 'Y', '*', 'Y', 'S', 'S', 'S', 'S', '*',
-    'C', 'W', 'C', 'L', 'F', 'L', 'F', 'X',
-];
-
-const ANTI_CODON_CODE: &'static [char] = &[
-    'F', 'V', 'L', 'I', 'C', 'G', 'R', 'S', 'S', 'A', 'P', 'T', 'Y', 'D', 'H', 'N', 'L', 'V', 'L',
-    'M', 'W', 'G', 'R', 'R', 'S', 'A', 'P', 'T', '*', 'E', 'Q', 'K', 'F', 'V', 'L', 'I', 'C', 'G',
-    'R', 'S', 'S', 'A', 'P', 'T', 'Y', 'D', 'H', 'N', 'L', 'V', 'L', 'I', '*', 'G', 'R', 'R', 'S',
-    'A', 'P', 'T', '*', 'E', 'Q', 'K', 'X',
-];
+const CODON: &[char] = &['A', 'C', 'G', 'T', 'N'];
 
 /*
 Code for loading the relevant information into the HMM struct for this CG value of the sequence
 */
-pub fn get_prob_from_cg(hmm: &mut HMM, train: &Train, seq: &String) -> usize {
+pub fn get_prob_from_cg(hmm: &mut HMM, train: &Train, seq: &str) -> usize {
     //change from void to int, Ye, April 18, 2016
-    let mut cg_count: usize = seq.chars().collect::<Vec<char>>().into_par_iter().map(|c|  match c {
+    let cg_bin = get_cg_bin(get_cg_content(seq));
+    load_cg_bin(hmm, train, cg_bin);
+    cg_bin
+}
+
+/**
+ * Computes the CG content of a sequence, as a percentage of its length.
+ */
+pub fn get_cg_content(seq: &str) -> f64 {
+    let cg_count: usize = seq.chars().collect::<Vec<char>>().into_par_iter().map(|c|  match c {
             'c' | 'C' | 'g' | 'G' => 1,
             _ => 0,
         }).sum();
-    
-    let len_seq = seq.len();
-
-    let cg_count_i = ((((cg_count as f64 * 1.0) / len_seq as f64) * 100.0).floor() as i32) - 26;
-    if cg_count_i < 0 {
-        cg_count = 0;
-    } else if cg_count_i > 43 {
-        cg_count = 43;
-    } else {
-        cg_count = cg_count_i as usize;
-    }
-    hmm.e_m = train.trans[cg_count].clone();
-    hmm.e_m_1 = train.rtrans[cg_count].clone();
-    hmm.tr_r_r = train.noncoding[cg_count].clone();
-    hmm.tr_s = train.start[cg_count].clone();
-    hmm.tr_e = train.stop[cg_count].clone();
-    hmm.tr_s_1 = train.start1[cg_count].clone();
-    hmm.tr_e_1 = train.stop1[cg_count].clone();
-    hmm.s_dist = train.s_dist[cg_count].clone();
-    hmm.e_dist = train.e_dist[cg_count].clone();
-    hmm.s1_dist = train.s1_dist[cg_count].clone();
-    hmm.e1_dist = train.e1_dist[cg_count].clone();
-    cg_count
+
+    ((cg_count as f64 * 1.0) / seq.len() as f64) * 100.0
+}
+
+/**
+ * Converts a CG percentage into the CG bin of the trained model, the percentage minus 26 clamped between 0 and 43.
+ */
+pub fn get_cg_bin(cg_content: f64) -> usize {
+    (cg_content.floor() as i32 - 26).clamp(0, NUM_CG_BINS as i32 - 1) as usize
+}
+
+/*
+Code for loading the relevant information into the HMM struct for the specified CG bin
+*/
+pub fn load_cg_bin(hmm: &mut HMM, train: &Train, cg_bin: usize) {
+    hmm.e_m = train.trans[cg_bin].clone();
+    hmm.e_m_1 = train.rtrans[cg_bin].clone();
+    hmm.tr_r_r = train.noncoding[cg_bin].clone();
+    hmm.tr_s = train.start[cg_bin].clone();
+    hmm.tr_e = train.stop[cg_bin].clone();
+    hmm.tr_s_1 = train.start1[cg_bin].clone();
+    hmm.tr_e_1 = train.stop1[cg_bin].clone();
+    hmm.s_dist = train.s_dist[cg_bin].clone();
+    hmm.e_dist = train.e_dist[cg_bin].clone();
+    hmm.s1_dist = train.s1_dist[cg_bin].clone();
+    hmm.e1_dist = train.e1_dist[cg_bin].clone();
 }
 
 /**
@@ -72,6 +71,31 @@ pub fn nt2int(nt: char) -> usize {
     }
 }
 
+/**
+ * Returns the complementary nucleotide, characters other than ACGT are complemented to N
+ */
+pub fn complement(nt: char) -> char {
+    CODON[nt2int_rc(&nt)]
+}
+
+/**
+ * Encodes a sequence with a byte per base, the integer of every nucleotide (see nt2int). This encoding is shared by
+ * viterbi and the forward-backward algorithm.
+ */
+pub fn encode_bases(sequence: &str) -> Vec<u8> {
+    sequence.chars().map(|nt| nt2int(nt) as u8).collect()
+}
+
+/**
+ * Returns the complement of an encoded nucleotide, other characters than ACGT (4) stay 4
+ */
+pub fn complement_nt(nt: u8) -> u8 {
+    match nt {
+        0..=3 => 3 - nt,
+        _ => 4,
+    }
+}
+
 fn nt2int_rc(nt: &char) -> usize {
     match nt {
         'A' | 'a' => 3,
@@ -83,25 +107,25 @@ fn nt2int_rc(nt: &char) -> usize {
 }
 
 /**
- * Converts a transition specifier string to an integer
+ * Converts a transition specifier string to an integer, None is returned for unknown transitions
  */
-pub fn tr2int(tr: &String) -> usize {
-    match tr.as_str() {
-        "MM" => 0,
-        "MI" => 1,
-        "MD" => 2,
-        "II" => 3,
-        "IM" => 4,
-        "DD" => 5,
-        "DM" => 6,
-        "GE" => 7,
-        "GG" => 8,
-        "ER" => 9,
-        "RS" => 10,
-        "RR" => 11,
-        "ES" => 12,
-        "ES1" => 13,
-        _ => panic!(format!("Unknown transition: {}", tr)),
+pub fn tr2int(tr: &str) -> Option<usize> {
+    match tr {
+        "MM" => Some(0),
+        "MI" => Some(1),
+        "MD" => Some(2),
+        "II" => Some(3),
+        "IM" => Some(4),
+        "DD" => Some(5),
+        "DM" => Some(6),
+        "GE" => Some(7),
+        "GG" => Some(8),
+        "ER" => Some(9),
+        "RS" => Some(10),
+        "RR" => Some(11),
+        "ES" => Some(12),
+        "ES1" => Some(13),
+        _ => None,
     }
 }
 
@@ -133,7 +157,20 @@ pub fn trinucleotide(a: &char, b: &char, c: &char) -> usize {
     result
 }
 
-fn trinucleotide_pep(a: &char, b: &char, c: &char) -> usize {
+/**
+ * Converts a trinucleotide of encoded nucleotides (see encode_bases) to an integer like trinucleotide, other
+ * characters than ACGT count as A
+ */
+pub fn trinucleotide_nts(a: u8, b: u8, c: u8) -> usize {
+    let nt = |nt: u8| if nt < 4 { nt as usize } else { 0 };
+    nt(a) * 16 + nt(b) * 4 + nt(c)
+}
+
+/**
+ * Converts a trinucleotide to an integer like trinucleotide, codons containing other characters than ACGT are
+ * converted to 64
+ */
+pub(crate) fn trinucleotide_pep(a: &char, b: &char, c: &char) -> usize {
     let mut result = match a {
         'A' | 'a' => 0,
         'C' | 'c' => 16,
@@ -171,47 +208,48 @@ fn trinucleotide_pep(a: &char, b: &char, c: &char) -> usize {
 /**
  * Get reverse coding DNA
  */
-pub fn get_rc_dna(dna: &Vec<char>) -> Vec<char> {
-    dna.par_iter().rev().map(|c| CODON[nt2int_rc(&*c)]).collect()
+pub fn get_rc_dna(dna: &[char]) -> Vec<char> {
+    dna.par_iter().rev().map(|c| CODON[nt2int_rc(c)]).collect()
 }
 
 /**
- * Get protein sequence from dna sequence
+ * Get protein sequence from dna sequence, translated with the genetic code and start codons of the config
  */
-pub fn get_protein(dna: &Vec<char>, strand: bool, wholegenome: bool) -> Vec<char> {
+pub fn get_protein(dna: &[char], strand: bool, config: &PredictionConfig) -> Vec<char> {
+    let code = config.genetic_code();
     let len = dna.len();
     let mut protein = vec!['\0'; len / 3];
     if strand {
         protein.par_iter_mut().enumerate().for_each(|(i, p)| {
             let i = i * 3;
-            *p = CODON_CODE[trinucleotide_pep(&dna[i], &dna[i + 1], &dna[i + 2])]
+            *p = code.translate(trinucleotide_pep(&dna[i], &dna[i + 1], &dna[i + 2]))
         })
     } else {
         for i in (0..len).step_by(3) {
             if (len - i) / 3 > 0 {
                 protein[(len - i) / 3 - 1] =
-                    ANTI_CODON_CODE[trinucleotide_pep(&dna[i], &dna[i + 1], &dna[i + 2])];
+                    code.translate_reverse(trinucleotide_pep(&dna[i], &dna[i + 1], &dna[i + 2]));
             }
         }
     }
-    if protein[len / 3 - 1] == '*' {
+    if strand && !len.is_multiple_of(3) {
+        // like FragGeneScan, an incomplete codon at the end of a forward gene is translated as X
+        protein.push('X');
+    } else if protein[len / 3 - 1] == '*' {
         protein.pop();
     }
-    if wholegenome {
+    if !config.wholegenome() {
         return protein; //short reads, skip
     }
+    // alternative start codons (e.g. GTG, TTG) are translated as M
     if strand {
-        let s = trinucleotide_pep(&dna[0], &dna[1], &dna[2]);
-        // trinucleotide_pep(&'G', &'T', &'G') == 46
-        // trinucleotide_pep(&'T', &'T', &'G') == 62
-        if s == 46 || s == 62 {
+        let codon = trinucleotide_pep(&dna[0], &dna[1], &dna[2]);
+        if config.is_start(codon) && code.translate(codon) != 'M' {
             protein[0] = 'M';
         }
     } else {
-        let s = trinucleotide_pep(&dna[len - 3], &dna[len - 2], &dna[len - 1]);
-        // trinucleotide_pep(&'C', &'A', &'C') == 17
-        // trinucleotide_pep(&'C', &'A', &'A') == 16
-        if s == 17 || s == 16 {
+        let codon = trinucleotide_pep(&dna[len - 3], &dna[len - 2], &dna[len - 1]);
+        if config.is_reverse_start(codon) && code.translate_reverse(codon) != 'M' {
             protein[0] = 'M';
         }
     }
@@ -0,0 +1,56 @@
+/*
+ * embedded.rs
+ * ===========
+ * This file contains the model files of the train/ folder, which are compiled into the binary when the
+ * embedded-models feature is enabled. The files are stored as text and parsed by train.rs when they are used.
+ */
+
+/**
+ * The global (CG binned) model files, by file name.
+ */
+const GLOBAL_FILES: &[(&str, &str)] = &[
+  ("gene", include_str!("../train/gene")),
+  ("rgene", include_str!("../train/rgene")),
+  ("noncoding", include_str!("../train/noncoding")),
+  ("start", include_str!("../train/start")),
+  ("stop", include_str!("../train/stop")),
+  ("start1", include_str!("../train/start1")),
+  ("stop1", include_str!("../train/stop1")),
+  ("pwm", include_str!("../train/pwm")),
+];
+
+/**
+ * The train models which can be selected with -t, by name.
+ */
+const MODEL_FILES: &[(&str, &str)] = &[
+  ("454_10", include_str!("../train/454_10")),
+  ("454_30", include_str!("../train/454_30")),
+  ("454_5", include_str!("../train/454_5")),
+  ("complete", include_str!("../train/complete")),
+  ("illumina_1", include_str!("../train/illumina_1")),
+  ("illumina_10", include_str!("../train/illumina_10")),
+  ("illumina_5", include_str!("../train/illumina_5")),
+  ("sanger_10", include_str!("../train/sanger_10")),
+  ("sanger_5", include_str!("../train/sanger_5")),
+];
+
+/**
+ * This method will yield the contents of the embedded global model file with the specified name.
+ */
+pub fn global_file(name: &str) -> Option<&'static str> {
+  find(GLOBAL_FILES, name)
+}
+
+/**
+ * This method will yield the contents of the embedded train model with the specified name (e.g. illumina_5).
+ */
+pub fn model_file(name: &str) -> Option<&'static str> {
+  find(MODEL_FILES, name)
+}
+
+fn find(files: &[(&str, &'static str)], name: &str) -> Option<&'static str> {
+  files
+    .iter()
+    .find(|(file_name, _)| *file_name == name)
+    .map(|(_, contents)| *contents)
+}
@@ -0,0 +1,114 @@
+use super::train::ModelNotFound;
+use std::fmt;
+use std::io;
+
+/*
+ * error.rs
+ * ========
+ * This file contains the Error type returned by the fallible operations of the crate (loading models, reading
+ * input and writing output), main.rs maps every kind of error to its own exit code.
+ */
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+  /**
+   * A file could not be opened or read.
+   */
+  Io { path: String, source: io::Error },
+  /**
+   * The model directory or a model file could not be found.
+   */
+  ModelNotFound(ModelNotFound),
+  /**
+   * A model file is malformed, line is None for the binary model format.
+   */
+  ModelFormat {
+    file: String,
+    line: Option<usize>,
+    message: String,
+  },
+  /**
+   * An input record (or annotation record) is malformed.
+   */
+  InputFormat { record: String, message: String },
+  /**
+   * Writing to one of the outputs failed.
+   */
+  Output { target: String, source: io::Error },
+  /**
+   * An invalid command line argument or configuration value was specified.
+   */
+  InvalidArgument(String),
+}
+
+impl Error {
+  /**
+   * This method will yield the exit code used by the fgsrs binary for this kind of error.
+   */
+  pub fn exit_code(&self) -> i32 {
+    match self {
+      Error::InvalidArgument(_) => 1,
+      Error::Io { .. } => 2,
+      Error::ModelNotFound(_) => 3,
+      Error::ModelFormat { .. } => 4,
+      Error::InputFormat { .. } => 5,
+      Error::Output { .. } => 6,
+    }
+  }
+
+  pub(crate) fn model_format(file: &str, line: Option<usize>, message: &str) -> Error {
+    Error::ModelFormat {
+      file: file.to_string(),
+      line,
+      message: message.to_string(),
+    }
+  }
+
+  pub(crate) fn output(target: &str, source: io::Error) -> Error {
+    Error::Output {
+      target: target.to_string(),
+      source,
+    }
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::Io { path, source } => write!(f, "Unable to read {}: {}", path, source),
+      Error::ModelNotFound(e) => write!(f, "{}", e),
+      Error::ModelFormat {
+        file,
+        line: Some(line),
+        message,
+      } => write!(f, "Invalid model file {} (line {}): {}", file, line, message),
+      Error::ModelFormat {
+        file,
+        line: None,
+        message,
+      } => write!(f, "Invalid model file {}: {}", file, message),
+      Error::InputFormat { record, message } => {
+        write!(f, "Invalid input record {}: {}", record, message)
+      }
+      Error::Output { target, source } => write!(f, "Unable to write {}: {}", target, source),
+      Error::InvalidArgument(message) => write!(f, "{}", message),
+    }
+  }
+}
+
+impl std::error::Error for Error {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Error::Io { source, .. } | Error::Output { source, .. } => Some(source),
+      _ => None,
+    }
+  }
+}
+
+impl From<ModelNotFound> for Error {
+  fn from(e: ModelNotFound) -> Error {
+    Error::ModelNotFound(e)
+  }
+}
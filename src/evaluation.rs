@@ -0,0 +1,371 @@
+use super::annotation::{check_coordinates, read_annotation, CdsFeature};
+use super::error::Error;
+use super::viterbi::Prediction;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/*
+ * evaluation.rs
+ * =============
+ * This file contains the evaluation of predicted genes against a reference annotation (see annotation.rs), which is
+ * used by the eval subcommand of the fgsrs binary. A predicted gene matches a reference gene when both lie on the
+ * same strand and end at the same stop codon, like the evaluations of Prodigal and GeneMark.
+ *
+ * The sequences of the predictions and the reference are matched by their accession (see sequence_key), so
+ * predictions on gi|49175990|ref|NC_000913.2| can be evaluated against NC_000913.3. When both contain a single
+ * sequence, these are always compared.
+ */
+
+/**
+ * Accuracy of the predictions at the level of genes, the specificity is the fraction of the predicted genes which
+ * match a reference gene (also known as precision).
+ */
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GeneAccuracy {
+  pub matched_reference: usize,
+  pub matched_predicted: usize,
+  pub sensitivity: f64,
+  pub specificity: f64,
+}
+
+/**
+ * Accuracy of the start codons of the matched genes.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StartAccuracy {
+  pub matched: usize,
+  pub exact: usize,
+  pub accuracy: f64,
+}
+
+/**
+ * Accuracy of the predictions at the level of nucleotides, a nucleotide is coding when it lies in a gene on either
+ * strand.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NucleotideAccuracy {
+  pub reference_coding: usize,
+  pub predicted_coding: usize,
+  pub overlap: usize,
+  pub sensitivity: f64,
+  pub specificity: f64,
+}
+
+/**
+ * The strand and frame of the predicted gene which overlaps most with every reference gene, the frame of a gene is
+ * the position of its stop codon modulo 3.
+ */
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct StrandFrameConfusion {
+  pub same_frame: usize,
+  pub other_frame: usize,
+  pub opposite_strand: usize,
+  pub no_overlap: usize,
+}
+
+/**
+ * Result of evaluate, which can be written as JSON with serde_json for tracking regressions.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EvaluationReport {
+  pub reference_genes: usize,
+  pub predicted_genes: usize,
+  pub genes: GeneAccuracy,
+  pub starts: StartAccuracy,
+  pub nucleotides: NucleotideAccuracy,
+  pub strand_frame: StrandFrameConfusion,
+}
+
+/**
+ * This method will read the predicted genes from the specified file, which holds either the metadata output (-e)
+ * or the GFF3 output (-g) of fgsrs or FragGeneScan. Any other annotation format of read_annotation works as well.
+ */
+pub fn read_predictions(path: &str) -> Result<Vec<CdsFeature>, Error> {
+  let io_error = |source| Error::Io {
+    path: path.to_string(),
+    source,
+  };
+  let file = File::open(path).map_err(io_error)?;
+  let lines: Vec<String> = BufReader::new(file)
+    .lines()
+    .collect::<Result<Vec<String>, _>>()
+    .map_err(io_error)?;
+
+  match lines.iter().find(|line| !line.trim().is_empty()) {
+    Some(line) if line.starts_with('>') => parse_metadata(&lines, path),
+    _ => read_annotation(path),
+  }
+}
+
+/**
+ * Parses the genes of the metadata output, a header line with the sequence id (and its CG bin) followed by a line
+ * per gene which starts with its start, end and strand. The name of the file is used in the InputFormat error of a
+ * gene with invalid coordinates.
+ */
+pub fn parse_metadata(lines: &[String], name: &str) -> Result<Vec<CdsFeature>, Error> {
+  let mut features = Vec::new();
+  let mut seqid = "";
+  for (line_number, line) in lines.iter().enumerate() {
+    if let Some(head) = line.strip_prefix('>') {
+      seqid = head.split_whitespace().next().unwrap_or("");
+      continue;
+    }
+    let columns: Vec<&str> = line.split('\t').collect();
+    if columns.len() < 3 {
+      continue;
+    }
+    if let (Ok(start), Ok(end)) = (columns[0].parse::<usize>(), columns[1].parse::<usize>()) {
+      check_coordinates(start, end, line_number + 1, name)?;
+      features.push(CdsFeature {
+        seqid: seqid.to_string(),
+        start,
+        end,
+        forward: columns[2] != "-",
+      });
+    }
+  }
+  Ok(features)
+}
+
+/**
+ * Converts the genes of a Prediction into CDS features, so predictions can be evaluated without writing them first.
+ */
+pub fn prediction_features(prediction: &Prediction) -> Vec<CdsFeature> {
+  prediction
+    .outs
+    .iter()
+    .map(|out| CdsFeature {
+      seqid: prediction.head.clone(),
+      start: out.dna_start_t,
+      end: out.dna_end_t,
+      forward: out.forward,
+    })
+    .collect()
+}
+
+/**
+ * This method will evaluate the predicted genes against the reference genes, see EvaluationReport.
+ */
+pub fn evaluate(reference: &[CdsFeature], predictions: &[CdsFeature]) -> EvaluationReport {
+  let reference = by_sequence(reference);
+  let mut predictions = by_sequence(predictions);
+  if reference.len() == 1 && predictions.len() == 1 {
+    let key = reference.keys().next().unwrap().clone();
+    let genes = predictions.drain().next().unwrap().1;
+    predictions.insert(key, genes);
+  }
+
+  let mut reference_genes = 0;
+  let mut predicted_genes = 0;
+  let mut matched_reference = 0;
+  let mut matched_predicted = 0;
+  let mut exact_starts = 0;
+  let mut reference_coding = 0;
+  let mut predicted_coding = 0;
+  let mut coding_overlap = 0;
+  let mut strand_frame = StrandFrameConfusion::default();
+
+  let no_genes = Vec::new();
+  let keys: HashSet<&String> = reference.keys().chain(predictions.keys()).collect();
+  for key in keys {
+    let reference = reference.get(key).unwrap_or(&no_genes);
+    let predictions = predictions.get(key).unwrap_or(&no_genes);
+    reference_genes += reference.len();
+    predicted_genes += predictions.len();
+
+    /* genes are matched by their stop codon, the start of every predicted stop codon is kept */
+    let predicted_stops: HashMap<(usize, bool), usize> =
+      predictions.iter().map(|gene| (stop(gene), start(gene))).collect();
+    let reference_stops: HashSet<(usize, bool)> = reference.iter().map(stop).collect();
+    for gene in reference {
+      if let Some(&predicted_start) = predicted_stops.get(&stop(gene)) {
+        matched_reference += 1;
+        if predicted_start == start(gene) {
+          exact_starts += 1;
+        }
+      }
+    }
+    matched_predicted += predictions
+      .iter()
+      .filter(|gene| reference_stops.contains(&stop(gene)))
+      .count();
+
+    /* nucleotides, up to the end of the last gene */
+    let length = reference.iter().chain(predictions.iter()).map(|gene| gene.end).max().unwrap_or(0);
+    let reference_mask = coding_mask(reference, length);
+    let predicted_mask = coding_mask(predictions, length);
+    for (&reference, &predicted) in reference_mask.iter().zip(&predicted_mask) {
+      reference_coding += reference as usize;
+      predicted_coding += predicted as usize;
+      coding_overlap += (reference && predicted) as usize;
+    }
+
+    confuse_strand_frame(reference, predictions, &mut strand_frame);
+  }
+
+  EvaluationReport {
+    reference_genes,
+    predicted_genes,
+    genes: GeneAccuracy {
+      matched_reference,
+      matched_predicted,
+      sensitivity: ratio(matched_reference, reference_genes),
+      specificity: ratio(matched_predicted, predicted_genes),
+    },
+    starts: StartAccuracy {
+      matched: matched_reference,
+      exact: exact_starts,
+      accuracy: ratio(exact_starts, matched_reference),
+    },
+    nucleotides: NucleotideAccuracy {
+      reference_coding,
+      predicted_coding,
+      overlap: coding_overlap,
+      sensitivity: ratio(coding_overlap, reference_coding),
+      specificity: ratio(coding_overlap, predicted_coding),
+    },
+    strand_frame,
+  }
+}
+
+/**
+ * Helper method to group the genes by the key of their sequence.
+ */
+fn by_sequence(features: &[CdsFeature]) -> HashMap<String, Vec<CdsFeature>> {
+  let mut sequences: HashMap<String, Vec<CdsFeature>> = HashMap::new();
+  for feature in features {
+    sequences.entry(sequence_key(&feature.seqid)).or_default().push(feature.clone());
+  }
+  sequences
+}
+
+/**
+ * Helper method to get the accession of a sequence id without its version, e.g. NC_000913 for both
+ * gi|49175990|ref|NC_000913.2| and NC_000913.3.
+ */
+fn sequence_key(seqid: &str) -> String {
+  let accession = seqid.split('|').rfind(|part| !part.is_empty()).unwrap_or(seqid);
+  match accession.rsplit_once('.') {
+    Some((name, version)) if !version.is_empty() && version.chars().all(|c| c.is_ascii_digit()) => name,
+    _ => accession,
+  }
+  .to_string()
+}
+
+/**
+ * Helper method to get the position of the stop codon of a gene and its strand.
+ */
+fn stop(gene: &CdsFeature) -> (usize, bool) {
+  match gene.forward {
+    true => (gene.end, true),
+    false => (gene.start, false),
+  }
+}
+
+/**
+ * Helper method to get the position of the start codon of a gene.
+ */
+fn start(gene: &CdsFeature) -> usize {
+  match gene.forward {
+    true => gene.start,
+    false => gene.end,
+  }
+}
+
+/**
+ * Helper method to mark the (1-based) positions 1..=length covered by the genes.
+ */
+fn coding_mask(genes: &[CdsFeature], length: usize) -> Vec<bool> {
+  let mut mask = vec![false; length];
+  for gene in genes {
+    mask[gene.start - 1..gene.end].iter_mut().for_each(|coding| *coding = true);
+  }
+  mask
+}
+
+/**
+ * Helper method to add the strand and frame of the predicted gene which overlaps most with every reference gene of a
+ * sequence to the confusion counts.
+ */
+fn confuse_strand_frame(reference: &[CdsFeature], predictions: &[CdsFeature], confusion: &mut StrandFrameConfusion) {
+  let mut predictions: Vec<&CdsFeature> = predictions.iter().collect();
+  predictions.sort_by_key(|gene| gene.start);
+  let longest = predictions.iter().map(|gene| gene.end - gene.start).max().unwrap_or(0);
+
+  for gene in reference {
+    /* only predictions starting at most longest bases before the gene can overlap it */
+    let first = predictions.partition_point(|prediction| prediction.start + longest < gene.start);
+    let best = predictions[first..]
+      .iter()
+      .take_while(|prediction| prediction.start <= gene.end)
+      .map(|prediction| (overlap(gene, prediction), prediction))
+      .filter(|(overlap, _)| *overlap > 0)
+      .max_by_key(|(overlap, _)| *overlap);
+    match best {
+      None => confusion.no_overlap += 1,
+      Some((_, prediction)) if prediction.forward != gene.forward => confusion.opposite_strand += 1,
+      Some((_, prediction)) if stop(prediction).0 % 3 == stop(gene).0 % 3 => confusion.same_frame += 1,
+      Some(_) => confusion.other_frame += 1,
+    }
+  }
+}
+
+/**
+ * Helper method to get the number of bases two genes have in common.
+ */
+fn overlap(a: &CdsFeature, b: &CdsFeature) -> usize {
+  (a.end.min(b.end) + 1).saturating_sub(a.start.max(b.start))
+}
+
+/**
+ * Helper method to divide two counts, 0 when the denominator is 0.
+ */
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+  match denominator {
+    0 => 0.0,
+    _ => numerator as f64 / denominator as f64,
+  }
+}
+
+impl fmt::Display for EvaluationReport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "Reference genes:\t{}", self.reference_genes)?;
+    writeln!(f, "Predicted genes:\t{}", self.predicted_genes)?;
+    writeln!(
+      f,
+      "Gene sensitivity:\t{:.4}\t({} of {} reference genes)",
+      self.genes.sensitivity, self.genes.matched_reference, self.reference_genes
+    )?;
+    writeln!(
+      f,
+      "Gene specificity:\t{:.4}\t({} of {} predicted genes)",
+      self.genes.specificity, self.genes.matched_predicted, self.predicted_genes
+    )?;
+    writeln!(
+      f,
+      "Exact starts:\t{:.4}\t({} of {} matched genes)",
+      self.starts.accuracy, self.starts.exact, self.starts.matched
+    )?;
+    writeln!(
+      f,
+      "Nucleotide sensitivity:\t{:.4}\t({} of {} coding bases)",
+      self.nucleotides.sensitivity, self.nucleotides.overlap, self.nucleotides.reference_coding
+    )?;
+    writeln!(
+      f,
+      "Nucleotide specificity:\t{:.4}\t({} of {} predicted coding bases)",
+      self.nucleotides.specificity, self.nucleotides.overlap, self.nucleotides.predicted_coding
+    )?;
+    writeln!(
+      f,
+      "Best overlapping prediction:\tsame frame {}\tother frame {}\topposite strand {}\tno overlap {}",
+      self.strand_frame.same_frame,
+      self.strand_frame.other_frame,
+      self.strand_frame.opposite_strand,
+      self.strand_frame.no_overlap
+    )
+  }
+}
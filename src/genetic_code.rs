@@ -0,0 +1,290 @@
+use super::dna_helpers::{complement, trinucleotide_pep};
+use super::error::Error;
+
+/*
+ * genetic_code.rs
+ * ===============
+ * This file contains the NCBI translation tables supported by the gene caller. A GeneticCode drives both the
+ * translation of the predicted genes and the recognition of start and stop codons in viterbi::viterbi.
+ */
+
+/**
+ * The translation table used when no genetic code is specified (bacterial, archaeal and plant plastid code).
+ */
+pub const DEFAULT_GENETIC_CODE: u8 = 11;
+
+/**
+ * Start codons modeled by FragGeneScan, only the start codons of a table which are also in this list are used.
+ */
+const MODELED_START_CODONS: &[&str] = &["ATG", "GTG", "TTG"];
+
+/**
+ * The NCBI translation tables as (id, name, amino acids, start codons), the codons are listed in the order used by
+ * NCBI (TTT, TTC, TTA, TTG, TCT, ..., GGG). Tables with context-dependent stop codons (27, 28 and 31) are not
+ * supported, since their stop codons cannot be recognized from the codon alone.
+ */
+const TABLES: &[(u8, &str, &str, &str)] = &[
+  (
+    1,
+    "Standard",
+    "FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    "---M------**--*----M---------------M----------------------------",
+  ),
+  (
+    2,
+    "Vertebrate Mitochondrial",
+    "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSS**VVVVAAAADDEEGGGG",
+    "----------**--------------------MMMM----------**---M------------",
+  ),
+  (
+    3,
+    "Yeast Mitochondrial",
+    "FFLLSSSSYY**CCWWTTTTPPPPHHQQRRRRIIMMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    "----------**----------------------MM---------------M------------",
+  ),
+  (
+    4,
+    "Mold, Protozoan, and Coelenterate Mitochondrial and Mycoplasma/Spiroplasma",
+    "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    "--MM------**-------M------------MMMM---------------M------------",
+  ),
+  (
+    5,
+    "Invertebrate Mitochondrial",
+    "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSSSSVVVVAAAADDEEGGGG",
+    "---M------**--------------------MMMM---------------M------------",
+  ),
+  (
+    6,
+    "Ciliate, Dasycladacean and Hexamita Nuclear",
+    "FFLLSSSSYYQQCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    "--------------*--------------------M----------------------------",
+  ),
+  (
+    9,
+    "Echinoderm and Flatworm Mitochondrial",
+    "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNNKSSSSVVVVAAAADDEEGGGG",
+    "----------**-----------------------M---------------M------------",
+  ),
+  (
+    10,
+    "Euplotid Nuclear",
+    "FFLLSSSSYY**CCCWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    "----------**-----------------------M----------------------------",
+  ),
+  (
+    11,
+    "Bacterial, Archaeal and Plant Plastid",
+    "FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    "---M------**--*----M------------MMMM---------------M------------",
+  ),
+  (
+    12,
+    "Alternative Yeast Nuclear",
+    "FFLLSSSSYY**CC*WLLLSPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    "----------**--*----M---------------M----------------------------",
+  ),
+  (
+    13,
+    "Ascidian Mitochondrial",
+    "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSSGGVVVVAAAADDEEGGGG",
+    "---M------**----------------------MM---------------M------------",
+  ),
+  (
+    14,
+    "Alternative Flatworm Mitochondrial",
+    "FFLLSSSSYYY*CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNNKSSSSVVVVAAAADDEEGGGG",
+    "-----------*-----------------------M----------------------------",
+  ),
+  (
+    16,
+    "Chlorophycean Mitochondrial",
+    "FFLLSSSSYY*LCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    "----------*---*--------------------M----------------------------",
+  ),
+  (
+    21,
+    "Trematode Mitochondrial",
+    "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNNKSSSSVVVVAAAADDEEGGGG",
+    "----------**-----------------------M---------------M------------",
+  ),
+  (
+    22,
+    "Scenedesmus obliquus Mitochondrial",
+    "FFLLSS*SYY*LCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    "------*---*---*--------------------M----------------------------",
+  ),
+  (
+    23,
+    "Thraustochytrium Mitochondrial",
+    "FF*LSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    "--*-------**--*-----------------M--M---------------M------------",
+  ),
+  (
+    24,
+    "Rhabdopleuridae Mitochondrial",
+    "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSSKVVVVAAAADDEEGGGG",
+    "---M------**-------M---------------M---------------M------------",
+  ),
+  (
+    25,
+    "Candidate Division SR1 and Gracilibacteria",
+    "FFLLSSSSYY**CCGWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    "---M------**-----------------------M---------------M------------",
+  ),
+  (
+    26,
+    "Pachysolen tannophilus Nuclear",
+    "FFLLSSSSYY**CC*WLLLAPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    "----------**--*----M---------------M----------------------------",
+  ),
+  (
+    29,
+    "Mesodinium Nuclear",
+    "FFLLSSSSYYYYCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    "--------------*--------------------M----------------------------",
+  ),
+  (
+    30,
+    "Peritrich Nuclear",
+    "FFLLSSSSYYEECC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    "--------------*--------------------M----------------------------",
+  ),
+  (
+    33,
+    "Cephalodiscidae Mitochondrial",
+    "FFLLSSSSYYY*CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSSKVVVVAAAADDEEGGGG",
+    "---M-------*-------M---------------M---------------M------------",
+  ),
+];
+
+/**
+ * Converts the nucleotides of a codon (as returned by dna_helpers::nt2int) to the index of the codon, codons
+ * containing other characters than ACGT get index 64.
+ */
+#[inline]
+pub fn codon_index(a: usize, b: usize, c: usize) -> usize {
+  if a > 3 || b > 3 || c > 3 {
+    64
+  } else {
+    a * 16 + b * 4 + c
+  }
+}
+
+/**
+ * A translation table, the codons are indexed like dna_helpers::trinucleotide (A = 0, C = 1, G = 2, T = 3) and
+ * index 64 is used for codons containing other characters (translated as X). The reverse tables hold the same
+ * information for the reverse complement of every codon, i.e. for codons read on the minus strand.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeneticCode {
+  id: u8,
+  name: &'static str,
+  amino_acids: [char; 65],
+  reverse_amino_acids: [char; 65],
+  starts: [bool; 65],
+  reverse_starts: [bool; 65],
+}
+
+impl GeneticCode {
+  /**
+   * Builds the GeneticCode for the NCBI translation table with the specified id.
+   */
+  pub fn from_id(id: u8) -> Result<GeneticCode, Error> {
+    let (id, name, amino_acids, starts) = TABLES
+      .iter()
+      .find(|table| table.0 == id)
+      .ok_or_else(|| {
+        let supported: Vec<String> = TABLES.iter().map(|table| table.0.to_string()).collect();
+        Error::InvalidArgument(format!(
+          "Unsupported genetic code {}, the supported NCBI translation tables are {}.",
+          id,
+          supported.join(", ")
+        ))
+      })?;
+    let amino_acids: Vec<char> = amino_acids.chars().collect();
+    let starts: Vec<char> = starts.chars().collect();
+
+    let mut code = GeneticCode {
+      id: *id,
+      name,
+      amino_acids: ['X'; 65],
+      reverse_amino_acids: ['X'; 65],
+      starts: [false; 65],
+      reverse_starts: [false; 65],
+    };
+    // NCBI lists the codons in TCAG order
+    let bases = ['T', 'C', 'A', 'G'];
+    for (i, &a) in bases.iter().enumerate() {
+      for (j, &b) in bases.iter().enumerate() {
+        for (k, &c) in bases.iter().enumerate() {
+          let ncbi = i * 16 + j * 4 + k;
+          let index = trinucleotide_pep(&a, &b, &c);
+          let reverse_index = trinucleotide_pep(&complement(c), &complement(b), &complement(a));
+          let codon: String = [a, b, c].iter().collect();
+          let start = starts[ncbi] == 'M' && MODELED_START_CODONS.contains(&codon.as_str());
+          code.amino_acids[index] = amino_acids[ncbi];
+          code.reverse_amino_acids[reverse_index] = amino_acids[ncbi];
+          code.starts[index] = start;
+          code.reverse_starts[reverse_index] = start;
+        }
+      }
+    }
+    Ok(code)
+  }
+
+  pub fn id(&self) -> u8 {
+    self.id
+  }
+
+  pub fn name(&self) -> &'static str {
+    self.name
+  }
+
+  /**
+   * Translates the codon with the specified index (read in forward direction) to its one letter amino acid code,
+   * stop codons are translated as '*'.
+   */
+  #[inline]
+  pub fn translate(&self, codon: usize) -> char {
+    self.amino_acids[codon]
+  }
+
+  /**
+   * Translates the reverse complement of the codon with the specified index, i.e. the codon as read on the minus
+   * strand.
+   */
+  #[inline]
+  pub fn translate_reverse(&self, codon: usize) -> char {
+    self.reverse_amino_acids[codon]
+  }
+
+  #[inline]
+  pub fn is_stop(&self, codon: usize) -> bool {
+    self.amino_acids[codon] == '*'
+  }
+
+  #[inline]
+  pub fn is_reverse_stop(&self, codon: usize) -> bool {
+    self.reverse_amino_acids[codon] == '*'
+  }
+
+  /**
+   * Checks whether the codon is a start codon of this table which is modeled by FragGeneScan (ATG, GTG or TTG).
+   */
+  #[inline]
+  pub fn is_start(&self, codon: usize) -> bool {
+    self.starts[codon]
+  }
+
+  #[inline]
+  pub fn is_reverse_start(&self, codon: usize) -> bool {
+    self.reverse_starts[codon]
+  }
+}
+
+impl Default for GeneticCode {
+  fn default() -> GeneticCode {
+    GeneticCode::from_id(DEFAULT_GENETIC_CODE).unwrap()
+  }
+}
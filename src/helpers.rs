@@ -1,10 +1,12 @@
+use super::error::Error;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use std::env;
 use std::fs::File;
-use std::io::Write;
-use std::path::Path;
+use std::io::{self, Write};
 use std::path::PathBuf;
 
-/**
+/*
  * helpers.rs
  * ==============
  * This file contains general helper methods related to file I/O,
@@ -12,35 +14,78 @@ use std::path::PathBuf;
  */
 
 /**
- * This method will swap the error message for file opening/creation
+ * An output file, which is compressed on the fly when its path ends in .gz (gzip) or .zst (zstd). Compressed files
+ * are only complete after finish has been called.
  */
-pub fn create_file_if_not_exists(path: &str) -> File {
-  match Path::new(path).exists() {
-    true => File::create(path).expect("Error: unable to open output file"),
-    false => File::create(path).expect("Error: unable to create output file"),
+pub enum OutputFile {
+  Plain(File),
+  Gzip(GzEncoder<File>),
+  Zstd(zstd::Encoder<'static, File>),
+}
+
+impl OutputFile {
+  /**
+   * This method will write the remaining compressed data (if any) and flush the file.
+   */
+  pub fn finish(self) -> io::Result<()> {
+    match self {
+      OutputFile::Plain(mut file) => file.flush(),
+      OutputFile::Gzip(encoder) => encoder.finish().map(|_| ()),
+      OutputFile::Zstd(encoder) => encoder.finish().map(|_| ()),
+    }
+  }
+}
+
+impl Write for OutputFile {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    match self {
+      OutputFile::Plain(file) => file.write(buf),
+      OutputFile::Gzip(encoder) => encoder.write(buf),
+      OutputFile::Zstd(encoder) => encoder.write(buf),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match self {
+      OutputFile::Plain(file) => file.flush(),
+      OutputFile::Gzip(encoder) => encoder.flush(),
+      OutputFile::Zstd(encoder) => encoder.flush(),
+    }
+  }
+}
+
+/**
+ * This method will create (or truncate) the output file at the specified path, paths ending in .gz or .zst are
+ * compressed with gzip or zstd respectively.
+ */
+pub fn create_file_if_not_exists(path: &str) -> Result<OutputFile, Error> {
+  let file = File::create(path).map_err(|e| Error::output(path, e))?;
+  if path.ends_with(".gz") {
+    Ok(OutputFile::Gzip(GzEncoder::new(file, Compression::default())))
+  } else if path.ends_with(".zst") {
+    let encoder = zstd::Encoder::new(file, 0).map_err(|e| Error::output(path, e))?;
+    Ok(OutputFile::Zstd(encoder))
+  } else {
+    Ok(OutputFile::Plain(file))
   }
 }
 
 /*
-* Helper method to catch any possible errors in writing to file.
+* Helper method to write a String to the specified output.
 */
-pub fn write_data(output: &mut File, data: String) {
-  match write!(output, "{}", data) {
-    Err(e) => eprintln!("Error: {:?}", e,),
-    _ => (),
-  }
+pub fn write_data<W: Write>(output: &mut W, data: String) -> io::Result<()> {
+  write!(output, "{}", data)
 }
 
 /*
 * Helper method to get executable path (e.g. used to find relative location of the train/ foder)
 */
 pub fn get_executable_path() -> PathBuf {
-  let path = match env::current_exe() {
+  match env::current_exe() {
     Ok(mut path) => {
       path.pop();
       path
     }
     Err(_) => panic!("[Error] Current executable path does not exist"),
-  };
-  path
+  }
 }
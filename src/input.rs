@@ -0,0 +1,482 @@
+use super::error::Error;
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::iter;
+
+/*
+ * input.rs
+ * ========
+ * This file contains the readers for the input sequences of the gene caller (FASTA and FASTQ), records are read one
+ * at a time so malformed input is reported with the id of the offending record. Compressed input (gzip, bzip2 or
+ * zstd) is detected from its magic bytes and decompressed on the fly.
+ */
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/**
+ * This method will wrap the reader in a decoder when the input starts with the magic bytes of gzip, bzip2 or zstd
+ * compressed data, other input is returned as is. The name of the input is only used in error messages.
+ */
+pub fn decompress<R>(mut reader: R, name: &str) -> Result<Box<dyn BufRead + Send>, Error>
+where
+  R: BufRead + Send + 'static,
+{
+  let io_error = |source| Error::Io {
+    path: name.to_string(),
+    source,
+  };
+  let magic = reader.fill_buf().map_err(io_error)?;
+  if magic.starts_with(GZIP_MAGIC) {
+    Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+  } else if magic.starts_with(BZIP2_MAGIC) {
+    Ok(Box::new(BufReader::new(MultiBzDecoder::new(reader))))
+  } else if magic.starts_with(ZSTD_MAGIC) {
+    let decoder = zstd::Decoder::with_buffer(reader).map_err(io_error)?;
+    Ok(Box::new(BufReader::new(decoder)))
+  } else {
+    Ok(Box::new(reader))
+  }
+}
+
+/**
+ * This method will open the (possibly compressed) input file at the specified path.
+ */
+pub fn open_input(path: &str) -> Result<Box<dyn BufRead + Send>, Error> {
+  let file = File::open(path).map_err(|source| Error::Io {
+    path: path.to_string(),
+    source,
+  })?;
+  decompress(BufReader::new(file), path)
+}
+
+/**
+ * This method will read the records of all specified input files one after the other, every file may be FASTA or
+ * FASTQ and may be compressed. Files are only opened once all records of the previous file have been read.
+ */
+pub fn read_input_files(paths: Vec<String>) -> impl Iterator<Item = Result<Record, Error>> + Send {
+  paths.into_iter().flat_map(
+    |path| -> Box<dyn Iterator<Item = Result<Record, Error>> + Send> {
+      match open_input(&path).and_then(|reader| SequenceReader::new(reader, &path)) {
+        Ok(reader) => Box::new(reader),
+        Err(e) => Box::new(iter::once(Err(e))),
+      }
+    },
+  )
+}
+
+/**
+ * This method will read the records of all specified input files like read_input_files, but every record gets the
+ * CG content of all records of its file, so all records of a file use the same CG bin. Every file is read twice,
+ * the first pass only computes the CG content, so the records are still streamed.
+ */
+pub fn read_input_files_with_cg_content(paths: Vec<String>) -> impl Iterator<Item = Result<Record, Error>> + Send {
+  paths.into_iter().flat_map(
+    |path| -> Box<dyn Iterator<Item = Result<Record, Error>> + Send> {
+      let records = file_cg_content(&path).and_then(|cg_content| {
+        let reader = SequenceReader::new(open_input(&path)?, &path)?;
+        Ok(reader.map(move |record| {
+          record.map(|mut record| {
+            if cg_content.is_some() {
+              record.cg_content = cg_content;
+            }
+            record
+          })
+        }))
+      });
+      match records {
+        Ok(records) => Box::new(records),
+        Err(e) => Box::new(iter::once(Err(e))),
+      }
+    },
+  )
+}
+
+/**
+ * This method will read all records of a single input (e.g. the contigs of a metagenome-assembled genome) into
+ * memory and set the CG content of every record to the CG content of all records together. Unlike
+ * read_input_files_with_cg_content, this works for input which can only be read once (e.g. standard input), at the
+ * cost of keeping all records in memory.
+ */
+pub fn with_file_cg_content<I>(records: I) -> Result<Vec<Record>, Error>
+where
+  I: Iterator<Item = Result<Record, Error>>,
+{
+  let mut records = records.collect::<Result<Vec<Record>, Error>>()?;
+  let cg: usize = records.iter().map(|record| cg_count(&record.seq)).sum();
+  let length: usize = records.iter().map(|record| record.seq.len()).sum();
+  if let Some(cg_content) = cg_percentage(cg, length) {
+    for record in &mut records {
+      record.cg_content = Some(cg_content);
+    }
+  }
+  Ok(records)
+}
+
+/**
+ * Helper method to compute the CG content of all records of an input file in a pass over the file, without keeping
+ * the records in memory. None when the file has no bases.
+ */
+fn file_cg_content(path: &str) -> Result<Option<f64>, Error> {
+  let (mut cg, mut length) = (0, 0);
+  for record in SequenceReader::new(open_input(path)?, path)? {
+    let record = record?;
+    cg += cg_count(&record.seq);
+    length += record.seq.len();
+  }
+  Ok(cg_percentage(cg, length))
+}
+
+/**
+ * Helper method to count the C and G bases of a sequence.
+ */
+fn cg_count(seq: &str) -> usize {
+  seq.bytes().filter(|c| b"cCgG".contains(c)).count()
+}
+
+/**
+ * Helper method to get the CG content (a percentage) of cg C and G bases out of length bases, None without bases.
+ */
+fn cg_percentage(cg: usize, length: usize) -> Option<f64> {
+  match length > 0 {
+    true => Some(cg as f64 / length as f64 * 100.0),
+    false => None,
+  }
+}
+
+/**
+ * A single input sequence, the quality holds the Phred quality score of every base for FASTQ input. A sequence is
+ * circular when its header contains topology=circular (see is_circular), the CG content (a percentage) is read from
+ * gc=PERCENTAGE in its header (see header_cg_content) or set for a whole file (see with_file_cg_content).
+ */
+#[derive(Clone, Debug)]
+pub struct Record {
+  pub id: String,
+  pub seq: String,
+  pub quality: Option<Vec<u8>>,
+  pub circular: bool,
+  pub cg_content: Option<f64>,
+}
+
+/**
+ * Checks whether the description of a header line marks the sequence as circular, with topology=circular or the
+ * [topology=circular] modifier of NCBI submissions.
+ */
+pub fn is_circular(header: &str) -> bool {
+  header
+    .split_whitespace()
+    .skip(1)
+    .any(|word| word.trim_matches(|c| c == '[' || c == ']').eq_ignore_ascii_case("topology=circular"))
+}
+
+/**
+ * Reads the CG content (a percentage) from the description of a header line, given as gc=PERCENTAGE or the
+ * [gc=PERCENTAGE] modifier (e.g. gc=52.3 or [GC=52.3%]). None is returned when the description has no valid CG content.
+ */
+pub fn header_cg_content(header: &str) -> Option<f64> {
+  header.split_whitespace().skip(1).find_map(|word| {
+    let (key, value) = word.trim_matches(['[', ']']).split_once('=')?;
+    if !key.eq_ignore_ascii_case("gc") {
+      return None;
+    }
+    value
+      .trim_end_matches('%')
+      .parse::<f64>()
+      .ok()
+      .filter(|cg_content| (0.0..=100.0).contains(cg_content))
+  })
+}
+
+/**
+ * Reader for FASTA or FASTQ input, the format is detected from the first character of the input ('@' for FASTQ).
+ */
+pub enum SequenceReader<R> {
+  Fasta(FastaReader<R>),
+  Fastq(FastqReader<R>),
+}
+
+impl<R: BufRead> SequenceReader<R> {
+  /**
+   * Builds a new SequenceReader, the name of the input (e.g. a file path) is only used in error messages.
+   */
+  pub fn new(mut reader: R, name: &str) -> Result<SequenceReader<R>, Error> {
+    let fastq = loop {
+      let buffer = reader.fill_buf().map_err(|source| Error::Io {
+        path: name.to_string(),
+        source,
+      })?;
+      if buffer.is_empty() {
+        break false;
+      }
+      match buffer.iter().position(|c| !c.is_ascii_whitespace()) {
+        Some(i) => break buffer[i] == b'@',
+        None => {
+          let length = buffer.len();
+          reader.consume(length);
+        }
+      }
+    };
+    Ok(if fastq {
+      SequenceReader::Fastq(FastqReader::new(reader, name))
+    } else {
+      SequenceReader::Fasta(FastaReader::new(reader, name))
+    })
+  }
+}
+
+impl<R: BufRead> Iterator for SequenceReader<R> {
+  type Item = Result<Record, Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    match self {
+      SequenceReader::Fasta(reader) => reader.next_record(),
+      SequenceReader::Fastq(reader) => reader.next(),
+    }
+  }
+}
+
+/**
+ * Iterator over the records of a FASTA file, every record is yielded as an (id, sequence) tuple where the id is
+ * the part of the header line up to the first whitespace character.
+ */
+pub struct FastaReader<R> {
+  reader: R,
+  name: String,
+  line: Vec<u8>,
+  line_number: usize,
+  done: bool,
+}
+
+impl<R: BufRead> FastaReader<R> {
+  /**
+   * Builds a new FastaReader, the name of the input (e.g. a file path) is only used in error messages.
+   */
+  pub fn new(reader: R, name: &str) -> FastaReader<R> {
+    FastaReader {
+      reader,
+      name: name.to_string(),
+      line: Vec::new(),
+      line_number: 0,
+      done: false,
+    }
+  }
+
+  /**
+   * Helper method to read the next line into self.line, false is returned at the end of the input.
+   */
+  fn read_line(&mut self) -> Result<bool, Error> {
+    self.line.clear();
+    let read = self
+      .reader
+      .read_until(b'\n', &mut self.line)
+      .map_err(|source| Error::Io {
+        path: self.name.clone(),
+        source,
+      })?;
+    self.line_number += 1;
+    Ok(read > 0)
+  }
+
+  fn read_record(&mut self) -> Result<Option<Record>, Error> {
+    // self.line holds the header of the next record, unless this is the first record
+    if self.line.is_empty() {
+      loop {
+        if !self.read_line()? {
+          return Ok(None);
+        }
+        if !self.line.iter().all(u8::is_ascii_whitespace) {
+          break;
+        }
+      }
+    }
+    if self.line[0] != b'>' {
+      return Err(Error::InputFormat {
+        record: format!("at line {} of {}", self.line_number, self.name),
+        message: String::from("expected '>' at the start of a record"),
+      });
+    }
+    let header = String::from_utf8_lossy(&self.line[1..]);
+    let id = header.split_whitespace().next().unwrap_or("").to_string();
+    let circular = is_circular(&header);
+    let cg_content = header_cg_content(&header);
+
+    let mut seq = Vec::new();
+    loop {
+      if !self.read_line()? {
+        self.line.clear();
+        self.done = true;
+        break;
+      }
+      if self.line.first() == Some(&b'>') {
+        break;
+      }
+      let end = self
+        .line
+        .iter()
+        .rposition(|c| !c.is_ascii_whitespace())
+        .map_or(0, |i| i + 1);
+      seq.extend_from_slice(&self.line[..end]);
+    }
+    match String::from_utf8(seq) {
+      Ok(seq) => Ok(Some(Record {
+        id,
+        seq,
+        quality: None,
+        circular,
+        cg_content,
+      })),
+      Err(_) => Err(Error::InputFormat {
+        record: id,
+        message: String::from("the sequence is not valid UTF-8"),
+      }),
+    }
+  }
+
+  /**
+   * Helper method to read the next record including its topology, used by SequenceReader.
+   */
+  fn next_record(&mut self) -> Option<Result<Record, Error>> {
+    if self.done {
+      return None;
+    }
+    match self.read_record() {
+      Ok(Some(record)) => Some(Ok(record)),
+      Ok(None) => {
+        self.done = true;
+        None
+      }
+      Err(e) => {
+        self.done = true;
+        Some(Err(e))
+      }
+    }
+  }
+}
+
+impl<R: BufRead> Iterator for FastaReader<R> {
+  type Item = Result<(String, String), Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.next_record().map(|record| record.map(|record| (record.id, record.seq)))
+  }
+}
+
+/**
+ * Iterator over the records of a FASTQ file with four lines per record (header, sequence, '+' separator and
+ * qualities). The qualities are converted to Phred scores assuming the Sanger (Phred+33) encoding.
+ */
+pub struct FastqReader<R> {
+  reader: R,
+  name: String,
+  line_number: usize,
+  done: bool,
+}
+
+impl<R: BufRead> FastqReader<R> {
+  /**
+   * Builds a new FastqReader, the name of the input (e.g. a file path) is only used in error messages.
+   */
+  pub fn new(reader: R, name: &str) -> FastqReader<R> {
+    FastqReader {
+      reader,
+      name: name.to_string(),
+      line_number: 0,
+      done: false,
+    }
+  }
+
+  /**
+   * Helper method to read the next line without the trailing whitespace, None is returned at the end of the input.
+   */
+  fn read_line(&mut self) -> Result<Option<Vec<u8>>, Error> {
+    let mut line = Vec::new();
+    let read = self
+      .reader
+      .read_until(b'\n', &mut line)
+      .map_err(|source| Error::Io {
+        path: self.name.clone(),
+        source,
+      })?;
+    if read == 0 {
+      return Ok(None);
+    }
+    self.line_number += 1;
+    let end = line
+      .iter()
+      .rposition(|c| !c.is_ascii_whitespace())
+      .map_or(0, |i| i + 1);
+    line.truncate(end);
+    Ok(Some(line))
+  }
+
+  fn read_record(&mut self) -> Result<Option<Record>, Error> {
+    let header = loop {
+      match self.read_line()? {
+        None => return Ok(None),
+        Some(line) if line.is_empty() => continue,
+        Some(line) => break line,
+      }
+    };
+    if header[0] != b'@' {
+      return Err(Error::InputFormat {
+        record: format!("at line {} of {}", self.line_number, self.name),
+        message: String::from("expected '@' at the start of a record"),
+      });
+    }
+    let header = String::from_utf8_lossy(&header[1..]).into_owned();
+    let id = header.split_whitespace().next().unwrap_or("").to_string();
+    let invalid = |message: &str| Error::InputFormat {
+      record: id.clone(),
+      message: message.to_string(),
+    };
+
+    let seq = self.read_line()?.ok_or_else(|| invalid("missing sequence line"))?;
+    let separator = self.read_line()?.ok_or_else(|| invalid("missing '+' line"))?;
+    if separator.first() != Some(&b'+') {
+      return Err(invalid("expected '+' after the sequence line"));
+    }
+    let quality = self.read_line()?.ok_or_else(|| invalid("missing quality line"))?;
+    if quality.len() != seq.len() {
+      return Err(invalid(&format!(
+        "the sequence has {} bases but {} quality scores",
+        seq.len(),
+        quality.len()
+      )));
+    }
+    if quality.iter().any(|q| !(b'!'..=b'~').contains(q)) {
+      return Err(invalid("the quality line contains characters outside of '!'..'~'"));
+    }
+    let seq = String::from_utf8(seq).map_err(|_| invalid("the sequence is not valid UTF-8"))?;
+    Ok(Some(Record {
+      id,
+      seq,
+      quality: Some(quality.iter().map(|q| q - b'!').collect()),
+      circular: is_circular(&header),
+      cg_content: header_cg_content(&header),
+    }))
+  }
+}
+
+impl<R: BufRead> Iterator for FastqReader<R> {
+  type Item = Result<Record, Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.done {
+      return None;
+    }
+    match self.read_record() {
+      Ok(Some(record)) => Some(Ok(record)),
+      Ok(None) => {
+        self.done = true;
+        None
+      }
+      Err(e) => {
+        self.done = true;
+        Some(Err(e))
+      }
+    }
+  }
+}
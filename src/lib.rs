@@ -0,0 +1,37 @@
+/*
+ * lib.rs
+ * ======
+ * This file exposes the FragGeneScan.rs gene caller as a library, so it can be embedded in other pipelines
+ * without going through the fgsrs binary. The GenePredictor struct is the main entry point, the lower level
+ * modules are public for callers who need finer control over the HMM.
+ */
+
+pub mod annotation;
+pub mod compiled_model;
+pub mod config;
+pub mod constants;
+pub mod dna_helpers;
+#[cfg(feature = "embedded-models")]
+pub mod embedded;
+pub mod error;
+pub mod evaluation;
+pub mod genetic_code;
+pub mod helpers;
+pub mod input;
+pub mod model_selection;
+pub mod output;
+pub mod pipeline;
+pub mod posterior;
+pub mod predictor;
+pub mod simulation;
+pub mod train;
+pub mod training;
+pub mod viterbi;
+pub mod windows;
+
+pub use config::{CgBin, PosteriorMode, PredictionConfig};
+pub use error::{Error, Result};
+pub use genetic_code::GeneticCode;
+pub use predictor::GenePredictor;
+pub use train::{Train, HMM};
+pub use viterbi::{Out, Prediction};
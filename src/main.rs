@@ -1,37 +1,53 @@
-mod constants;
-mod dna_helpers;
-mod helpers;
-mod output;
-mod train;
-mod viterbi;
-
-use bio::io::fasta;
-use clap::{App, Arg};
-use dna_helpers::get_prob_from_cg;
-use helpers::create_file_if_not_exists;
-use output::print_prediction;
+use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches, SubCommand};
+use fgsrs::annotation::read_annotation;
+use fgsrs::compiled_model::write_compiled_model;
+use fgsrs::evaluation::{evaluate, prediction_features, read_predictions};
+use fgsrs::helpers::{create_file_if_not_exists, write_data};
+use fgsrs::input::{
+    decompress, open_input, read_input_files, read_input_files_with_cg_content, with_file_cg_content, FastaReader, Record,
+    SequenceReader,
+};
+use fgsrs::model_selection::{select_model, ReadStatistics, SAMPLE_SIZE};
+use fgsrs::output::OutputFiles;
+use fgsrs::pipeline::run_pipeline;
+use fgsrs::simulation::{write_simulated_read, ErrorProfile, Platform, ReadSimulator, SimulationConfig};
+use fgsrs::training::train_from_annotation;
+use fgsrs::train::load_models;
+use fgsrs::genetic_code::DEFAULT_GENETIC_CODE;
+use fgsrs::config::DEFAULT_WINDOW_OVERLAP;
+use fgsrs::{CgBin, Error, GenePredictor, GeneticCode, PosteriorMode, PredictionConfig};
 use rayon::prelude::*;
-use std::fs::File;
-use std::io::{self};
+use std::io::{self, BufReader, BufWriter};
 use std::path::Path;
-use train::{Train, HMM};
-use viterbi::{viterbi};
-use std::sync::{Arc, Mutex};
+use std::process;
+use std::sync::Mutex;
 
 
-/**
+/*
  * main.rs
  * =======
- * This file contains the main method used for calling the viterbi function from the command line
- * and processing the output.
+ * This file contains the main method used for calling the gene predictor from the command line
+ * and processing the output, the actual gene calling is implemented in the fgsrs library (see lib.rs).
  */
 
 fn main() {
+    if let Err(e) = run() {
+        eprintln!("ERROR: {}", e);
+        process::exit(e.exit_code());
+    }
+}
+
+/**
+ * This method parses the command line arguments and runs the requested command, any error is returned to main
+ * which maps it to an exit code.
+ */
+fn run() -> Result<(), Error> {
     // We use clap to process command line arguments
     let matches = App::new("fgsrs")
         .version("1.0")
         .author("Laurens Debackere <Laurens.Debackere@UGent.be>")
         .about("A reimplementation of the original FragGeneScan project (see https://omics.informatics.indiana.edu/FragGeneScan/) in Rust with an improved command-line interface, better performance and code quality.")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("whole-genome")
                 .short("w")
@@ -54,10 +70,122 @@ fn main() {
                 .short("t")
                 .long("train")
                 .value_name("TRAIN_PATH")
-                .help("(REQUIRED) Path to a directory containing the train model files to be used.")
+                .help("(REQUIRED) Path to the train model file to be used, or the name of a model in the model directory (e.g. complete, sanger_5, 454_10 or illumina_5). With auto, the model is selected from the first 1000 sequences of the input.")
                 .takes_value(true)
                 .required(true)
         )
+        .arg(
+            Arg::with_name("platform")
+                .long("platform")
+                .value_name("PLATFORM")
+                .help("(OPTIONAL) With -t auto, the sequencing platform of the reads (complete, sanger, 454 or illumina) instead of guessing it from the reads.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("model-dir")
+                .short("m")
+                .long("model-dir")
+                .value_name("MODEL_DIR")
+                .help("(OPTIONAL) Directory containing the model files (gene, rgene, noncoding, start, stop, start1, stop1, pwm and the train models). When omitted, the directory is taken from the FGSRS_MODEL_DIR environment variable or searched for in the train/ folder next to the executable, $XDG_DATA_HOME/fgsrs/train and /usr/share/fgsrs/train, builds with the embedded-models feature fall back to the models compiled into the binary.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("genetic-code")
+                .long("genetic-code")
+                .value_name("TABLE")
+                .help("(OPTIONAL) NCBI translation table used for recognizing start and stop codons and translating the predicted genes, e.g. 4 for Mycoplasma/Spiroplasma or 25 for SR1 and Gracilibacteria (default: 11).")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("start-codons")
+                .long("start-codons")
+                .value_name("CODONS")
+                .help("(OPTIONAL) Comma-separated list of start codons, e.g. ATG,GTG,TTG,CTG (default: the start codons ATG, GTG and TTG of the genetic code). Start codons other than ATG and GTG are scored like TTG and translated as M.")
+                .takes_value(true)
+                .use_delimiter(true)
+        )
+        .arg(
+            Arg::with_name("refine-window")
+                .long("refine-window")
+                .value_name("BP")
+                .help("(OPTIONAL) Refine the start codons with -w 1, by moving every start codon at most BP base pairs upstream to the start codon that best matches the ribosome binding site profile, or up to the first in-frame stop codon with stop. FragGeneScan 1.31 does not refine the start codons (the default).")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("min-gene-length")
+                .long("min-gene-length")
+                .value_name("BP")
+                .help("(OPTIONAL) Only report genes longer than BP base pairs (default: 60, or 120 with -w 1). Genes not longer than the default are marked as short in the metadata output.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("min-read-length")
+                .long("min-read-length")
+                .value_name("BP")
+                .help("(OPTIONAL) Only process input sequences longer than BP base pairs (default: 70). Genes on sequences not longer than 70 bp are marked as short in the metadata output.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("posteriors")
+                .long("posteriors")
+                .help("(OPTIONAL) Compute the posterior probability of every predicted gene being coding and of its start and stop codon with the forward-backward algorithm, these are written to the metadata and GFF3 output. This roughly triples the running time.")
+        )
+        .arg(
+            Arg::with_name("base-posteriors")
+                .long("base-posteriors")
+                .value_name("POSTERIOR_OUTPUT_FILE")
+                .help("(OPTIONAL) Specifies a file path where the posterior probability of every base being coding on the forward and reverse strand is written to, implies --posteriors.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("circular")
+                .long("circular")
+                .help("(OPTIONAL) Treat all input sequences as circular (e.g. complete chromosomes and plasmids), so genes spanning the origin are predicted across the junction. Sequences with topology=circular in their FASTA header are always treated as circular. Genes spanning the origin end past the end of the sequence, like the circular convention of GFF3.")
+        )
+        .arg(
+            Arg::with_name("partial")
+                .long("partial")
+                .help("(OPTIONAL) Flag the genes running off the sequence with a partial=XY column in the metadata output and a partial=XY attribute in the GFF3 output (see README), FragGeneScan 1.31 has neither.")
+        )
+        .arg(
+            Arg::with_name("cg-bin")
+                .long("cg-bin")
+                .value_name("CG_BIN")
+                .help("(OPTIONAL) How the CG bin of the model is chosen for every sequence: sequence (default) uses the CG content of the sequence, file the CG content of all sequences of its input file (e.g. the contigs of a metagenome-assembled genome; the files given with -i are read twice, standard input is read into memory), header the CG content given as gc=PERCENTAGE in the FASTA header (falling back to the sequence) and a number between 0 and 43 uses that bin for every sequence. The bin of a sequence is its CG percentage minus 26, with this option it is reported in the header lines of the metadata output.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("window-length")
+                .long("window-length")
+                .value_name("BP")
+                .help("(OPTIONAL) Split sequences longer than BP base pairs into overlapping windows, which are predicted in parallel and stitched together. This spreads a single genome over multiple threads (see -p), genes longer than half of the window overlap may be predicted differently than in a single pass.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("window-overlap")
+                .long("window-overlap")
+                .value_name("BP")
+                .help("(OPTIONAL) Overlap (in bp) between consecutive windows with --window-length (default: 20000).")
+                .takes_value(true)
+                .requires("window-length")
+        )
+        .arg(
+            Arg::with_name("input")
+                .short("i")
+                .long("input")
+                .value_name("INPUT_FILE")
+                .help("(OPTIONAL) One or more FASTA or FASTQ files (optionally compressed with gzip, bzip2 or zstd) containing the input sequences, which are read from standard input when omitted.")
+                .takes_value(true)
+                .multiple(true)
+        )
+        .arg(
+            Arg::with_name("protein")
+                .short("a")
+                .long("protein")
+                .value_name("PROTEIN_OUTPUT_FILE")
+                .help("(OPTIONAL) Specifies a file path where the protein-FASTA-file is written to, instead of standard output.")
+                .takes_value(true)
+        )
         .arg(
             Arg::with_name("output")
                 .short("d")
@@ -74,20 +202,258 @@ fn main() {
                 .help("(OPTIONAL) Specifies a file path where the Metadata-file is written to.")
                 .takes_value(true)
         )
+        .arg(
+            Arg::with_name("gff")
+                .short("g")
+                .long("gff")
+                .value_name("GFF_OUTPUT_FILE")
+                .help("(OPTIONAL) Specifies a file path where the GFF3-file is written to.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("prefix")
+                .short("o")
+                .long("out-prefix")
+                .value_name("OUTPUT_PREFIX")
+                .help("(OPTIONAL) Writes the protein, DNA, metadata and GFF3 output to OUTPUT_PREFIX.faa, OUTPUT_PREFIX.ffn, OUTPUT_PREFIX.out and OUTPUT_PREFIX.gff, like the -o option of FragGeneScan.")
+                .takes_value(true)
+                .conflicts_with_all(&["protein", "output", "metadata", "gff"])
+        )
+        .arg(
+            Arg::with_name("use-quality")
+                .short("q")
+                .long("use-quality")
+                .help("(OPTIONAL) For FASTQ input, weight the match emissions and indel transitions by the quality score of every base so sequencing errors are expected at low quality positions.")
+        )
+        .arg(
+            Arg::with_name("unordered")
+                .long("unordered")
+                .help("(OPTIONAL) Write every prediction as soon as it is available instead of in the order of the input sequences, the order of the output then depends on the number of threads.")
+        )
+        .subcommand(
+            SubCommand::with_name("train")
+                .about("Estimates the global training files (gene, rgene, noncoding, start, stop, start1, stop1 and pwm) from an annotated genome.")
+                .arg(
+                    Arg::with_name("genome")
+                        .short("g")
+                        .long("genome")
+                        .value_name("GENOME_FASTA")
+                        .help("(REQUIRED) Path to the FASTA file containing the genome sequence(s).")
+                        .takes_value(true)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("annotation")
+                        .short("a")
+                        .long("annotation")
+                        .value_name("ANNOTATION_FILE")
+                        .help("(REQUIRED) Path to the GFF3 or GenBank file containing the CDS annotation of the genome.")
+                        .takes_value(true)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("output-dir")
+                        .short("o")
+                        .long("output-dir")
+                        .value_name("OUTPUT_DIR")
+                        .help("(REQUIRED) Directory where the training files are written to, it is created if it does not exist.")
+                        .takes_value(true)
+                        .required(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("model")
+                .about("Utilities for working with model files.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("compile")
+                        .about("Compiles the global model files and a train model into a single binary model file, which loads much faster and can be passed to -t.")
+                        .arg(
+                            Arg::with_name("train")
+                                .short("t")
+                                .long("train")
+                                .value_name("TRAIN_PATH")
+                                .help("(REQUIRED) Path to the train model file to be compiled, or the name of a model in the model directory.")
+                                .takes_value(true)
+                                .required(true)
+                        )
+                        .arg(
+                            Arg::with_name("model-dir")
+                                .short("m")
+                                .long("model-dir")
+                                .value_name("MODEL_DIR")
+                                .help("(OPTIONAL) Directory containing the model files, see the -m option of fgsrs.")
+                                .takes_value(true)
+                        )
+                        .arg(
+                            Arg::with_name("output")
+                                .short("o")
+                                .long("output")
+                                .value_name("OUTPUT_FILE")
+                                .help("(REQUIRED) Path where the compiled model is written to.")
+                                .takes_value(true)
+                                .required(true)
+                        )
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("simulate")
+                .about("Simulates sequence reads with the sequencing errors of a platform from an annotated genome, the coding regions of every read are written as GFF3 for scoring the predictions with fgsrs eval.")
+                .arg(
+                    Arg::with_name("genome")
+                        .short("g")
+                        .long("genome")
+                        .value_name("GENOME_FASTA")
+                        .help("(REQUIRED) Path to the FASTA file containing the genome sequence(s).")
+                        .takes_value(true)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("annotation")
+                        .short("a")
+                        .long("annotation")
+                        .value_name("ANNOTATION_FILE")
+                        .help("(REQUIRED) Path to the GFF3, GenBank or PTT file containing the CDS annotation of the genome.")
+                        .takes_value(true)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("OUTPUT_PREFIX")
+                        .help("(REQUIRED) Prefix of the output files, the reads are written to OUTPUT_PREFIX.fna and their coding regions to OUTPUT_PREFIX.gff.")
+                        .takes_value(true)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("error-model")
+                        .short("e")
+                        .long("error-model")
+                        .value_name("ERROR_MODEL")
+                        .help("(REQUIRED) Sequencing errors of the reads, complete for error-free reads or sanger, 454 or illumina followed by the error rate in tenths of a percent, like the FragGeneScan models (e.g. sanger_5, 454_10 or illumina_1 for 0.5%, 1% and 0.1% errors).")
+                        .takes_value(true)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("reads")
+                        .short("n")
+                        .long("reads")
+                        .value_name("NUM_READS")
+                        .help("(OPTIONAL) Number of simulated reads (default: 10000).")
+                        .takes_value(true)
+                        .default_value("10000")
+                )
+                .arg(
+                    Arg::with_name("length")
+                        .short("l")
+                        .long("length")
+                        .value_name("BP")
+                        .help("(OPTIONAL) Mean length of the reads before the sequencing errors (default: 100).")
+                        .takes_value(true)
+                        .default_value("100")
+                )
+                .arg(
+                    Arg::with_name("length-sd")
+                        .long("length-sd")
+                        .value_name("BP")
+                        .help("(OPTIONAL) Standard deviation of the normally distributed read lengths (default: 0, all reads have the same length).")
+                        .takes_value(true)
+                        .default_value("0")
+                )
+                .arg(
+                    Arg::with_name("seed")
+                        .long("seed")
+                        .value_name("SEED")
+                        .help("(OPTIONAL) Seed of the random number generator, the same seed gives the same reads (default: 1).")
+                        .takes_value(true)
+                        .default_value("1")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("eval")
+                .about("Evaluates predicted genes against a reference annotation, reporting the gene level sensitivity and specificity, the exact start accuracy, the nucleotide level accuracy and the strand and frame of the best overlapping predictions.")
+                .arg(
+                    Arg::with_name("reference")
+                        .short("r")
+                        .long("reference")
+                        .value_name("ANNOTATION_FILE")
+                        .help("(REQUIRED) Path to the reference annotation in GFF3, GenBank or PTT format.")
+                        .takes_value(true)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("predictions")
+                        .short("i")
+                        .long("predictions")
+                        .value_name("PREDICTION_FILE")
+                        .help("(OPTIONAL) Path to the predictions, the metadata (-e) or GFF3 (-g) output of fgsrs or FragGeneScan.")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("genome")
+                        .short("g")
+                        .long("genome")
+                        .value_name("GENOME_FASTA")
+                        .help("(OPTIONAL) Path to the FASTA file containing the genome sequence(s), which are predicted with the model of -t instead of reading the predictions from a file.")
+                        .takes_value(true)
+                        .requires("train")
+                )
+                .group(
+                    ArgGroup::with_name("input")
+                        .args(&["predictions", "genome"])
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("train")
+                        .short("t")
+                        .long("train")
+                        .value_name("TRAIN_PATH")
+                        .help("(OPTIONAL) Path to the train model file used with --genome, or the name of a model in the model directory.")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("model-dir")
+                        .short("m")
+                        .long("model-dir")
+                        .value_name("MODEL_DIR")
+                        .help("(OPTIONAL) Directory containing the model files, see the -m option of fgsrs.")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("whole-genome")
+                        .short("w")
+                        .long("whole-genome")
+                        .value_name("WHOLE_GENOME")
+                        .help("(OPTIONAL) Whether the sequences of --genome are sequence reads (WHOLE_GENOME = 0) or full genome sequences (WHOLE_GENOME = 1, default).")
+                        .takes_value(true)
+                        .default_value("1")
+                        .possible_values(&["0", "1"])
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .value_name("JSON_FILE")
+                        .help("(OPTIONAL) Specifies a file path where the evaluation is written to as JSON, e.g. for tracking regressions.")
+                        .takes_value(true)
+                )
+        )
         .get_matches();
 
-    /*
-    Convert the specified training path into a Train struct
-    */
-    let train_path = matches.value_of("train").unwrap();
-    let train;
-    let hmm;
-    if Path::new(train_path).exists() {
-        train = Train::from_file();
-        hmm = HMM::from_file(train_path);
-    } else {
-        println!("ERROR: Something went wrong while accessing the specified training directory.");
-        return;
+    if let Some(train_matches) = matches.subcommand_matches("train") {
+        return run_training(train_matches);
+    }
+    if let Some(simulate_matches) = matches.subcommand_matches("simulate") {
+        return run_simulation(simulate_matches);
+    }
+    if let Some(eval_matches) = matches.subcommand_matches("eval") {
+        return run_eval(eval_matches);
+    }
+    if let Some(model_matches) = matches.subcommand_matches("model") {
+        if let Some(compile_matches) = model_matches.subcommand_matches("compile") {
+            return run_model_compile(compile_matches);
+        }
+        return Ok(());
     }
 
     /*
@@ -98,23 +464,64 @@ fn main() {
         "1" => true,
         "0" => false,
         _ => {
-            println!("ERROR: Invalid value specified for -w.");
-            return;
+            return Err(Error::InvalidArgument(String::from(
+                "Invalid value specified for -w.",
+            )))
         }
     };
 
+    /*
+    Open the -i files (or STDIN), with -t auto the train model is selected from a sample of the first sequences,
+    which are put back in front of the input afterwards
+    */
+    let cg_bin = match matches.value_of("cg-bin").unwrap_or("sequence") {
+        "sequence" => CgBin::Sequence,
+        "file" | "header" => CgBin::Record,
+        bin => CgBin::Fixed(bin.parse().map_err(|_| {
+            Error::InvalidArgument(String::from(
+                "The parameter --cg-bin should be sequence, file, header or a numeric value.",
+            ))
+        })?),
+    };
+    let file_cg_content = matches.value_of("cg-bin") == Some("file");
+    let mut input: Box<dyn Iterator<Item = Result<Record, Error>> + Send> = match matches.values_of("input") {
+        Some(paths) if file_cg_content => Box::new(read_input_files_with_cg_content(paths.map(String::from).collect())),
+        Some(paths) => Box::new(read_input_files(paths.map(String::from).collect())),
+        None => {
+            let stdin = decompress(BufReader::new(io::stdin()), "standard input")?;
+            let reader = SequenceReader::new(stdin, "standard input")?;
+            match file_cg_content {
+                true => Box::new(with_file_cg_content(reader)?.into_iter().map(Ok)),
+                false => Box::new(reader),
+            }
+        }
+    };
+    let mut train_path = matches.value_of("train").unwrap().to_string();
+    if train_path == "auto" {
+        let hint = matches.value_of("platform").map(Platform::from_name).transpose()?;
+        let sample = input.by_ref().take(SAMPLE_SIZE).collect::<Result<Vec<Record>, Error>>()?;
+        let choice = select_model(&ReadStatistics::from_records(&sample), wholegenome, hint);
+        eprintln!("Selected the {} model: {}.", choice.model, choice.reason);
+        train_path = choice.model;
+        input = Box::new(sample.into_iter().map(Ok).chain(input));
+    } else if matches.is_present("platform") {
+        return Err(Error::InvalidArgument(String::from("The parameter --platform requires -t auto.")));
+    }
+
+    /*
+    Convert the specified training path into a Train struct
+    */
+    let (train, hmm) = load_models(&train_path, matches.value_of("model-dir"))?;
+
     /*
     Process the -p parameter
     */
     if matches.is_present("threads") {
-        let threadnum: usize = matches
-            .value_of("threads")
-            .unwrap()
-            .parse()
-            .expect("ERROR: The parameter -p should have a numeric value.");
+        let threadnum: usize = matches.value_of("threads").unwrap().parse().map_err(|_| {
+            Error::InvalidArgument(String::from("The parameter -p should have a numeric value."))
+        })?;
         if threadnum < 1 {
-            println!("ERROR: Invalid number of threads");
-            return;
+            return Err(Error::InvalidArgument(String::from("Invalid number of threads")));
         }
         // Configure threadpool based on specifed number of threads
         rayon::ThreadPoolBuilder::new()
@@ -124,51 +531,275 @@ fn main() {
     }
 
     /*
-     * Next we should read the fasta sequences from STDIN, we will only process those sequences longer than 70 bp's
-     */
-    let records: Vec<(String, String)> = fasta::Reader::new(io::stdin())
-        .records()
-        .map(|result| {
-            let record = result.unwrap();
-            // obtain sequence
-            let seq = std::str::from_utf8(record.seq()).unwrap().to_string();
-            let id = String::from(record.id());
-            (id, seq)
-        })
-        .filter(|(_, seq)| seq.len() > 70)
-        .collect();
-
-    
+    Process the --genetic-code parameter
+    */
+    let genetic_code = match matches.value_of("genetic-code") {
+        Some(id) => GeneticCode::from_id(id.parse().map_err(|_| {
+            Error::InvalidArgument(String::from(
+                "The parameter --genetic-code should have a numeric value.",
+            ))
+        })?)?,
+        None => GeneticCode::from_id(DEFAULT_GENETIC_CODE)?,
+    };
+    let mut config = PredictionConfig::new(wholegenome).with_genetic_code(genetic_code);
+
     /*
-     * Process -e parameter to get output metadata file
-     */
-    let mut metadata_output: Arc<Mutex<Option<File>>> = Arc::new(Mutex::new(None));
-    if matches.is_present("metadata") {
-        metadata_output = Arc::new(Mutex::new(Some(create_file_if_not_exists(
-            matches.value_of("metadata").unwrap(),
-        ))));
+    Process the --start-codons and --refine-window parameters
+    */
+    if let Some(start_codons) = matches.values_of("start-codons") {
+        let start_codons: Vec<&str> = start_codons.collect();
+        config = config.with_start_codons(&start_codons)?;
+    }
+    if let Some(window) = matches.value_of("refine-window") {
+        let window = match window {
+            "stop" => usize::MAX,
+            window => window.parse().map_err(|_| {
+                Error::InvalidArgument(String::from(
+                    "The parameter --refine-window should be stop or a numeric value.",
+                ))
+            })?,
+        };
+        config = config.with_refine_window(Some(window));
     }
 
     /*
-     * Process -d parameter to get output dna file
+    Process the --min-gene-length and --min-read-length parameters
+    */
+    if let Some(length) = matches.value_of("min-gene-length") {
+        let length = length.parse().map_err(|_| {
+            Error::InvalidArgument(String::from(
+                "The parameter --min-gene-length should have a numeric value.",
+            ))
+        })?;
+        config = config.with_min_gene_length(length);
+    }
+    if let Some(length) = matches.value_of("min-read-length") {
+        let length = length.parse().map_err(|_| {
+            Error::InvalidArgument(String::from(
+                "The parameter --min-read-length should have a numeric value.",
+            ))
+        })?;
+        config = config.with_min_sequence_length(length);
+    }
+    let min_sequence_length = config.min_sequence_length();
+
+    /*
+    Process the --posteriors and --base-posteriors parameters
+    */
+    if matches.is_present("base-posteriors") {
+        config = config.with_posteriors(PosteriorMode::Bases);
+    } else if matches.is_present("posteriors") {
+        config = config.with_posteriors(PosteriorMode::Genes);
+    }
+
+    /*
+    Process the --circular parameter
+    */
+    config = config.with_circular(matches.is_present("circular"));
+
+    /*
+    Process the --cg-bin parameter
+    */
+    config = config.with_cg_bin(cg_bin)?;
+
+    /*
+    Process the --window-length and --window-overlap parameters
+    */
+    if let Some(length) = matches.value_of("window-length") {
+        let length = length.parse().map_err(|_| {
+            Error::InvalidArgument(String::from(
+                "The parameter --window-length should have a numeric value.",
+            ))
+        })?;
+        let overlap = match matches.value_of("window-overlap") {
+            Some(overlap) => overlap.parse().map_err(|_| {
+                Error::InvalidArgument(String::from(
+                    "The parameter --window-overlap should have a numeric value.",
+                ))
+            })?,
+            None => DEFAULT_WINDOW_OVERLAP,
+        };
+        config = config.with_windows(Some(length), overlap)?;
+    }
+
+    let predictor = GenePredictor::new(train, hmm, wholegenome).with_config(config);
+
+    /*
+     * Process the -o parameter, or the -a, -e, -d and -g parameters, and the --base-posteriors parameter to get the
+     * output files (paths ending in .gz or .zst are compressed)
      */
-    let mut dna_output: Arc<Mutex<Option<File>>> = Arc::new(Mutex::new(None));
-    if matches.is_present("output") {
-        dna_output = Arc::new(Mutex::new(Some(create_file_if_not_exists(
-            matches.value_of("output").unwrap(),
-        ))));
+    let mut output = if matches.is_present("prefix") {
+        OutputFiles::from_prefix(matches.value_of("prefix").unwrap())?
+    } else {
+        let mut output = OutputFiles::default();
+        if matches.is_present("protein") {
+            output.protein = Some(create_file_if_not_exists(
+                matches.value_of("protein").unwrap(),
+            )?);
+        }
+        if matches.is_present("metadata") {
+            output.metadata = Some(create_file_if_not_exists(
+                matches.value_of("metadata").unwrap(),
+            )?);
+        }
+        if matches.is_present("output") {
+            output.dna = Some(create_file_if_not_exists(
+                matches.value_of("output").unwrap(),
+            )?);
+        }
+        if matches.is_present("gff") {
+            output.set_gff(create_file_if_not_exists(matches.value_of("gff").unwrap())?)?;
+        }
+        output
+    };
+    if let Some(path) = matches.value_of("base-posteriors") {
+        output.posteriors = Some(create_file_if_not_exists(path)?);
     }
+    // the partial flags and CG bins are only written on request, so the output stays identical to FragGeneScan 1.31
+    output.partial_flags = matches.is_present("partial");
+    output.cg_bins = matches.is_present("cg-bin");
+    let output = Mutex::new(output);
 
     /*
-     * Now we use the Rayon Parallel Iterator to perform the viterbi algorithm on each of the sequences,
-     * this will return a Vec of predictions
+     * Next we stream the FASTA or FASTQ sequences from the -i files (or STDIN) through the pipeline, we will only
+     * process those sequences longer than 70 bp's (or --min-read-length). The quality scores of FASTQ input are only
+     * used when -q was specified. The predictions are written in input order, unless --unordered was specified.
      */
-    records
-        .into_par_iter()
-        .for_each(|(header, sequence)| {
-            let mut local_hmm = hmm.clone();
-            let cg = get_prob_from_cg(&mut local_hmm, &train, &sequence);
-            let pred = viterbi(&local_hmm, &train, &sequence, wholegenome, cg, &header);
-            print_prediction(pred, &metadata_output, &dna_output);
+    let use_quality = matches.is_present("use-quality");
+    let records = input
+        .filter(|record| !matches!(record, Ok(record) if record.seq.len() <= min_sequence_length))
+        .map(move |record| {
+            record.map(|mut record| {
+                if !use_quality {
+                    record.quality = None;
+                }
+                record
+            })
         });
+    run_pipeline(records, &predictor, &output, !matches.is_present("unordered"))?;
+    output.into_inner().unwrap().finish()
+}
+
+/**
+ * This method implements the train subcommand, which estimates new training files from a genome and its
+ * CDS annotation.
+ */
+fn run_training(matches: &ArgMatches) -> Result<(), Error> {
+    let genome_path = matches.value_of("genome").unwrap();
+    let genome = FastaReader::new(open_input(genome_path)?, genome_path)
+        .collect::<Result<Vec<(String, String)>, Error>>()?;
+    let features = read_annotation(matches.value_of("annotation").unwrap())?;
+
+    let (train, summary) = train_from_annotation(&genome, &features);
+    if summary.genes_used == 0 {
+        return Err(Error::InvalidArgument(String::from(
+            "None of the annotated CDS features could be used for training.",
+        )));
+    }
+
+    let output_dir = Path::new(matches.value_of("output-dir").unwrap());
+    std::fs::create_dir_all(output_dir)
+        .and_then(|_| train.write_to_dir(output_dir))
+        .map_err(|e| Error::Output {
+            target: output_dir.display().to_string(),
+            source: e,
+        })?;
+    eprintln!(
+        "Trained on {} genes ({} skipped), training files written to {}",
+        summary.genes_used,
+        summary.genes_skipped,
+        output_dir.display()
+    );
+    Ok(())
+}
+
+/**
+ * This method implements the model compile subcommand, which writes the selected models to a binary model file.
+ */
+fn run_model_compile(matches: &ArgMatches) -> Result<(), Error> {
+    let (train, hmm) = load_models(matches.value_of("train").unwrap(), matches.value_of("model-dir"))?;
+    write_compiled_model(Path::new(matches.value_of("output").unwrap()), &train, &hmm)
+}
+
+/**
+ * This method implements the simulate subcommand, which writes simulated reads of an annotated genome and the
+ * coding regions of every read.
+ */
+fn run_simulation(matches: &ArgMatches) -> Result<(), Error> {
+    let numeric = |name: &str| {
+        Error::InvalidArgument(format!("The parameter --{} should have a numeric value.", name))
+    };
+    let reads: usize = matches.value_of("reads").unwrap().parse().map_err(|_| numeric("reads"))?;
+    let length: usize = matches.value_of("length").unwrap().parse().map_err(|_| numeric("length"))?;
+    let length_sd: f64 = matches.value_of("length-sd").unwrap().parse().map_err(|_| numeric("length-sd"))?;
+    let seed: u64 = matches.value_of("seed").unwrap().parse().map_err(|_| numeric("seed"))?;
+    let profile = ErrorProfile::from_name(matches.value_of("error-model").unwrap())?;
+    let config = SimulationConfig::new(profile)
+        .with_reads(reads)
+        .with_length(length, length_sd)?
+        .with_seed(seed);
+
+    let genome_path = matches.value_of("genome").unwrap();
+    let genome = FastaReader::new(open_input(genome_path)?, genome_path)
+        .collect::<Result<Vec<(String, String)>, Error>>()?;
+    let features = read_annotation(matches.value_of("annotation").unwrap())?;
+    let simulator = ReadSimulator::new(&genome, &features, config)?;
+
+    let prefix = matches.value_of("output").unwrap();
+    let fasta_path = format!("{}.fna", prefix);
+    let gff_path = format!("{}.gff", prefix);
+    let mut fasta = BufWriter::new(create_file_if_not_exists(&fasta_path)?);
+    let mut gff = BufWriter::new(create_file_if_not_exists(&gff_path)?);
+    let output_error = |path: &str, e: io::Error| Error::Output {
+        target: path.to_string(),
+        source: e,
+    };
+    write_data(&mut gff, String::from("##gff-version 3\n")).map_err(|e| output_error(&gff_path, e))?;
+    for read in simulator {
+        write_simulated_read(&mut fasta, &mut gff, &read).map_err(|e| output_error(&fasta_path, e))?;
+    }
+    for (path, file) in [(&fasta_path, fasta), (&gff_path, gff)] {
+        file.into_inner()
+            .map_err(|e| e.into_error())
+            .and_then(|file| file.finish())
+            .map_err(|e| output_error(path, e))?;
+    }
+    Ok(())
+}
+
+/**
+ * This method implements the eval subcommand, which evaluates predictions (read from a file, or predicted on the
+ * genome with the selected model) against a reference annotation and writes the report to stdout.
+ */
+fn run_eval(matches: &ArgMatches) -> Result<(), Error> {
+    let reference = read_annotation(matches.value_of("reference").unwrap())?;
+    let predictions = match matches.value_of("predictions") {
+        Some(path) => read_predictions(path)?,
+        None => {
+            let (train, hmm) = load_models(matches.value_of("train").unwrap(), matches.value_of("model-dir"))?;
+            let wholegenome = matches.value_of("whole-genome") == Some("1");
+            let predictor = GenePredictor::new(train, hmm, wholegenome);
+            let genome_path = matches.value_of("genome").unwrap();
+            let records = SequenceReader::new(open_input(genome_path)?, genome_path)?
+                .collect::<Result<Vec<Record>, Error>>()?;
+            records
+                .par_iter()
+                .flat_map(|record| prediction_features(&predictor.predict_record(record)))
+                .collect()
+        }
+    };
+
+    let report = evaluate(&reference, &predictions);
+    print!("{}", report);
+    if let Some(path) = matches.value_of("json") {
+        let json = serde_json::to_string_pretty(&report).expect("the report can always be serialized");
+        let mut file = create_file_if_not_exists(path)?;
+        write_data(&mut file, format!("{}\n", json))
+            .and_then(|_| file.finish())
+            .map_err(|e| Error::Output {
+                target: path.to_string(),
+                source: e,
+            })?;
+    }
+    Ok(())
 }
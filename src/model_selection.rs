@@ -0,0 +1,286 @@
+use super::input::Record;
+use super::simulation::Platform;
+use std::collections::HashMap;
+
+/*
+ * model_selection.rs
+ * ==================
+ * This file contains the automatic selection of the train model (-t auto) for sequence reads. The platform of the
+ * reads is given as a hint or guessed from a sample of the reads: their names, the distribution of their lengths
+ * and the frequency of long homopolymer runs, which 454 reads lengthen and shorten. The error rate is estimated
+ * from the quality scores of FASTQ reads, reads without quality scores get the default model of their platform.
+ */
+
+/**
+ * The number of reads sampled from the start of the input for selecting the model.
+ */
+pub const SAMPLE_SIZE: usize = 1000;
+
+/**
+ * Median read length (in bp) from which the sequences are considered assembled contigs.
+ */
+const CONTIG_LENGTH: usize = 2000;
+
+/**
+ * Median read length (in bp) from which reads without other signals are considered Sanger reads.
+ */
+const SANGER_LENGTH: usize = 500;
+
+/**
+ * Coefficient of variation of the read lengths from which reads are considered 454 reads, Illumina reads have
+ * (nearly) the same length.
+ */
+const VARIABLE_LENGTH_CV: f64 = 0.15;
+
+/**
+ * Coefficient of variation of the read lengths from which an excess of homopolymer runs is attributed to 454 reads,
+ * rather than to the genome of reads with (nearly) the same length.
+ */
+const HOMOPOLYMER_LENGTH_CV: f64 = 0.05;
+
+/**
+ * Ratio of the observed to the expected number of homopolymer runs of at least HOMOPOLYMER_RUN bases, from which
+ * the reads are considered 454 reads with a high error rate. Genomes without sequencing errors typically have a
+ * ratio between 1.1 and 1.2.
+ */
+const HOMOPOLYMER_EXCESS: f64 = 1.25;
+const HOMOPOLYMER_RUN: usize = 4;
+
+/**
+ * The bundled models of every platform, with their error rate.
+ */
+const MODELS: [(Platform, &str, f64); 8] = [
+  (Platform::Sanger, "sanger_5", 0.005),
+  (Platform::Sanger, "sanger_10", 0.01),
+  (Platform::Roche454, "454_5", 0.005),
+  (Platform::Roche454, "454_10", 0.01),
+  (Platform::Roche454, "454_30", 0.03),
+  (Platform::Illumina, "illumina_1", 0.001),
+  (Platform::Illumina, "illumina_5", 0.005),
+  (Platform::Illumina, "illumina_10", 0.01),
+];
+
+/**
+ * The characteristics of a sample of reads which are used for selecting the model.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReadStatistics {
+  pub reads: usize,
+  pub median_length: usize,
+  /* coefficient of variation (standard deviation / mean) of the read lengths */
+  pub length_cv: f64,
+  /* mean error probability of the quality scores, None when the reads have no quality scores */
+  pub mean_error: Option<f64>,
+  /* ratio of the observed to the expected number of long homopolymer runs, see HOMOPOLYMER_EXCESS */
+  pub homopolymer_excess: f64,
+  /* the platform of the read names, when all reads are named like the reads of one platform */
+  pub named_platform: Option<Platform>,
+}
+
+impl ReadStatistics {
+  /**
+   * Computes the statistics of a sample of reads.
+   */
+  pub fn from_records(records: &[Record]) -> ReadStatistics {
+    let mut lengths: Vec<usize> = records.iter().map(|record| record.seq.len()).collect();
+    lengths.sort_unstable();
+    let reads = lengths.len();
+    let mean = lengths.iter().sum::<usize>() as f64 / reads.max(1) as f64;
+    let variance = lengths.iter().map(|&length| (length as f64 - mean).powi(2)).sum::<f64>() / reads.max(1) as f64;
+
+    let errors: Vec<f64> = records
+      .iter()
+      .filter_map(|record| record.quality.as_ref())
+      .flatten()
+      .map(|&quality| 10.0_f64.powf(-(quality as f64) / 10.0))
+      .collect();
+
+    let platforms: Vec<Option<Platform>> = records.iter().map(|record| read_name_platform(&record.id)).collect();
+    let named_platform = match platforms.first() {
+      Some(&Some(platform)) if platforms.iter().all(|&other| other == Some(platform)) => Some(platform),
+      _ => None,
+    };
+
+    ReadStatistics {
+      reads,
+      median_length: lengths.get(reads / 2).copied().unwrap_or(0),
+      length_cv: if mean > 0.0 { variance.sqrt() / mean } else { 0.0 },
+      mean_error: match errors.is_empty() {
+        true => None,
+        false => Some(errors.iter().sum::<f64>() / errors.len() as f64),
+      },
+      homopolymer_excess: homopolymer_excess(records),
+      named_platform,
+    }
+  }
+}
+
+/**
+ * The selected model, with the reason it was selected.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelChoice {
+  pub model: String,
+  pub reason: String,
+}
+
+/**
+ * Selects the bundled model for a sample of reads, the platform is guessed from the statistics of the reads unless
+ * a hint is given. Whole genome sequences always get the complete model.
+ */
+pub fn select_model(statistics: &ReadStatistics, wholegenome: bool, hint: Option<Platform>) -> ModelChoice {
+  if wholegenome {
+    return ModelChoice {
+      model: String::from("complete"),
+      reason: String::from("the input consists of whole genome sequences (-w 1)"),
+    };
+  }
+
+  let homopolymers = format!(
+    "homopolymer runs are {:.2} times as frequent as expected",
+    statistics.homopolymer_excess
+  );
+  let (platform, platform_reason) = if let Some(platform) = hint {
+    (platform, String::from("the platform was given with --platform"))
+  } else if let Some(platform) = statistics.named_platform {
+    (platform, format!("the reads are named like {} reads", platform_name(platform)))
+  } else if statistics.median_length >= CONTIG_LENGTH {
+    (
+      Platform::ErrorFree,
+      format!(
+        "the median length of {} bp is typical for assembled contigs",
+        statistics.median_length
+      ),
+    )
+  } else if statistics.homopolymer_excess >= HOMOPOLYMER_EXCESS && statistics.length_cv >= HOMOPOLYMER_LENGTH_CV {
+    (Platform::Roche454, format!("{} and the read lengths vary", homopolymers))
+  } else if statistics.median_length >= SANGER_LENGTH {
+    (
+      Platform::Sanger,
+      format!("the median length of {} bp is typical for Sanger reads", statistics.median_length),
+    )
+  } else if statistics.length_cv >= VARIABLE_LENGTH_CV {
+    (
+      Platform::Roche454,
+      format!("the read lengths vary (coefficient of variation {:.2})", statistics.length_cv),
+    )
+  } else {
+    (
+      Platform::Illumina,
+      format!("the reads have (nearly) the same length of {} bp", statistics.median_length),
+    )
+  };
+
+  if platform == Platform::ErrorFree {
+    return ModelChoice {
+      model: String::from("complete"),
+      reason: platform_reason,
+    };
+  }
+  let models: Vec<&(Platform, &str, f64)> = MODELS.iter().filter(|(other, ..)| *other == platform).collect();
+  let (model, rate_reason) = match statistics.mean_error {
+    Some(error) => {
+      /* the model with the closest error rate, on a logarithmic scale */
+      let distance = |rate: f64| (rate.ln() - error.max(1e-6).ln()).abs();
+      let (_, model, _) = models
+        .iter()
+        .min_by(|(_, _, a), (_, _, b)| distance(*a).partial_cmp(&distance(*b)).unwrap())
+        .unwrap();
+      (
+        *model,
+        format!("the quality scores give an error rate of {:.2}%", error * 100.0),
+      )
+    }
+    None if platform == Platform::Roche454 && statistics.homopolymer_excess >= HOMOPOLYMER_EXCESS => {
+      ("454_30", homopolymers)
+    }
+    None => {
+      let model = match platform {
+        Platform::Sanger => "sanger_5",
+        Platform::Roche454 => "454_10",
+        _ => "illumina_5",
+      };
+      (model, String::from("the reads have no quality scores, the default error rate of the platform is used"))
+    }
+  };
+
+  ModelChoice {
+    model: model.to_string(),
+    reason: format!("{}, {}", platform_reason, rate_reason),
+  }
+}
+
+/**
+ * Guesses the platform of a read from its name: Illumina reads are named
+ * instrument:run:flowcell:lane:tile:x:y (or instrument:lane:tile:x:y#index/pair before CASAVA 1.8), 454 reads
+ * have a 14 character name of which the 8th and 9th character are the region of the plate.
+ */
+pub fn read_name_platform(id: &str) -> Option<Platform> {
+  let fields: Vec<&str> = id.split(':').collect();
+  let numeric = |field: &str| {
+    let field = field.split(['#', '/']).next().unwrap_or("");
+    !field.is_empty() && field.chars().all(|c| c.is_ascii_digit())
+  };
+  if fields.len() >= 5 && fields[fields.len() - 3..].iter().all(|field| numeric(field)) {
+    return Some(Platform::Illumina);
+  }
+  let bytes = id.as_bytes();
+  if bytes.len() == 14
+    && bytes.iter().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+    && bytes[7..9].iter().all(u8::is_ascii_digit)
+  {
+    return Some(Platform::Roche454);
+  }
+  None
+}
+
+/**
+ * Helper method to compute the ratio of the observed number of homopolymer runs of at least HOMOPOLYMER_RUN bases
+ * to the number expected from the base composition of the reads.
+ */
+fn homopolymer_excess(records: &[Record]) -> f64 {
+  let mut composition: HashMap<u8, usize> = HashMap::new();
+  let mut runs = 0;
+  let mut long_runs = 0;
+  for record in records {
+    let seq = record.seq.as_bytes();
+    let mut start = 0;
+    while start < seq.len() {
+      let base = seq[start].to_ascii_uppercase();
+      let length = seq[start..].iter().take_while(|c| c.to_ascii_uppercase() == base).count();
+      if b"ACGT".contains(&base) {
+        *composition.entry(base).or_insert(0) += length;
+        runs += 1;
+        if length >= HOMOPOLYMER_RUN {
+          long_runs += 1;
+        }
+      }
+      start += length;
+    }
+  }
+
+  /* a run of base b starts with probability p(b) (1 - p(b)) and continues with probability p(b) */
+  let total = composition.values().sum::<usize>() as f64;
+  let (mut starts, mut long_starts) = (0.0, 0.0);
+  for &count in composition.values() {
+    let p = count as f64 / total;
+    starts += p * (1.0 - p);
+    long_starts += p * (1.0 - p) * p.powi(HOMOPOLYMER_RUN as i32 - 1);
+  }
+  match runs > 0 && long_starts > 0.0 {
+    true => (long_runs as f64 / runs as f64) / (long_starts / starts),
+    false => 1.0,
+  }
+}
+
+/**
+ * Helper method to get the name of a platform used in the reasons of a ModelChoice.
+ */
+fn platform_name(platform: Platform) -> &'static str {
+  match platform {
+    Platform::ErrorFree => "error-free",
+    Platform::Sanger => "Sanger",
+    Platform::Roche454 => "454",
+    Platform::Illumina => "Illumina",
+  }
+}
@@ -1,134 +1,293 @@
-use super::helpers::write_data;
-use super::viterbi::Prediction;
-use std::fs::File;
-use std::sync::{Arc, Mutex};
+use super::error::Error;
+use super::helpers::{create_file_if_not_exists, write_data, OutputFile};
+use super::posterior::BasePosteriors;
+use super::viterbi::{Out, Prediction};
+use std::io::{self, Write};
+use std::sync::Mutex;
 
-
-/**
+/*
  * output.rs
  * =========
  * This file contains methods responsible for processing the Prediction structs resulting from a run of viterbi::viterbi,
- * it will take care of writing the output to the proper channel (stdout / (optional) metadata files).
+ * it will take care of writing the output to the proper channel (stdout / (optional) protein, metadata, DNA and GFF3 files).
  */
 
 /**
- * This method will print a single Prediction to stdout as AA sequence and optionally output metadata and dna metadata.
+ * The OutputFiles struct bundles all output channels of a run, the protein output is written to stdout when no
+ * protein file was specified, all other outputs are only written when their file is present.
  */
-pub fn print_prediction(
-    prediction: Prediction,
-    metadata_output: &Arc<Mutex<Option<File>>>,
-    dna_output: &Arc<Mutex<Option<File>>>,
-) {
-    let metadata_option = &mut *(metadata_output.lock().unwrap());
-    let dna_option = &mut *(dna_output.lock().unwrap());
-
-    
+#[derive(Default)]
+pub struct OutputFiles {
+    pub protein: Option<OutputFile>,
+    pub metadata: Option<OutputFile>,
+    pub dna: Option<OutputFile>,
+    pub gff: Option<OutputFile>,
+    pub posteriors: Option<OutputFile>,
+    /* whether partial genes get a partial=XY column and attribute (see gene_partial_flags), not in FGS 1.31 */
+    pub partial_flags: bool,
+    /* whether the CG bin of every sequence is written to its metadata header line, FragGeneScan 1.31 does not */
+    pub cg_bins: bool,
+}
+
+impl OutputFiles {
+    /**
+     * This method will create the four output files <prefix>.faa, <prefix>.ffn, <prefix>.out and <prefix>.gff
+     * using the same naming conventions as the -o option of FragGeneScan 1.31.
+     */
+    pub fn from_prefix(prefix: &str) -> Result<OutputFiles, Error> {
+        let mut output = OutputFiles {
+            protein: Some(create_file_if_not_exists(&format!("{}.faa", prefix))?),
+            metadata: Some(create_file_if_not_exists(&format!("{}.out", prefix))?),
+            dna: Some(create_file_if_not_exists(&format!("{}.ffn", prefix))?),
+            gff: None,
+            ..OutputFiles::default()
+        };
+        output.set_gff(create_file_if_not_exists(&format!("{}.gff", prefix))?)?;
+        Ok(output)
+    }
+
+    /**
+     * This method will set the GFF3 output file and write the GFF3 header line to it.
+     */
+    pub fn set_gff(&mut self, mut gff_output: OutputFile) -> Result<(), Error> {
+        print_gff_header(&mut gff_output).map_err(|e| Error::output("GFF3 output", e))?;
+        self.gff = Some(gff_output);
+        Ok(())
+    }
+
+    /**
+     * This method will finish all output files, which writes the end of the compressed outputs. It should be
+     * called once after the last prediction was written.
+     */
+    pub fn finish(self) -> Result<(), Error> {
+        let outputs = [
+            (self.protein, "protein output"),
+            (self.metadata, "metadata output"),
+            (self.dna, "DNA output"),
+            (self.gff, "GFF3 output"),
+            (self.posteriors, "posterior output"),
+        ];
+        for (file, target) in outputs {
+            if let Some(file) = file {
+                file.finish().map_err(|e| Error::output(target, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/**
+ * This method will print a single Prediction as AA sequence (to stdout or the protein file) and optionally output
+ * metadata, dna metadata and GFF3 features.
+ */
+pub fn print_prediction(prediction: Prediction, output: &Mutex<OutputFiles>) -> Result<(), Error> {
+    let output = &mut *(output.lock().unwrap());
+
     // Should we output to the metadata file
-    if metadata_option.is_some() {
-        write_data(
-            metadata_option.as_mut().unwrap(),
-            format!(">{}\n", prediction.head),
-        );
+    if let Some(metadata_file) = output.metadata.as_mut() {
+        let header = match output.cg_bins {
+            true => format!(">{}\tcg_bin={}\n", prediction.head, prediction.cg),
+            false => format!(">{}\n", prediction.head),
+        };
+        write_data(metadata_file, header).map_err(|e| Error::output("metadata output", e))?;
     }
-    for out in prediction.outs {
-        print_aa(
-            &prediction.head,
-            out.dna_start_t,
-            out.dna_end_t,
-            out.forward,
-            &out.protein,
-        );
+    // Circular sequences get a region feature in the GFF3 file
+    if let (Some(gff_file), Some(length)) = (output.gff.as_mut(), prediction.circular_length) {
+        print_gff_region(gff_file, &prediction.head, length).map_err(|e| Error::output("GFF3 output", e))?;
+    }
+    for out in &prediction.outs {
+        match output.protein.as_mut() {
+            Some(protein_file) => print_aa(protein_file, &prediction.head, out),
+            None => print_aa(&mut io::stdout().lock(), &prediction.head, out),
+        }
+        .map_err(|e| Error::output("protein output", e))?;
         // Should we output to the metadata file
-        if metadata_option.is_some() {
-            print_metadata(
-                metadata_option.as_mut().unwrap(),
-                out.dna_start_t,
-                out.dna_end_t,
-                out.frame,
-                out.forward,
-                out.final_score,
-                out.insert,
-                out.delete,
-            );
+        if let Some(metadata_file) = output.metadata.as_mut() {
+            print_metadata(metadata_file, out, output.partial_flags)
+                .map_err(|e| Error::output("metadata output", e))?;
         }
         // Should we output to the dna metadata file
-        if dna_option.is_some() {
-            print_dna_metadata(
-                dna_option.as_mut().unwrap(),
-                &prediction.head,
-                out.dna_start_t,
-                out.dna_end_t,
-                out.forward,
-                &out.dna,
-            );
+        if let Some(dna_file) = output.dna.as_mut() {
+            print_dna_metadata(dna_file, &prediction.head, out)
+                .map_err(|e| Error::output("DNA output", e))?;
+        }
+        // Should we output to the GFF3 file
+        if let Some(gff_file) = output.gff.as_mut() {
+            print_gff(gff_file, &prediction.head, out, output.partial_flags)
+                .map_err(|e| Error::output("GFF3 output", e))?;
         }
     }
+    // Should we output the per base posteriors
+    if let (Some(posterior_file), Some(posteriors)) = (output.posteriors.as_mut(), &prediction.posteriors) {
+        print_posteriors(posterior_file, &prediction.head, posteriors)
+            .map_err(|e| Error::output("posterior output", e))?;
+    }
+    Ok(())
 }
 
 /**
- * Helper method to print amino acids to stdout in correct format
+ * This method will write the GFF3 header line, it should be called once before any prediction is written to the
+ * GFF3 output file.
  */
-fn print_aa(head: &String, start_t: usize, end_t: usize, forward: bool, protein: &String) {
-    println!(
-        ">{}_{}_{}_{}",
-        head,
-        start_t,
-        end_t,
-        forward_to_chr(forward)
-    );
-    println!("{}", protein);
+pub fn print_gff_header<W: Write>(gff_output: &mut W) -> io::Result<()> {
+    write_data(gff_output, String::from("##gff-version 3\n"))
+}
+
+/**
+ * Helper method to write amino acids to the protein output in correct format
+ */
+fn print_aa<W: Write>(protein_output: &mut W, head: &str, out: &Out) -> io::Result<()> {
+    write_data(
+        protein_output,
+        format!(
+            ">{}_{}_{}_{}\n",
+            head,
+            out.dna_start_t,
+            out.dna_end_t,
+            forward_to_chr(out.forward)
+        ),
+    )?;
+    write_data(protein_output, format!("{}\n", out.protein))
 }
 
 /**
  * Helper method to write dna output metadata to the specified output file
  */
-fn print_dna_metadata(
-    dna_output: &mut File,
-    head: &String,
-    start_t: usize,
-    end_t: usize,
-    forward: bool,
-    dna: &String,
-) {
+fn print_dna_metadata<W: Write>(dna_output: &mut W, head: &str, out: &Out) -> io::Result<()> {
     write_data(
         dna_output,
         format!(
             ">{}_{}_{}_{}\n",
             head,
-            start_t,
-            end_t,
-            forward_to_chr(forward)
+            out.dna_start_t,
+            out.dna_end_t,
+            forward_to_chr(out.forward)
+        ),
+    )?;
+    write_data(dna_output, format!("{}\n", out.dna))
+}
+
+/**
+ * Helper method to write metadata to the specified output file, the score is written with six decimals
+ * like FragGeneScan 1.31 does. When posteriors were computed, the coding, start and stop posterior of the gene are
+ * written in an additional "P:" column. With partial_flags, partial genes get an additional "partial=XY" column
+ * (see gene_partial_flags). Genes below the thresholds of FragGeneScan 1.31 get an additional "short" column.
+ */
+fn print_metadata<W: Write>(metadata_output: &mut W, out: &Out, partial_flags: bool) -> io::Result<()> {
+    write_data(
+        metadata_output,
+        format!(
+            "{}\t{}\t{}\t{}\t{:.6}\t",
+            out.dna_start_t,
+            out.dna_end_t,
+            forward_to_chr(out.forward),
+            out.frame,
+            out.final_score
         ),
-    );
-    write_data(dna_output, format!("{}\n", dna));
+    )?;
+    write_data(metadata_output, String::from("I:"))?;
+    for i in &out.insert {
+        write_data(metadata_output, format!("{},", i))?;
+    }
+    write_data(metadata_output, String::from("\tD:"))?;
+    for d in &out.delete {
+        write_data(metadata_output, format!("{},", d))?;
+    }
+    if let Some(posterior) = &out.posterior {
+        write_data(
+            metadata_output,
+            format!("\tP:{:.4},{:.4},{:.4}", posterior.coding, posterior.start, posterior.stop),
+        )?;
+    }
+    if let Some(partial) = gene_partial_flags(out).filter(|_| partial_flags) {
+        write_data(metadata_output, format!("\tpartial={}", partial))?;
+    }
+    if out.below_threshold {
+        write_data(metadata_output, String::from("\tshort"))?;
+    }
+    write_data(metadata_output, String::from("\n"))
 }
 
 /**
- * Helper method to write metadata to the specified output file
+ * Helper method to write a single CDS feature to the specified GFF3 output file, the format is identical to
+ * the one used by FragGeneScan 1.31 (the phase is derived from the frame of the prediction). When posteriors were
+ * computed, the coding posterior of the gene is written as score. With partial_flags, partial genes get a partial
+ * attribute.
  */
-fn print_metadata(
-    out: &mut File,
-    start_t: usize,
-    end_t: usize,
-    frame: usize,
-    forward: bool,
-    final_score: f64,
-    insert: Vec<usize>,
-    delete: Vec<usize>,
-) {
+fn print_gff<W: Write>(gff_output: &mut W, head: &str, out: &Out, partial_flags: bool) -> io::Result<()> {
+    let score = match &out.posterior {
+        Some(posterior) => format!("{:.4}", posterior.coding),
+        None => String::from("."),
+    };
+    let partial = match gene_partial_flags(out).filter(|_| partial_flags) {
+        Some(partial) => format!(";partial={}", partial),
+        None => String::new(),
+    };
     write_data(
-        out,
-        format!("{}\t{}\t{}\t{}\t{}\t", start_t, end_t, forward_to_chr(forward), frame, final_score),
-    );
-    write_data(out, String::from("I:"));
-    for i in insert {
-        write_data(out, format!("{},", i));
+        gff_output,
+        format!(
+            "{}\tFGS\tCDS\t{}\t{}\t{}\t{}\t{}\tID={}_{}_{}_{};product=predicted protein{}\n",
+            head,
+            out.dna_start_t,
+            out.dna_end_t,
+            score,
+            forward_to_chr(out.forward),
+            out.frame - 1,
+            head,
+            out.dna_start_t,
+            out.dna_end_t,
+            forward_to_chr(out.forward),
+            partial
+        ),
+    )
+}
+
+/**
+ * Helper method to get the partial flags of a gene in the convention of Prodigal, two digits for the left and right
+ * end of the gene on the sequence, 1 when the gene runs off the sequence at that end and 0 when it ends at a start
+ * or stop codon. Complete genes (00) give None.
+ */
+fn gene_partial_flags(out: &Out) -> Option<String> {
+    let (left, right) = match out.forward {
+        true => (out.partial_start, out.partial_end),
+        false => (out.partial_end, out.partial_start),
+    };
+    match (left, right) {
+        (false, false) => None,
+        _ => Some(format!("{}{}", left as u8, right as u8)),
     }
-    write_data(out, String::from("\tD:"));
-    for d in delete {
-        write_data(out, format!("{},", d));
+}
+
+/**
+ * Helper method to write the region feature of a circular sequence to the specified GFF3 output file, features
+ * spanning the origin end past the end of this region.
+ */
+fn print_gff_region<W: Write>(gff_output: &mut W, head: &str, length: usize) -> io::Result<()> {
+    write_data(
+        gff_output,
+        format!(
+            "{}\tFGS\tregion\t1\t{}\t.\t+\t.\tID={};Is_circular=true\n",
+            head, length, head
+        ),
+    )
+}
+
+/**
+ * Helper method to write the coding posterior of every base of a sequence on the forward and reverse strand, as
+ * one tab separated line (sequence, position, forward, reverse) per base.
+ */
+fn print_posteriors<W: Write>(
+    posterior_output: &mut W,
+    head: &str,
+    posteriors: &BasePosteriors,
+) -> io::Result<()> {
+    for (t, (forward, reverse)) in posteriors.forward.iter().zip(&posteriors.reverse).enumerate() {
+        write_data(
+            posterior_output,
+            format!("{}\t{}\t{:.4}\t{:.4}\n", head, t + 1, forward, reverse),
+        )?;
     }
-    write_data(out, String::from("\n"));
+    Ok(())
 }
 
 /**
@@ -0,0 +1,130 @@
+use super::error::Error;
+use super::input::Record;
+use super::output::{print_prediction, OutputFiles};
+use super::predictor::GenePredictor;
+use super::viterbi::Prediction;
+use rayon::prelude::*;
+use std::mem;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::Mutex;
+use std::thread;
+
+/*
+ * pipeline.rs
+ * ===========
+ * This file contains the streaming pipeline used by the fgsrs binary. A reader thread groups the input records into
+ * chunks, which are sent over a bounded channel to the calling thread. Only a limited number of chunks is in memory
+ * at any time (at most CHUNKS_IN_FLIGHT read ahead plus the chunk being predicted), and the size of a chunk grows
+ * with the number of threads, so the memory usage does not depend on the size of the input.
+ *
+ * The records of a chunk are predicted in parallel by the rayon workers. By default the predictions of a chunk are
+ * collected and written in the order of the input records, otherwise every prediction is written as soon as it is
+ * available. Chunks are predicted one after the other, so no worker ever waits on the reader.
+ */
+
+/**
+ * Maximum number of records in a single chunk.
+ */
+#[doc(hidden)]
+pub const CHUNK_RECORDS: usize = 1024;
+
+/**
+ * A chunk is sent as soon as it holds at least this many bases per worker thread, so long sequences (e.g. complete
+ * genomes) are still predicted in parallel with each other.
+ */
+const CHUNK_BASES: usize = 4 * 1024 * 1024;
+
+/**
+ * Maximum number of chunks which have been read but not yet predicted.
+ */
+#[doc(hidden)]
+pub const CHUNKS_IN_FLIGHT: usize = 4;
+
+type Chunk = Vec<Record>;
+
+/**
+ * This method will run the predictor on every record of the input and write the predictions to the output, either
+ * in input order (ordered = true) or as soon as they are available. The first error (while reading, predicting or
+ * writing) stops the pipeline and is returned.
+ */
+pub fn run_pipeline<I>(
+  records: I,
+  predictor: &GenePredictor,
+  output: &Mutex<OutputFiles>,
+  ordered: bool,
+) -> Result<(), Error>
+where
+  I: Iterator<Item = Result<Record, Error>> + Send,
+{
+  run_pipeline_with(records, |record| predictor.predict_record(record), output, ordered)
+}
+
+/**
+ * This method will run the pipeline like run_pipeline, but every record is predicted with the specified function
+ * (e.g. a GenePredictor followed by some post-processing). The records of a chunk are predicted in parallel, so
+ * an input smaller than a single chunk is still spread over all threads.
+ */
+pub fn run_pipeline_with<I, P>(
+  records: I,
+  predict: P,
+  output: &Mutex<OutputFiles>,
+  ordered: bool,
+) -> Result<(), Error>
+where
+  I: Iterator<Item = Result<Record, Error>> + Send,
+  P: Fn(&Record) -> Prediction + Sync,
+{
+  let chunk_bases = CHUNK_BASES * rayon::current_num_threads();
+  let (sender, receiver) = sync_channel(CHUNKS_IN_FLIGHT);
+
+  thread::scope(move |scope| {
+    scope.spawn(move || read_chunks(records, sender, chunk_bases));
+    // Dropping the receiver on an error unblocks the reader thread
+    receiver.into_iter().try_for_each(|chunk: Result<Chunk, Error>| {
+      let chunk = chunk?;
+      if ordered {
+        let predictions: Vec<Prediction> = chunk.par_iter().map(&predict).collect();
+        predictions
+          .into_iter()
+          .try_for_each(|prediction| print_prediction(prediction, output))
+      } else {
+        chunk
+          .par_iter()
+          .try_for_each(|record| print_prediction(predict(record), output))
+      }
+    })
+  })
+}
+
+/**
+ * Helper method run by the reader thread. The reader stops as soon as the pipeline is gone.
+ */
+fn read_chunks<I>(records: I, sender: SyncSender<Result<Chunk, Error>>, chunk_bases: usize)
+where
+  I: Iterator<Item = Result<Record, Error>>,
+{
+  let mut chunk = Vec::new();
+  let mut bases = 0;
+  for record in records {
+    match record {
+      Ok(record) => {
+        bases += record.seq.len();
+        chunk.push(record);
+        if chunk.len() >= CHUNK_RECORDS || bases >= chunk_bases {
+          if sender.send(Ok(mem::take(&mut chunk))).is_err() {
+            return;
+          }
+          bases = 0;
+        }
+      }
+      Err(e) => {
+        // the receiver may already be gone, in which case an earlier error is reported instead
+        let _ = sender.send(Err(e));
+        return;
+      }
+    }
+  }
+  if !chunk.is_empty() {
+    let _ = sender.send(Ok(chunk));
+  }
+}
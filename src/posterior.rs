@@ -0,0 +1,474 @@
+use super::config::PredictionConfig;
+use super::constants::*;
+use super::dna_helpers::complement_nt;
+use super::genetic_code::codon_index;
+use super::train::HMM;
+use super::viterbi::{
+  reverse_start_adjustment, reverse_stop_adjustment, start_adjustment, start_codon_prob,
+  stop_adjustment, stop_codon_prob, Column, Out, Window,
+};
+
+/*
+ * posterior.rs
+ * ============
+ * This file contains the forward-backward algorithm, which computes posterior probabilities over the same HMM as
+ * viterbi::viterbi. Every transition considered by the viterbi recursion is an edge of a lattice, which goes from a
+ * state at position t - 1 to a state at position t (or t + 2 for the start and stop codon states, which emit three
+ * bases at once). The forward pass sums the probabilities of all paths into a node instead of keeping the best one,
+ * the backward pass does the same for all paths out of a node.
+ *
+ * The lattice is derived from the viterbi matrices: states the viterbi recursion excluded (e.g. match states which
+ * would read through a stop codon) are excluded here as well, and the constraints which depend on the viterbi path
+ * (insertions next to stop codons) are taken from the viterbi path. All probabilities are kept as -log values like
+ * in viterbi.rs.
+ */
+
+/**
+ * Posterior probabilities of a single predicted gene.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GenePosterior {
+  /* mean posterior probability of the bases of the gene being coding on the strand of the gene */
+  pub coding: f64,
+  /* posterior probability of the start codon, 0 when the gene has no start codon in the sequence */
+  pub start: f64,
+  /* posterior probability of the stop codon, 0 when the gene has no stop codon in the sequence */
+  pub stop: f64,
+}
+
+/**
+ * Posterior probability of every base of a sequence being coding on the forward and reverse strand.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasePosteriors {
+  pub forward: Vec<f64>,
+  pub reverse: Vec<f64>,
+}
+
+/**
+ * Result of the forward-backward algorithm, the start and stop codon posteriors are stored at the position of the
+ * last base of every codon.
+ */
+pub(crate) struct Posteriors {
+  bases: BasePosteriors,
+  start: Vec<f64>,
+  stop: Vec<f64>,
+  reverse_start: Vec<f64>,
+  reverse_stop: Vec<f64>,
+}
+
+impl Posteriors {
+  /**
+   * Gets the posterior probabilities of a gene predicted by viterbi::viterbi.
+   */
+  pub fn gene(&self, out: &Out) -> GenePosterior {
+    let (coding, start, stop) = if out.forward {
+      (&self.bases.forward, &self.start, &self.stop)
+    } else {
+      (&self.bases.reverse, &self.reverse_start, &self.reverse_stop)
+    };
+    let bases = &coding[out.dna_start_t - 1..out.dna_end_t];
+    let codon = |codons: &Vec<f64>, t: usize| codons.get(t).copied().unwrap_or(0.0);
+    // the codon at the left end of the gene is the start codon on the forward and the stop codon on the
+    // reverse strand
+    let (start_t, stop_t) = if out.forward {
+      (out.dna_start_t + 1, out.dna_end_t - 1)
+    } else {
+      (out.dna_end_t - 1, out.dna_start_t + 1)
+    };
+    GenePosterior {
+      coding: bases.iter().sum::<f64>() / bases.len() as f64,
+      start: codon(start, start_t),
+      stop: codon(stop, stop_t),
+    }
+  }
+
+  pub fn into_bases(self) -> BasePosteriors {
+    self.bases
+  }
+}
+
+/**
+ * The lattice of a single sequence, see the top of this file.
+ */
+struct Lattice<'a> {
+  hmm: &'a HMM,
+  /* the encoded sequence (see dna_helpers::encode_bases) */
+  nts: &'a [u8],
+  config: &'a PredictionConfig,
+  error_prob: Option<&'a [f64]>,
+  viterbi_alpha: &'a [Window<f64>],
+  viterbi_path: &'a [Window<i8>],
+  /* per position, the match states (bit k for M(k+1), bit 6 + k for M'(k+1)) which cannot be entered from an
+   * insert state since that would create a stop codon */
+  blocked_inserts: Vec<u16>,
+}
+
+/**
+ * Runs the forward-backward algorithm on a sequence after viterbi::viterbi filled in the alpha and path matrices
+ * for it, on the same encoded sequence (see dna_helpers::encode_bases). The error_prob holds the error probability
+ * of every base when quality scores are used.
+ */
+pub(crate) fn forward_backward(
+  hmm: &HMM,
+  nts: &[u8],
+  error_prob: Option<&[f64]>,
+  config: &PredictionConfig,
+  viterbi_alpha: &[Window<f64>],
+  viterbi_path: &[Window<i8>],
+) -> Posteriors {
+  let len_seq = nts.len();
+  let mut lattice = Lattice {
+    hmm,
+    nts,
+    config,
+    error_prob,
+    viterbi_alpha,
+    viterbi_path,
+    blocked_inserts: Vec::new(),
+  };
+  lattice.blocked_inserts = lattice.blocked_inserts();
+
+  /* forward pass */
+  let mut alpha = vec![vec![f64::INFINITY; len_seq]; NUM_STATE];
+  for (i, alpha) in alpha.iter_mut().enumerate() {
+    if lattice.live(i, 0) {
+      alpha[0] = -hmm.initial_state[i];
+    }
+  }
+  for t in 1..len_seq {
+    if t == 2 && lattice.live(E_STATE, 2) {
+      /* stop codon at the start of the sequence */
+      let s = nts;
+      alpha[E_STATE][2] = -stop_codon_prob(s[0], s[1], s[2], [0.53_f64.ln(), 0.16_f64.ln(), 0.30_f64.ln()]);
+    }
+    lattice.edges(t, |i, j, from_t, cost| {
+      alpha[i][t] = log_add(alpha[i][t], alpha[j][from_t] + cost);
+    });
+  }
+  let total = alpha.iter().fold(f64::INFINITY, |total, alpha| log_add(total, alpha[len_seq - 1]));
+
+  /* backward pass, only the last four positions of beta are kept */
+  let mut beta = [[f64::INFINITY; NUM_STATE]; 4];
+  beta[(len_seq - 1) % 4] = [0.0; NUM_STATE];
+  let mut posteriors = Posteriors {
+    bases: BasePosteriors {
+      forward: vec![0.0; len_seq],
+      reverse: vec![0.0; len_seq],
+    },
+    start: vec![0.0; len_seq],
+    stop: vec![0.0; len_seq],
+    reverse_start: vec![0.0; len_seq],
+    reverse_stop: vec![0.0; len_seq],
+  };
+  let posterior = |alpha: f64, beta: f64| (total - alpha - beta).exp().min(1.0);
+  for t in (0..len_seq).rev() {
+    let beta_t = beta[t % 4];
+    for i in 0..NUM_STATE {
+      if alpha[i][t] == f64::INFINITY || beta_t[i] == f64::INFINITY {
+        continue;
+      }
+      let p = posterior(alpha[i][t], beta_t[i]);
+      match i {
+        S_STATE => posteriors.start[t] = p,
+        E_STATE => posteriors.stop[t] = p,
+        S_STATE_1 => posteriors.reverse_stop[t] = p,
+        E_STATE_1 => posteriors.reverse_start[t] = p,
+        _ => {}
+      }
+      if is_forward_gene_state(i) {
+        posteriors.bases.forward[t] += p;
+      } else if i != R_STATE {
+        posteriors.bases.reverse[t] += p;
+      }
+    }
+    if t == 2 && lattice.live(E_STATE, 2) && alpha[E_STATE][2] < f64::INFINITY {
+      /* paths starting with a stop codon do not visit the first two positions */
+      let p = posterior(alpha[E_STATE][2], beta_t[E_STATE]);
+      posteriors.bases.forward[0] += p;
+      posteriors.bases.forward[1] += p;
+    }
+    if t == 0 {
+      break;
+    }
+    if t >= 3 {
+      beta[(t - 3) % 4] = [f64::INFINITY; NUM_STATE];
+    }
+    lattice.edges(t, |i, j, from_t, cost| {
+      if beta_t[i] == f64::INFINITY {
+        return;
+      }
+      let slot = &mut beta[from_t % 4][j];
+      *slot = log_add(*slot, beta_t[i] + cost);
+      if from_t + 1 < t && alpha[j][from_t] < f64::INFINITY {
+        /* the codon states skip the positions between from_t and t */
+        let p = posterior(alpha[j][from_t] + cost, beta_t[i]);
+        let bases = if is_forward_gene_state(i) {
+          &mut posteriors.bases.forward
+        } else {
+          &mut posteriors.bases.reverse
+        };
+        for base in &mut bases[from_t + 1..t] {
+          *base += p;
+        }
+      }
+    });
+  }
+  for base in posteriors.bases.forward.iter_mut().chain(posteriors.bases.reverse.iter_mut()) {
+    *base = base.min(1.0);
+  }
+  posteriors
+}
+
+impl<'a> Lattice<'a> {
+  /**
+   * Checks whether the state i at position t is part of the lattice, i.e. whether the viterbi recursion could reach
+   * it.
+   */
+  fn live(&self, i: usize, t: usize) -> bool {
+    self.viterbi_alpha[i][t] < f64::INFINITY
+  }
+
+  fn codon(&self, a: usize, b: usize, c: usize) -> usize {
+    codon_index(self.nts[a] as usize, self.nts[b] as usize, self.nts[c] as usize)
+  }
+
+  /**
+   * Helper method to replay the positions of the last insertion which were used by the viterbi recursion to avoid
+   * stop codons when going from an insert state back to a match state.
+   */
+  fn blocked_inserts(&self) -> Vec<u16> {
+    let len_seq = self.nts.len();
+    let code = self.config.genetic_code();
+    let mut blocked = vec![0; len_seq];
+    let mut temp_i = [0; 6];
+    let mut temp_i_1 = [0; 6];
+    for (t, blocked) in blocked.iter_mut().enumerate().skip(1) {
+      for k in 0..6 {
+        let temp = temp_i[(k + 5) % 6];
+        let stop_codon = t < 2
+          || ((k == 1 || k == 4) && t < len_seq - 1 && code.is_stop(self.codon(temp, t, t + 1)))
+          || ((k == 2 || k == 5) && temp as isize - 1 > 0 && code.is_stop(self.codon(temp - 1, temp, t)));
+        let temp = temp_i_1[(k + 5) % 6];
+        let reverse_stop_codon = t < 2
+          || t == len_seq - 1
+          || ((k == 1 || k == 4) && code.is_reverse_stop(self.codon(temp, t, t + 1)))
+          || ((k == 2 || k == 5)
+            && temp as isize - 1 > 0
+            && code.is_reverse_stop(self.codon(temp - 1, temp, t)));
+        *blocked |= (stop_codon as u16) << k | (reverse_stop_codon as u16) << (6 + k);
+      }
+      for k in 0..6 {
+        if self.viterbi_path[I1_STATE + k][t] == (M1_STATE + k) as i8 {
+          temp_i[k] = t - 1;
+        }
+        if self.viterbi_path[I1_STATE_1 + k][t] == (M1_STATE_1 + k) as i8 {
+          temp_i_1[k] = t - 1;
+        }
+      }
+    }
+    blocked
+  }
+
+  /**
+   * Calls f(i, j, from_t, cost) for every edge into a state i at position t (t > 0) from a state j at position from_t,
+   * cost is the -log probability of the transition (including the emission of state i).
+   */
+  fn edges<F: FnMut(usize, usize, usize, f64)>(&self, t: usize, mut f: F) {
+    let hmm = self.hmm;
+    let tr = &hmm.tr;
+    let code = self.config.genetic_code();
+    let log95 = 0.95_f64.ln();
+    let column = Column::new(hmm, self.nts, t, self.error_prob);
+    let (from, to) = (column.from, column.to);
+
+    /* M state */
+    if self.live(M1_STATE, t) {
+      f(M1_STATE, S_STATE, t - 1, -column.e_m[0]);
+    }
+    self.match_edges(t, M1_STATE, I1_STATE, &column.e_m, column.tr_md, self.blocked_inserts[t], false, &mut f);
+
+    /* I state */
+    self.insert_edges(t, M1_STATE, I1_STATE, &column, &mut f);
+
+    /* M' state */
+    let after_stop = t >= 3 && code.is_reverse_stop(self.codon(t - 3, t - 2, t - 1));
+    if after_stop {
+      /* from Start' state since this is actually a stop codon on the minus strand */
+      for i in [M1_STATE_1, M4_STATE_1] {
+        if self.live(i, t) {
+          f(i, S_STATE_1, t - 1, -column.e_m_1[i - M1_STATE_1]);
+        }
+      }
+    }
+    let blocked = self.blocked_inserts[t] >> 6;
+    self.match_edges(t, M1_STATE_1, I1_STATE_1, &column.e_m_1, column.tr_md, blocked, after_stop, &mut f);
+
+    /* I' state */
+    let from_match = t > 4
+      && (3..=5).all(|k| self.viterbi_path[S_STATE_1][t - k] != R_STATE as i8);
+    if from_match {
+      self.insert_edges(t, M1_STATE_1, I1_STATE_1, &column, &mut f);
+    } else {
+      for i in I1_STATE_1..=I6_STATE_1 {
+        if self.live(i, t) {
+          f(i, i, t - 1, -tr[TR_II] - hmm.tr_i_i[from][to]);
+        }
+      }
+    }
+
+    /* Non_coding state */
+    if self.live(R_STATE, t) {
+      f(R_STATE, R_STATE, t - 1, -hmm.tr_r_r[from][to] - tr[TR_RR] - log95);
+      f(R_STATE, E_STATE, t - 1, -tr[TR_ER] - log95);
+      f(R_STATE, E_STATE_1, t - 1, -tr[TR_ER] - log95);
+    }
+
+    if t == 2 && self.live(S_STATE_1, 2) {
+      /* stop codon on the minus strand at the start of the sequence */
+      let s = self.nts;
+      let probs = [0.53_f64.ln(), 0.16_f64.ln(), 0.30_f64.ln()];
+      f(S_STATE_1, S_STATE, 0, -stop_codon_prob(complement_nt(s[2]), complement_nt(s[1]), complement_nt(s[0]), probs));
+    }
+    if t < 3 {
+      return;
+    }
+
+    /* codon states, the codon starts at c and the state is entered from position c - 1 */
+    let c = t - 2;
+    let s = self.nts;
+    let stop_probs = [0.54_f64.ln(), 0.16_f64.ln(), 0.30_f64.ln()];
+    let start_probs = [0.83_f64.ln(), 0.10_f64.ln(), 0.07_f64.ln()];
+    if self.live(E_STATE, t) {
+      let cost = -tr[TR_GE]
+        - stop_codon_prob(s[c], s[c + 1], s[c + 2], stop_probs)
+        - stop_adjustment(hmm, s, c).ln();
+      f(E_STATE, M6_STATE, c - 1, cost);
+      f(E_STATE, M3_STATE, c - 1, cost);
+    }
+    if self.live(S_STATE_1, t) {
+      let cost = -stop_codon_prob(complement_nt(s[c + 2]), complement_nt(s[c + 1]), complement_nt(s[c]), stop_probs)
+        - reverse_stop_adjustment(hmm, s, c).ln();
+      f(S_STATE_1, R_STATE, c - 1, cost - tr[TR_RS]);
+      f(S_STATE_1, E_STATE_1, c - 1, cost - tr[TR_ES]);
+      f(S_STATE_1, E_STATE, c - 1, cost - tr[TR_ES1]);
+    }
+    if self.live(S_STATE, t) {
+      let cost = -start_codon_prob(s[c], s[c + 1], s[c + 2], start_probs) - start_adjustment(hmm, s, c).ln();
+      f(S_STATE, R_STATE, c - 1, cost - tr[TR_RS]);
+      f(S_STATE, E_STATE, c - 1, cost - tr[TR_ES]);
+      f(S_STATE, E_STATE_1, c - 1, cost - tr[TR_ES1]);
+    }
+    if self.live(E_STATE_1, t) {
+      let cost = -tr[TR_GE]
+        - start_codon_prob(complement_nt(s[c + 2]), complement_nt(s[c + 1]), complement_nt(s[c]), start_probs)
+        - reverse_start_adjustment(hmm, s, c).ln();
+      f(E_STATE_1, M6_STATE_1, c - 1, cost);
+    }
+  }
+
+  /**
+   * Helper method for the edges into the match states m1..m1 + 5 from the previous match state, from a deletion
+   * (sequence reads only) and from an insert state (unless blocked). After a stop codon on the minus strand, the
+   * first match state of a codon can only be entered from the Start' state (see edges).
+   */
+  #[allow(clippy::too_many_arguments)]
+  fn match_edges<F: FnMut(usize, usize, usize, f64)>(
+    &self,
+    t: usize,
+    m1: usize,
+    i1: usize,
+    e_m: &[f64; 6],
+    tr_md: f64,
+    blocked: u16,
+    after_stop: bool,
+    f: &mut F,
+  ) {
+    let tr = &self.hmm.tr;
+    let log25 = 0.25_f64.ln();
+    for k in 0..6 {
+      let i = m1 + k;
+      if !self.live(i, t) || (after_stop && (k == 0 || k == 3)) {
+        continue;
+      }
+      /* from M state */
+      if k == 0 {
+        f(i, m1 + 5, t - 1, -tr[TR_GG] - tr[TR_MM] - e_m[0]);
+      } else {
+        f(i, i - 1, t - 1, -tr[TR_MM] - e_m[k]);
+      }
+      /* from D state */
+      if !self.config.wholegenome() {
+        let last = if k == 0 { 4 } else { 5 };
+        for l in 0..=last {
+          let num_d = if l >= k {
+            k as isize - l as isize + 6
+          } else if l + 1 < k {
+            k as isize - l as isize
+          } else {
+            -10
+          };
+          if num_d > 0 {
+            let cost = -tr_md - e_m[k] - log25 * (num_d as f64 - 1.0) - tr[TR_DD] * (num_d as f64 - 2.0)
+              - tr[TR_DM];
+            f(i, m1 + l, t - 1, cost);
+          }
+        }
+      }
+      /* from I state */
+      if blocked >> k & 1 == 0 {
+        let j = if k == 0 { i1 + 5 } else { i1 + k - 1 };
+        f(i, j, t - 1, -tr[TR_IM] - log25);
+      }
+    }
+  }
+
+  /**
+   * Helper method for the edges into the insert states i1..i1 + 5 from the same insert state and from the
+   * corresponding match state.
+   */
+  fn insert_edges<F: FnMut(usize, usize, usize, f64)>(
+    &self,
+    t: usize,
+    m1: usize,
+    i1: usize,
+    column: &Column,
+    f: &mut F,
+  ) {
+    let hmm = self.hmm;
+    let (from, to) = (column.from, column.to);
+    for k in 0..6 {
+      let i = i1 + k;
+      if !self.live(i, t) {
+        continue;
+      }
+      f(i, i, t - 1, -hmm.tr[TR_II] - hmm.tr_i_i[from][to]);
+      let mut cost = -column.tr_mi - hmm.tr_m_i[from][to];
+      if k == 5 {
+        cost -= hmm.tr[TR_GG];
+      }
+      f(i, m1 + k, t - 1, cost);
+    }
+  }
+}
+
+/**
+ * Helper method to check whether the state belongs to a gene on the forward strand.
+ */
+fn is_forward_gene_state(i: usize) -> bool {
+  i == S_STATE || i == E_STATE || (M1_STATE..=M6_STATE).contains(&i) || (I1_STATE..=I6_STATE).contains(&i)
+}
+
+/**
+ * Adds two probabilities stored as -log values.
+ */
+fn log_add(a: f64, b: f64) -> f64 {
+  if a == f64::INFINITY {
+    return b;
+  }
+  if b == f64::INFINITY {
+    return a;
+  }
+  let (low, high) = if a < b { (a, b) } else { (b, a) };
+  low - (low - high).exp().ln_1p()
+}
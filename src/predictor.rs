@@ -0,0 +1,181 @@
+use super::config::{CgBin, PredictionConfig};
+use super::dna_helpers::{get_cg_bin, get_cg_content, load_cg_bin};
+use super::error::Error;
+use super::genetic_code::GeneticCode;
+use super::input::Record;
+use super::train::{load_models, Train, HMM};
+use super::viterbi::{viterbi, Prediction};
+use super::windows::{fold_circular, sequence_windows, stitch_predictions};
+use rayon::prelude::*;
+
+/*
+ * predictor.rs
+ * ============
+ * This file contains the GenePredictor struct, which bundles a loaded Train and HMM and exposes a typed
+ * interface for predicting genes on a single sequence.
+ */
+
+#[derive(Clone, Debug)]
+pub struct GenePredictor {
+  train: Train,
+  hmm: HMM,
+  config: PredictionConfig,
+}
+
+impl GenePredictor {
+  /**
+   * Builds a new GenePredictor from an already loaded Train and HMM, the parameter wholegenome specifies
+   * whether the sequences passed to predict are full genome sequences (true) or sequence reads (false).
+   * The bacterial genetic code (translation table 11) is used, see with_genetic_code and with_config.
+   */
+  pub fn new(train: Train, hmm: HMM, wholegenome: bool) -> GenePredictor {
+    GenePredictor {
+      train,
+      hmm,
+      config: PredictionConfig::new(wholegenome),
+    }
+  }
+
+  /**
+   * Sets the genetic code used for recognizing start and stop codons and translating the predicted genes.
+   */
+  pub fn with_genetic_code(mut self, genetic_code: GeneticCode) -> GenePredictor {
+    self.config = self.config.with_genetic_code(genetic_code);
+    self
+  }
+
+  /**
+   * Replaces the PredictionConfig of the predictor (including the wholegenome setting passed to new).
+   */
+  pub fn with_config(mut self, config: PredictionConfig) -> GenePredictor {
+    self.config = config;
+    self
+  }
+
+  /**
+   * Builds a new GenePredictor using the global model files found on the default search path and the specified
+   * HMM, which is either a path to a train file or the name of a model in the model directory (e.g. illumina_5).
+   */
+  pub fn from_file(train_file: &str, wholegenome: bool) -> Result<GenePredictor, Error> {
+    let (train, hmm) = load_models(train_file, None)?;
+    Ok(GenePredictor::new(train, hmm, wholegenome))
+  }
+
+  /**
+   * Runs the gene caller on a single sequence and returns the resulting Prediction, the id is used as the
+   * head of the Prediction.
+   */
+  pub fn predict(&self, id: &str, seq: &str) -> Prediction {
+    self.predict_with_quality(id, seq, None)
+  }
+
+  /**
+   * Runs the gene caller on a single sequence with the Phred quality score of every base, the match emissions
+   * and indel transitions are weighted by the quality of each base (see viterbi::viterbi). The sequence is
+   * predicted as a circular sequence when the config says so (see predict_circular).
+   */
+  pub fn predict_with_quality(&self, id: &str, seq: &str, quality: Option<&[u8]>) -> Prediction {
+    if self.config.circular() {
+      return self.predict_circular(id, seq, quality);
+    }
+    let (hmm, cg) = self.cg_hmm(seq, None);
+    self.predict_sequence(&hmm, cg, id, seq, quality)
+  }
+
+  /**
+   * Runs the gene caller on a circular sequence (e.g. a complete chromosome or plasmid): the first bases of the
+   * sequence (the window overlap of the config, or the whole sequence when it is shorter) are appended to its end,
+   * so genes spanning the origin are predicted across the junction. These genes end past the end of the sequence,
+   * at their end position plus the length of the sequence.
+   */
+  pub fn predict_circular(&self, id: &str, seq: &str, quality: Option<&[u8]>) -> Prediction {
+    let (hmm, cg) = self.cg_hmm(seq, None);
+    self.predict_wrapped(&hmm, cg, id, seq, quality)
+  }
+
+  /**
+   * Runs the gene caller on an input record, using its quality scores when present. Records marked as circular in
+   * their header are predicted as circular sequences, the CG content of the record is used for choosing the CG bin
+   * when the config says so (see CgBin::Record).
+   */
+  pub fn predict_record(&self, record: &Record) -> Prediction {
+    let (hmm, cg) = self.cg_hmm(&record.seq, record.cg_content);
+    let quality = record.quality.as_deref();
+    match record.circular || self.config.circular() {
+      true => self.predict_wrapped(&hmm, cg, &record.id, &record.seq, quality),
+      false => self.predict_sequence(&hmm, cg, &record.id, &record.seq, quality),
+    }
+  }
+
+  /**
+   * Helper method to load the HMM for the CG bin of a sequence, chosen as specified by the config (see CgBin).
+   */
+  fn cg_hmm(&self, seq: &str, cg_content: Option<f64>) -> (HMM, usize) {
+    let cg = match (self.config.cg_bin(), cg_content) {
+      (CgBin::Fixed(bin), _) => bin,
+      (CgBin::Record, Some(cg_content)) => get_cg_bin(cg_content),
+      _ => get_cg_bin(get_cg_content(seq)),
+    };
+    let mut hmm = self.hmm.clone();
+    load_cg_bin(&mut hmm, &self.train, cg);
+    (hmm, cg)
+  }
+
+  /**
+   * Helper method to predict a circular sequence across its origin, see predict_circular.
+   */
+  fn predict_wrapped(&self, hmm: &HMM, cg: usize, id: &str, seq: &str, quality: Option<&[u8]>) -> Prediction {
+    // the copy is sliced by byte, valid sequences only contain ASCII characters
+    if !seq.is_ascii() {
+      return self.predict_sequence(hmm, cg, id, seq, quality);
+    }
+    let wrap = self.config.window_overlap().min(seq.len());
+    let circular_seq = [seq, &seq[..wrap]].concat();
+    let circular_quality = quality.map(|quality| [quality, &quality[..wrap]].concat());
+    let prediction = self.predict_sequence(hmm, cg, id, &circular_seq, circular_quality.as_deref());
+    fold_circular(prediction, seq.len(), wrap)
+  }
+
+  /**
+   * Helper method to run viterbi on a sequence with an HMM for its CG content, sequences longer than the window
+   * length of the config are predicted in parallel windows.
+   */
+  fn predict_sequence(&self, hmm: &HMM, cg: usize, id: &str, seq: &str, quality: Option<&[u8]>) -> Prediction {
+    match self.config.window_length() {
+      // the windows are sliced by byte, valid sequences only contain ASCII characters
+      Some(window_length) if seq.len() > window_length && seq.is_ascii() => {
+        let windows = sequence_windows(seq.len(), window_length, self.config.window_overlap());
+        let predictions = windows
+          .par_iter()
+          .map(|window| {
+            let quality = quality.map(|quality| &quality[window.start..window.end]);
+            let window_seq = &seq[window.start..window.end];
+            viterbi(hmm, &self.train, window_seq, cg, id, quality, &self.config)
+          })
+          .collect();
+        stitch_predictions(id, &windows, predictions)
+      }
+      _ => viterbi(hmm, &self.train, seq, cg, id, quality, &self.config),
+    }
+  }
+
+  pub fn train(&self) -> &Train {
+    &self.train
+  }
+
+  pub fn hmm(&self) -> &HMM {
+    &self.hmm
+  }
+
+  pub fn config(&self) -> &PredictionConfig {
+    &self.config
+  }
+
+  pub fn wholegenome(&self) -> bool {
+    self.config.wholegenome()
+  }
+
+  pub fn genetic_code(&self) -> &GeneticCode {
+    self.config.genetic_code()
+  }
+}
@@ -0,0 +1,441 @@
+use super::annotation::CdsFeature;
+use super::error::Error;
+use super::training::find_sequence;
+use rand::distributions::WeightedIndex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+use std::io::{self, Write};
+
+/*
+ * simulation.rs
+ * =============
+ * This file contains the read simulator used by the simulate subcommand of the fgsrs binary, which fragments an
+ * annotated genome into reads with the sequencing errors of a platform (see ErrorProfile) and keeps track of the
+ * coding regions of every read. The ground truth is written as GFF3, so the predictions on the simulated reads can
+ * be scored with the eval subcommand (see evaluation.rs).
+ */
+
+const BASES: [u8; 4] = [b'A', b'C', b'G', b'T'];
+
+/**
+ * The sequencing platforms of the error models of FragGeneScan.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+  /* no sequencing errors, like the complete model */
+  ErrorFree,
+  /* mostly substitutions, some insertions and deletions */
+  Sanger,
+  /* pyrosequencing, mostly insertions and deletions in homopolymer runs */
+  Roche454,
+  /* almost only substitutions */
+  Illumina,
+}
+
+impl Platform {
+  /**
+   * Parses a platform by the name of its models (complete, sanger, 454 or illumina).
+   */
+  pub fn from_name(name: &str) -> Result<Platform, Error> {
+    match name {
+      "complete" => Ok(Platform::ErrorFree),
+      "sanger" => Ok(Platform::Sanger),
+      "454" => Ok(Platform::Roche454),
+      "illumina" => Ok(Platform::Illumina),
+      _ => Err(Error::InvalidArgument(format!(
+        "Unknown platform {}, expected complete, sanger, 454 or illumina.",
+        name
+      ))),
+    }
+  }
+}
+
+/**
+ * The sequencing errors of a simulated read, the error rate is the expected number of errors per base.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ErrorProfile {
+  pub platform: Platform,
+  pub error_rate: f64,
+}
+
+impl ErrorProfile {
+  /**
+   * Gets the error profile matching the name of a FragGeneScan model, i.e. complete (no errors) or the platform
+   * (sanger, 454 or illumina) followed by the error rate in tenths of a percent, e.g. 454_10 (1% errors) or
+   * illumina_1 (0.1% errors).
+   */
+  pub fn from_name(name: &str) -> Result<ErrorProfile, Error> {
+    let invalid = || {
+      Error::InvalidArgument(format!(
+        "Unknown error model {}, expected complete or sanger, 454 or illumina followed by the error rate in \
+         tenths of a percent (e.g. 454_10).",
+        name
+      ))
+    };
+    if name == "complete" {
+      return Ok(ErrorProfile {
+        platform: Platform::ErrorFree,
+        error_rate: 0.0,
+      });
+    }
+    let (platform, rate) = name.rsplit_once('_').ok_or_else(invalid)?;
+    let platform = match platform {
+      "sanger" => Platform::Sanger,
+      "454" => Platform::Roche454,
+      "illumina" => Platform::Illumina,
+      _ => return Err(invalid()),
+    };
+    match rate.parse::<f64>() {
+      Ok(rate) if (0.0..=500.0).contains(&rate) => Ok(ErrorProfile {
+        platform,
+        error_rate: rate / 1000.0,
+      }),
+      _ => Err(invalid()),
+    }
+  }
+
+  /**
+   * Helper method to get the probability of a substitution, insertion and deletion per base.
+   */
+  fn rates(&self) -> (f64, f64, f64) {
+    let (substitutions, insertions, deletions) = match self.platform {
+      Platform::ErrorFree => (0.0, 0.0, 0.0),
+      Platform::Sanger => (0.5, 0.25, 0.25),
+      Platform::Roche454 => (0.1, 0.45, 0.45),
+      Platform::Illumina => (0.9, 0.05, 0.05),
+    };
+    (
+      substitutions * self.error_rate,
+      insertions * self.error_rate,
+      deletions * self.error_rate,
+    )
+  }
+}
+
+/**
+ * The settings of a simulation, the read lengths follow a normal distribution (a fixed length when the standard
+ * deviation is 0). Reads are never longer than the sequence they are taken from.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulationConfig {
+  profile: ErrorProfile,
+  reads: usize,
+  mean_length: usize,
+  length_sd: f64,
+  seed: u64,
+}
+
+impl SimulationConfig {
+  /**
+   * Builds the default configuration: 10000 reads of 100 bp with the specified sequencing errors.
+   */
+  pub fn new(profile: ErrorProfile) -> SimulationConfig {
+    SimulationConfig {
+      profile,
+      reads: 10000,
+      mean_length: 100,
+      length_sd: 0.0,
+      seed: 1,
+    }
+  }
+
+  /**
+   * Sets the number of simulated reads.
+   */
+  pub fn with_reads(mut self, reads: usize) -> SimulationConfig {
+    self.reads = reads;
+    self
+  }
+
+  /**
+   * Sets the mean and standard deviation of the read lengths (before the sequencing errors).
+   */
+  pub fn with_length(mut self, mean_length: usize, length_sd: f64) -> Result<SimulationConfig, Error> {
+    if mean_length == 0 || !(length_sd >= 0.0 && length_sd.is_finite()) {
+      return Err(Error::InvalidArgument(String::from(
+        "The read length should be positive and its standard deviation should not be negative.",
+      )));
+    }
+    self.mean_length = mean_length;
+    self.length_sd = length_sd;
+    Ok(self)
+  }
+
+  /**
+   * Sets the seed of the random number generator, the same seed gives the same reads.
+   */
+  pub fn with_seed(mut self, seed: u64) -> SimulationConfig {
+    self.seed = seed;
+    self
+  }
+}
+
+/**
+ * A coding region of a simulated read, the feature is in the coordinates of the read (after the sequencing errors)
+ * and on the strand of the read. partial_left and partial_right tell whether the gene runs off the read at its left
+ * or right end, the phase is the number of bases before the first complete codon (ignoring sequencing errors).
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrueGene {
+  pub feature: CdsFeature,
+  pub phase: usize,
+  pub partial_left: bool,
+  pub partial_right: bool,
+}
+
+/**
+ * A simulated read, the source is the 1-based position and strand of the read on the genome, e.g.
+ * NC_000913.3:101-200:-.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulatedRead {
+  pub id: String,
+  pub source: String,
+  pub seq: String,
+  pub errors: usize,
+  pub genes: Vec<TrueGene>,
+}
+
+/**
+ * An iterator over the simulated reads of a genome, the reads are named read_1, read_2, ...
+ */
+pub struct ReadSimulator<'a> {
+  genome: &'a [(String, String)],
+  /* the annotated genes of every sequence, sorted by their start */
+  genes: Vec<Vec<CdsFeature>>,
+  longest_gene: Vec<usize>,
+  config: SimulationConfig,
+  sequences: WeightedIndex<usize>,
+  lengths: Option<Normal<f64>>,
+  rng: StdRng,
+  count: usize,
+}
+
+impl<'a> ReadSimulator<'a> {
+  /**
+   * Builds a simulator for the sequences of a genome (as (id, sequence) pairs) and their CDS annotation, the
+   * sequences are sampled proportionally to their length.
+   */
+  pub fn new(
+    genome: &'a [(String, String)],
+    features: &[CdsFeature],
+    config: SimulationConfig,
+  ) -> Result<ReadSimulator<'a>, Error> {
+    let sequences = WeightedIndex::new(genome.iter().map(|(_, seq)| seq.len()))
+      .map_err(|_| Error::InvalidArgument(String::from("The genome does not contain any sequence to simulate.")))?;
+    let lengths = match config.length_sd > 0.0 {
+      true => Some(Normal::new(config.mean_length as f64, config.length_sd).unwrap()),
+      false => None,
+    };
+
+    let mut genes = vec![Vec::new(); genome.len()];
+    for feature in features {
+      if let Some(index) = find_sequence(genome, &feature.seqid) {
+        genes[index].push(feature.clone());
+      }
+    }
+    for sequence_genes in genes.iter_mut() {
+      sequence_genes.sort_by_key(|gene| gene.start);
+    }
+    let longest_gene = genes
+      .iter()
+      .map(|genes| genes.iter().map(|gene| gene.end + 1 - gene.start).max().unwrap_or(0))
+      .collect();
+
+    Ok(ReadSimulator {
+      genome,
+      genes,
+      longest_gene,
+      rng: StdRng::seed_from_u64(config.seed),
+      config,
+      sequences,
+      lengths,
+      count: 0,
+    })
+  }
+
+  /**
+   * Helper method to simulate the next read.
+   */
+  fn simulate(&mut self) -> SimulatedRead {
+    let index = self.sequences.sample(&mut self.rng);
+    let (seqid, seq) = &self.genome[index];
+    let length = match &self.lengths {
+      Some(lengths) => lengths.sample(&mut self.rng).round().max(1.0) as usize,
+      None => self.config.mean_length,
+    }
+    .min(seq.len());
+    let start = self.rng.gen_range(0..=seq.len() - length);
+    let end = start + length;
+    let forward = self.rng.gen_bool(0.5);
+
+    /* the genes overlapping the fragment, in the orientation of the read with 0-based half-open positions */
+    let sequence_genes = &self.genes[index];
+    let first = sequence_genes.partition_point(|gene| gene.start + self.longest_gene[index] <= start + 1);
+    let last = sequence_genes.partition_point(|gene| gene.start <= end);
+    let genes: Vec<(usize, usize, bool, usize, bool, bool)> = sequence_genes[first..last]
+      .iter()
+      .filter(|gene| gene.end > start && gene.start <= end)
+      .map(|gene| {
+        let (from, to) = (gene.start - 1, gene.end);
+        let (left, right) = (from.max(start) - start, to.min(end) - start);
+        /* bases of the gene missing at its 5' end */
+        let clipped = match gene.forward {
+          true => start.saturating_sub(from),
+          false => to.saturating_sub(end),
+        };
+        let phase = (3 - clipped % 3) % 3;
+        match forward {
+          true => (left, right, gene.forward, phase, from < start, to > end),
+          false => (length - right, length - left, !gene.forward, phase, to > end, from < start),
+        }
+      })
+      .collect();
+
+    let fragment: Vec<u8> = match forward {
+      true => seq.as_bytes()[start..end].to_ascii_uppercase(),
+      false => seq.as_bytes()[start..end]
+        .iter()
+        .rev()
+        .map(|&base| complement(base.to_ascii_uppercase()))
+        .collect(),
+    };
+    let (read, positions, errors) = self.inject_errors(&fragment);
+
+    self.count += 1;
+    let id = format!("read_{}", self.count);
+    let mut genes: Vec<TrueGene> = genes
+      .into_iter()
+      .filter(|&(left, right, ..)| positions[right] > positions[left])
+      .map(|(left, right, gene_forward, phase, partial_left, partial_right)| TrueGene {
+        feature: CdsFeature {
+          seqid: id.clone(),
+          start: positions[left] + 1,
+          end: positions[right],
+          forward: gene_forward,
+        },
+        phase,
+        partial_left,
+        partial_right,
+      })
+      .collect();
+    genes.sort_by_key(|gene| gene.feature.start);
+
+    SimulatedRead {
+      source: format!("{}:{}-{}:{}", seqid, start + 1, end, if forward { '+' } else { '-' }),
+      id,
+      seq: String::from_utf8(read).unwrap(),
+      errors,
+      genes,
+    }
+  }
+
+  /**
+   * Helper method to add the sequencing errors to a fragment. Besides the read and its number of errors, this
+   * returns the position in the read of every base of the fragment (and of the end of the fragment), deleted bases
+   * get the position of the next base.
+   */
+  fn inject_errors(&mut self, fragment: &[u8]) -> (Vec<u8>, Vec<usize>, usize) {
+    let (substitution, insertion, deletion) = self.config.profile.rates();
+    let mut read = Vec::with_capacity(fragment.len() + fragment.len() / 10);
+    let mut positions = Vec::with_capacity(fragment.len() + 1);
+    let mut errors = 0;
+
+    let mut i = 0;
+    while i < fragment.len() {
+      /* insertions and deletions of 454 reads lengthen or shorten a homopolymer run, more likely for longer runs */
+      let run = match self.config.profile.platform {
+        Platform::Roche454 => fragment[i..].iter().take_while(|&&base| base == fragment[i]).count(),
+        _ => 1,
+      };
+      for &base in &fragment[i..i + run] {
+        positions.push(read.len());
+        read.push(match self.rng.gen_bool(substitution) {
+          true => {
+            errors += 1;
+            substitute(base, &mut self.rng)
+          }
+          false => base,
+        });
+      }
+
+      let indel: f64 = self.rng.gen();
+      let length = run as f64;
+      if indel < deletion * length {
+        read.pop();
+        errors += 1;
+      } else if indel < (deletion + insertion) * length {
+        read.push(match self.config.profile.platform {
+          Platform::Roche454 => fragment[i],
+          _ => BASES[self.rng.gen_range(0..4)],
+        });
+        errors += 1;
+      }
+      i += run;
+    }
+    positions.push(read.len());
+    (read, positions, errors)
+  }
+}
+
+impl<'a> Iterator for ReadSimulator<'a> {
+  type Item = SimulatedRead;
+
+  fn next(&mut self) -> Option<SimulatedRead> {
+    match self.count < self.config.reads {
+      true => Some(self.simulate()),
+      false => None,
+    }
+  }
+}
+
+/**
+ * This method will write a simulated read to the FASTA output and its coding regions to the GFF3 output, with the
+ * partial flags in the convention of the GFF3 output of fgsrs (see output.rs).
+ */
+pub fn write_simulated_read<W: Write, V: Write>(fasta: &mut W, gff: &mut V, read: &SimulatedRead) -> io::Result<()> {
+  writeln!(fasta, ">{} source={} errors={}", read.id, read.source, read.errors)?;
+  writeln!(fasta, "{}", read.seq)?;
+  for (i, gene) in read.genes.iter().enumerate() {
+    let partial = match (gene.partial_left, gene.partial_right) {
+      (false, false) => String::new(),
+      (left, right) => format!(";partial={}{}", left as u8, right as u8),
+    };
+    writeln!(
+      gff,
+      "{}\tsimulate\tCDS\t{}\t{}\t.\t{}\t{}\tID={}_gene{}{}",
+      read.id,
+      gene.feature.start,
+      gene.feature.end,
+      if gene.feature.forward { '+' } else { '-' },
+      gene.phase,
+      read.id,
+      i + 1,
+      partial
+    )?;
+  }
+  Ok(())
+}
+
+/**
+ * Helper method to get the complement of a base, other characters are kept.
+ */
+fn complement(base: u8) -> u8 {
+  match base {
+    b'A' => b'T',
+    b'C' => b'G',
+    b'G' => b'C',
+    b'T' => b'A',
+    other => other,
+  }
+}
+
+/**
+ * Helper method to replace a base with one of the three other bases.
+ */
+fn substitute<R: Rng>(base: u8, rng: &mut R) -> u8 {
+  let choices: Vec<u8> = BASES.iter().copied().filter(|&other| other != base).collect();
+  choices[rng.gen_range(0..choices.len())]
+}
@@ -1,26 +1,194 @@
+use super::compiled_model::{is_compiled_model, read_compiled_model};
 use super::dna_helpers::{nt2int, tr2int};
+#[cfg(feature = "embedded-models")]
+use super::embedded;
+use super::error::Error;
 use super::helpers::get_executable_path;
+use std::env;
+use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "embedded-models")]
+use std::sync::OnceLock;
 use whiteread::parse_string;
 
-/**
+/*
  * train.rs
  * ========
  * This file contains code responsible for reading training files and building the respective Train and HMM structs
  * from those files.
  */
 
-const MFILENAME: &'static str = "train/gene";
-const M1FILENAME: &'static str = "train/rgene";
-const NFILENAME: &'static str = "train/noncoding";
-const SFILENAME: &'static str = "train/start";
-const PFILENAME: &'static str = "train/stop";
-const S1FILENAME: &'static str = "train/stop1";
-const P1FILENAME: &'static str = "train/start1";
-const DFILENAME: &'static str = "train/pwm";
+const TRAIN_DIR: &str = "train";
+const SYSTEM_DATA_DIR: &str = "/usr/share/fgsrs";
+const MFILENAME: &str = "gene";
+const M1FILENAME: &str = "rgene";
+const NFILENAME: &str = "noncoding";
+const SFILENAME: &str = "start";
+const PFILENAME: &str = "stop";
+const S1FILENAME: &str = "stop1";
+const P1FILENAME: &str = "start1";
+const DFILENAME: &str = "pwm";
 
+/**
+ * The number of CG bins in the global training files, bin p holds the values for sequences with a CG content
+ * of (26 + p)%.
+ */
+pub const NUM_CG_BINS: usize = 44;
+
+/**
+ * The smallest probability written to training files, this prevents values from being rounded to zero.
+ */
+const MIN_WRITTEN_PROB: f64 = 0.000001;
+
+/**
+ * Environment variable which can be used to specify the directory containing the model files.
+ */
+pub const MODEL_DIR_ENV: &str = "FGSRS_MODEL_DIR";
+
+/**
+ * Error returned when a model directory or model file could not be found, it lists every path that was tried.
+ */
 #[derive(Clone, Debug)]
+pub struct ModelNotFound {
+  pub name: String,
+  pub tried: Vec<PathBuf>,
+}
+
+impl fmt::Display for ModelNotFound {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Unable to find {}, the following paths were tried:", self.name)?;
+    for path in &self.tried {
+      write!(f, "\n  {}", path.display())?;
+    }
+    Ok(())
+  }
+}
+
+/**
+ * This method will yield the directories which are searched for the model files, in order of priority:
+ *  1. the directory specified on the command line (if any)
+ *  2. the directory specified by the FGSRS_MODEL_DIR environment variable (if set)
+ *  3. the train/ folder stored in the same path as the executable
+ *  4. $XDG_DATA_HOME/fgsrs/train (or ~/.local/share/fgsrs/train when XDG_DATA_HOME is not set)
+ *  5. /usr/share/fgsrs/train
+ */
+pub fn model_search_path(model_dir: Option<&str>) -> Vec<PathBuf> {
+  let mut paths = Vec::new();
+  if let Some(dir) = model_dir {
+    paths.push(PathBuf::from(dir));
+  }
+  if let Some(dir) = env::var_os(MODEL_DIR_ENV) {
+    paths.push(PathBuf::from(dir));
+  }
+  paths.push(get_executable_path().join(TRAIN_DIR));
+  if let Some(dir) = env::var_os("XDG_DATA_HOME") {
+    paths.push(PathBuf::from(dir).join("fgsrs").join(TRAIN_DIR));
+  } else if let Some(home) = env::var_os("HOME") {
+    paths.push(PathBuf::from(home).join(".local/share/fgsrs").join(TRAIN_DIR));
+  }
+  paths.push(Path::new(SYSTEM_DATA_DIR).join(TRAIN_DIR));
+  paths
+}
+
+/**
+ * This method will yield the first directory of the search path which contains all global model files.
+ */
+pub fn find_model_dir(model_dir: Option<&str>) -> Result<PathBuf, ModelNotFound> {
+  let tried = model_search_path(model_dir);
+  match tried.iter().find(|dir| is_model_dir(dir)) {
+    Some(dir) => Ok(dir.clone()),
+    None => Err(ModelNotFound {
+      name: String::from("the model directory"),
+      tried,
+    }),
+  }
+}
+
+/**
+ * This method will resolve the -t argument to an HMM file, the argument is either a path to the file or the
+ * bare name of a model (e.g. illumina_5) stored in the model directory.
+ */
+pub fn find_hmm_file(train_file: &str, model_dir: &Path) -> Result<PathBuf, ModelNotFound> {
+  let tried = vec![PathBuf::from(train_file), model_dir.join(train_file)];
+  match tried.iter().find(|path| path.is_file()) {
+    Some(path) => Ok(path.clone()),
+    None => Err(ModelNotFound {
+      name: format!("the model {}", train_file),
+      tried,
+    }),
+  }
+}
+
+/**
+ * This method will load the global Train tables from the model directory (see find_model_dir) and the HMM specified
+ * by train_file (see find_hmm_file). When train_file is a compiled model (see compiled_model.rs), both are read from
+ * that file instead. When the embedded-models feature is enabled, the models compiled into the binary are used
+ * whenever no model directory or model file can be found on disk.
+ */
+pub fn load_models(train_file: &str, model_dir: Option<&str>) -> Result<(Train, HMM), Error> {
+  let path = Path::new(train_file);
+  if is_compiled_model(path) {
+    return read_compiled_model(path);
+  }
+  match find_model_dir(model_dir) {
+    Ok(dir) => {
+      let hmm = match find_hmm_file(train_file, &dir) {
+        Ok(path) => HMM::from_file(&path.to_string_lossy())?,
+        Err(e) => embedded_hmm(train_file).ok_or(e)?,
+      };
+      Ok((Train::from_dir(&dir)?, hmm))
+    }
+    Err(e) => {
+      let train = embedded_train().ok_or(e)?;
+      let hmm = if Path::new(train_file).is_file() {
+        HMM::from_file(train_file)?
+      } else {
+        embedded_hmm(train_file).ok_or_else(|| ModelNotFound {
+          name: format!("the model {}", train_file),
+          tried: vec![PathBuf::from(train_file)],
+        })?
+      };
+      Ok((train, hmm))
+    }
+  }
+}
+
+#[cfg(feature = "embedded-models")]
+fn embedded_train() -> Option<Train> {
+  Some(Train::embedded())
+}
+
+#[cfg(not(feature = "embedded-models"))]
+fn embedded_train() -> Option<Train> {
+  None
+}
+
+#[cfg(feature = "embedded-models")]
+fn embedded_hmm(name: &str) -> Option<HMM> {
+  embedded::model_file(name).map(|contents| {
+    HMM::from_reader(contents.as_bytes(), name).expect("The embedded model files are invalid.")
+  })
+}
+
+#[cfg(not(feature = "embedded-models"))]
+fn embedded_hmm(_name: &str) -> Option<HMM> {
+  None
+}
+
+/**
+ * Helper method to check whether a directory contains all global model files.
+ */
+fn is_model_dir(dir: &Path) -> bool {
+  [
+    MFILENAME, M1FILENAME, NFILENAME, SFILENAME, PFILENAME, S1FILENAME, P1FILENAME, DFILENAME,
+  ]
+  .iter()
+  .all(|name| dir.join(name).is_file())
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct HMM {
   pub initial_state: Vec<f64>,
 
@@ -44,7 +212,7 @@ pub struct HMM {
   pub e1_dist: Vec<f64>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Train {
   pub trans: Vec<Vec<Vec<Vec<f64>>>>,
   pub rtrans: Vec<Vec<Vec<Vec<f64>>>>,
@@ -63,119 +231,166 @@ pub struct Train {
 
 impl Train {
   /**
-   * This method will build a new Train struct from the train/ folder stored in the same path as the
-   * executable.
+   * This method will build a new Train struct from the first model directory found on the default search
+   * path (see model_search_path).
    */
-  pub fn from_file() -> Train {
-    let mut result = Train {
-      trans: vec![vec![vec![vec![0.0; 4]; 16]; 6]; 44],
-      rtrans: vec![vec![vec![vec![0.0; 4]; 16]; 6]; 44],
-
-      noncoding: vec![vec![vec![0.0; 4]; 4]; 44],
-      start: vec![vec![vec![0.0; 64]; 61]; 44],
-      stop: vec![vec![vec![0.0; 64]; 61]; 44],
-      start1: vec![vec![vec![0.0; 64]; 61]; 44],
-      stop1: vec![vec![vec![0.0; 64]; 61]; 44],
-
-      s_dist: vec![vec![0.0; 6]; 44],
-      e_dist: vec![vec![0.0; 6]; 44],
-      s1_dist: vec![vec![0.0; 6]; 44],
-      e1_dist: vec![vec![0.0; 6]; 44],
-    };
-    result.load_m_state();
-    result.load_m_1_state();
-    result.load_noncoding_state();
-    result.load_start_state();
-    result.load_stop_state();
-    result.load_start_1_state();
-    result.load_stop_1_state();
-    result.load_pwm_dist();
-    result
+  pub fn from_file() -> Result<Train, Error> {
+    Train::from_dir(&find_model_dir(None)?)
   }
+
   /**
-   * This method wil load the train/gene file into the trans field of our struct
+   * This method will build a new Train struct from the model files stored in the specified directory.
+   */
+  pub fn from_dir(dir: &Path) -> Result<Train, Error> {
+    Train::load(|name| {
+      let path = dir.join(name);
+      let file = File::open(&path).map_err(|source| Error::Io {
+        path: path.display().to_string(),
+        source,
+      })?;
+      Ok(ModelReader::new(BufReader::new(file), path.display().to_string()))
+    })
+  }
+
+  /**
+   * This method will build a new Train struct from the model files compiled into the binary, the files are only
+   * parsed the first time this method is called.
+   */
+  #[cfg(feature = "embedded-models")]
+  pub fn embedded() -> Train {
+    static EMBEDDED_TRAIN: OnceLock<Train> = OnceLock::new();
+    EMBEDDED_TRAIN
+      .get_or_init(|| {
+        Train::load(|name| {
+          let contents = embedded::global_file(name).unwrap();
+          Ok(ModelReader::new(contents.as_bytes(), format!("<embedded>/{}", name)))
+        })
+        .expect("The embedded model files are invalid.")
+      })
+      .clone()
+  }
+
+  /**
+   * Helper method to build a Train struct, the open closure yields a reader for each of the model file names.
+   */
+  fn load<F, R>(open: F) -> Result<Train, Error>
+  where
+    F: Fn(&str) -> Result<ModelReader<R>, Error>,
+    R: BufRead,
+  {
+    let mut result = Train::empty();
+    result.load_m_state(open(MFILENAME)?)?;
+    result.load_m_1_state(open(M1FILENAME)?)?;
+    result.load_noncoding_state(open(NFILENAME)?)?;
+    Train::load_position_state(&mut result.start, open(SFILENAME)?)?;
+    Train::load_position_state(&mut result.stop, open(PFILENAME)?)?;
+    Train::load_position_state(&mut result.start1, open(S1FILENAME)?)?;
+    Train::load_position_state(&mut result.stop1, open(P1FILENAME)?)?;
+    result.load_pwm_dist(open(DFILENAME)?)?;
+    Ok(result)
+  }
+
+  /**
+   * This method will build a Train struct with all values set to zero.
    */
-  fn load_m_state(&mut self) {
-    const READ_ERROR: &'static str = "Something went wrong while reading train/gene.";
-    let mut path = get_executable_path();
-    path.push(MFILENAME);
-    let file = File::open(path).expect(READ_ERROR);
-    let mut lines = BufReader::new(file).lines();
+  pub fn empty() -> Train {
+    Train {
+      trans: vec![vec![vec![vec![0.0; 4]; 16]; 6]; NUM_CG_BINS],
+      rtrans: vec![vec![vec![vec![0.0; 4]; 16]; 6]; NUM_CG_BINS],
+
+      noncoding: vec![vec![vec![0.0; 4]; 4]; NUM_CG_BINS],
+      start: vec![vec![vec![0.0; 64]; 61]; NUM_CG_BINS],
+      stop: vec![vec![vec![0.0; 64]; 61]; NUM_CG_BINS],
+      start1: vec![vec![vec![0.0; 64]; 61]; NUM_CG_BINS],
+      stop1: vec![vec![vec![0.0; 64]; 61]; NUM_CG_BINS],
+
+      s_dist: vec![vec![0.0; 6]; NUM_CG_BINS],
+      e_dist: vec![vec![0.0; 6]; NUM_CG_BINS],
+      s1_dist: vec![vec![0.0; 6]; NUM_CG_BINS],
+      e1_dist: vec![vec![0.0; 6]; NUM_CG_BINS],
+    }
+  }
 
+  /**
+   * This method will write the Train struct to the specified directory, using the same file names and format
+   * as the files in the train/ folder (so the result can be read by Train::from_file).
+   */
+  pub fn write_to_dir(&self, dir: &Path) -> io::Result<()> {
+    write_codon_file(&dir.join(MFILENAME), &self.trans)?;
+    write_codon_file(&dir.join(M1FILENAME), &self.rtrans)?;
+    write_matrix_file(&dir.join(NFILENAME), "", &self.noncoding)?;
+    write_matrix_file(&dir.join(SFILENAME), ">", &self.start)?;
+    write_matrix_file(&dir.join(PFILENAME), ">", &self.stop)?;
+    write_matrix_file(&dir.join(S1FILENAME), ">", &self.start1)?;
+    write_matrix_file(&dir.join(P1FILENAME), ">", &self.stop1)?;
+
+    let mut out = BufWriter::new(File::create(dir.join(DFILENAME))?);
+    for p in 0..NUM_CG_BINS {
+      writeln!(out, "{}", p + 26)?;
+      for dist in &[&self.s_dist, &self.e_dist, &self.s1_dist, &self.e1_dist] {
+        let values: Vec<String> = dist[p].iter().map(|v| format!("{:.4}", v)).collect();
+        writeln!(out, "{}", values.join("\t"))?;
+      }
+    }
+    out.flush()
+  }
+  /**
+   * This method wil load the train/gene file into the trans field of our struct
+   */
+  fn load_m_state<R: BufRead>(&mut self, mut reader: ModelReader<R>) -> Result<(), Error> {
     for p in 0..44 {
-      lines.next(); // first line is header
+      reader.next_line()?; // first line is header
       for i in 0..6 {
         for j in 0..16 {
-          let line = lines.next().expect(READ_ERROR).expect(READ_ERROR);
-          let tup: (f64, f64, f64, f64) = parse_string(&line).expect(READ_ERROR);
-          self.trans[p][i][j][0] = tup.0.ln();
-          self.trans[p][i][j][1] = tup.1.ln();
-          self.trans[p][i][j][2] = tup.2.ln();
-          self.trans[p][i][j][3] = tup.3.ln();
+          let values = reader.next_values(4)?;
+          for (k, prob) in values.iter().enumerate() {
+            self.trans[p][i][j][k] = prob.ln();
+          }
         }
       }
     }
+    Ok(())
   }
   /**
    * This method wil load the train/rgene file into the rtrans field of our struct
    */
-  fn load_m_1_state(&mut self) {
-    const READ_ERROR: &'static str = "Something went wrong while reading train/rgene.";
-    let mut path = get_executable_path();
-    path.push(M1FILENAME);
-    let file = File::open(path).expect(READ_ERROR);
-    let mut lines = BufReader::new(file).lines();
-
+  fn load_m_1_state<R: BufRead>(&mut self, mut reader: ModelReader<R>) -> Result<(), Error> {
     for p in 0..44 {
-      lines.next(); // first line is header
+      reader.next_line()?; // first line is header
       for i in 0..6 {
         for j in 0..16 {
-          let line = lines.next().expect(READ_ERROR).expect(READ_ERROR);
-          let tup: (f64, f64, f64, f64) = parse_string(&line).expect(READ_ERROR);
-          self.rtrans[p][i][j][0] = tup.0.ln();
-          self.rtrans[p][i][j][1] = tup.1.ln();
-          self.rtrans[p][i][j][2] = tup.2.ln();
-          self.rtrans[p][i][j][3] = tup.3.ln();
+          let values = reader.next_values(4)?;
+          for (k, prob) in values.iter().enumerate() {
+            self.rtrans[p][i][j][k] = prob.ln();
+          }
         }
       }
     }
+    Ok(())
   }
 
   /**
    * This method wil load the train/noncoding file into the noncoding field of our struct
    */
-  fn load_noncoding_state(&mut self) {
-    const READ_ERROR: &'static str = "Something went wrong while reading train/noncoding.";
-    let mut path = get_executable_path();
-    path.push(NFILENAME);
-    let file = File::open(path).expect(READ_ERROR);
-    let mut lines = BufReader::new(file).lines();
-
+  fn load_noncoding_state<R: BufRead>(&mut self, mut reader: ModelReader<R>) -> Result<(), Error> {
     for p in 0..44 {
-      lines.next(); // first line is header
+      reader.next_line()?; // first line is header
       for j in 0..4 {
-        let line = lines.next().expect(READ_ERROR).expect(READ_ERROR);
-        let tup: (f64, f64, f64, f64) = parse_string(&line).expect(READ_ERROR);
-        self.noncoding[p][j][0] = tup.0.ln();
-        self.noncoding[p][j][1] = tup.1.ln();
-        self.noncoding[p][j][2] = tup.2.ln();
-        self.noncoding[p][j][3] = tup.3.ln();
+        let values = reader.next_values(4)?;
+        for (k, prob) in values.iter().enumerate() {
+          self.noncoding[p][j][k] = prob.ln();
+        }
       }
     }
+    Ok(())
   }
 
   /**
    * This method wil load the train/pwm file into the s_dist/e_dist/s1_dist/e1_dist fields of our struct
    */
-  fn load_pwm_dist(&mut self) {
-    const READ_ERROR: &'static str = "Something went wrong while reading train/pwm.";
-    let mut path = get_executable_path();
-    path.push(DFILENAME);
-    let file = File::open(path).expect(READ_ERROR);
-    let mut lines = BufReader::new(file).lines();
-
+  fn load_pwm_dist<R: BufRead>(&mut self, mut reader: ModelReader<R>) -> Result<(), Error> {
     for p in 0..44 {
-      lines.next(); // first line is header
+      reader.next_line()?; // first line is header
 
       let dists = [
         &mut self.s_dist,
@@ -183,116 +398,154 @@ impl Train {
         &mut self.s1_dist,
         &mut self.e1_dist,
       ];
-      for i in 0..4 {
-        let line = lines.next().expect(READ_ERROR).expect(READ_ERROR);
-        let tup: (f64, f64, f64, f64, f64, f64) = parse_string(&line).expect(READ_ERROR);
-        dists[i][p][0] = tup.0;
-        dists[i][p][1] = tup.1;
-        dists[i][p][2] = tup.2;
-        dists[i][p][3] = tup.3;
-        dists[i][p][4] = tup.4;
-        dists[i][p][5] = tup.5;
+      for dist in dists {
+        dist[p] = reader.next_values(6)?;
       }
     }
+    Ok(())
   }
+
   /**
-   * This method wil load the train/start file into the start field of our struct
+   * This method wil load one of the train/start, train/stop, train/start1 or train/stop1 files into the
+   * specified field of our struct
    */
-  fn load_start_state(&mut self) {
-    const READ_ERROR: &'static str = "Something went wrong while reading train/start.";
-    let mut path = get_executable_path();
-    path.push(SFILENAME);
-    let file = File::open(path).expect(READ_ERROR);
-    let mut lines = BufReader::new(file).lines();
-
-    for p in 0..44 {
-      lines.next(); // first line is header
-      for j in 0..61 {
-        let line = lines.next().expect(READ_ERROR).expect(READ_ERROR);
-        for (k, value) in line.split_whitespace().enumerate() {
-          assert!(k < 64, READ_ERROR);
-          let prob = value.parse::<f64>().expect(READ_ERROR);
-          self.start[p][j][k] = prob.ln();
+  fn load_position_state<R: BufRead>(
+    table: &mut [Vec<Vec<f64>>],
+    mut reader: ModelReader<R>,
+  ) -> Result<(), Error> {
+    for bin in table.iter_mut().take(44) {
+      reader.next_line()?; // first line is header
+      for row in bin.iter_mut() {
+        let values = reader.next_values(64)?;
+        for (k, prob) in values.iter().enumerate() {
+          row[k] = prob.ln();
         }
       }
     }
+    Ok(())
   }
+}
 
-  /**
-   * This method wil load the train/stop file into the stop field of our struct
-   */
-  fn load_stop_state(&mut self) {
-    const READ_ERROR: &'static str = "Something went wrong while reading train/stop.";
-    let mut path = get_executable_path();
-    path.push(PFILENAME);
-    let file = File::open(path).expect(READ_ERROR);
-    let mut lines = BufReader::new(file).lines();
+/**
+ * Helper struct to read the lines of a model file, it keeps track of the line number for error messages.
+ */
+struct ModelReader<R> {
+  lines: io::Lines<R>,
+  file: String,
+  line: usize,
+}
 
-    for p in 0..44 {
-      lines.next(); // first line is header
-      for j in 0..61 {
-        let line = lines.next().expect(READ_ERROR).expect(READ_ERROR);
-        for (k, value) in line.split_whitespace().enumerate() {
-          assert!(k < 64, READ_ERROR);
-          let prob = value.parse::<f64>().expect(READ_ERROR);
-          self.stop[p][j][k] = prob.ln();
-        }
-      }
+impl<R: BufRead> ModelReader<R> {
+  fn new(reader: R, file: String) -> ModelReader<R> {
+    ModelReader {
+      lines: reader.lines(),
+      file,
+      line: 0,
     }
   }
 
   /**
-   * This method wil load the train/start file into the start1 fields of our struct
+   * This method will yield the next line of the file, reaching the end of the file is an error.
    */
-  fn load_start_1_state(&mut self) {
-    const READ_ERROR: &'static str = "Something went wrong while reading train/stop1.";
-    let mut path = get_executable_path();
-    path.push(S1FILENAME);
-    let file = File::open(path).expect(READ_ERROR);
-    let mut lines = BufReader::new(file).lines();
-
-    for p in 0..44 {
-      lines.next(); // first line is header
-      for j in 0..61 {
-        let line = lines.next().expect(READ_ERROR).expect(READ_ERROR);
-        for (k, value) in line.split_whitespace().enumerate() {
-          assert!(k < 64, READ_ERROR);
-          let prob = value.parse::<f64>().expect(READ_ERROR);
-          self.start1[p][j][k] = prob.ln();
-        }
-      }
+  fn next_line(&mut self) -> Result<String, Error> {
+    self.line += 1;
+    match self.lines.next() {
+      Some(Ok(line)) => Ok(line),
+      Some(Err(e)) => Err(self.error(&e.to_string())),
+      None => Err(self.error("unexpected end of file")),
     }
   }
+
   /**
-   * This method wil load the train/stop file into the stop1 fields of our struct
+   * This method will parse the next line of the file, which should contain exactly count numeric values.
    */
-  fn load_stop_1_state(&mut self) {
-    const READ_ERROR: &'static str = "Something went wrong while reading train/start1.";
-    let mut path = get_executable_path();
-    path.push(P1FILENAME);
-    let file = File::open(path).expect(READ_ERROR);
-    let mut lines = BufReader::new(file).lines();
+  fn next_values(&mut self, count: usize) -> Result<Vec<f64>, Error> {
+    let line = self.next_line()?;
+    let values = line
+      .split_whitespace()
+      .map(|value| value.parse::<f64>())
+      .collect::<Result<Vec<f64>, _>>()
+      .map_err(|_| self.error("invalid numeric value"))?;
+    if values.len() != count {
+      return Err(self.error(&format!("expected {} values but found {}", count, values.len())));
+    }
+    Ok(values)
+  }
 
-    for p in 0..44 {
-      lines.next(); // first line is header
-      for j in 0..61 {
-        let line = lines.next().expect(READ_ERROR).expect(READ_ERROR);
-        for (k, value) in line.split_whitespace().enumerate() {
-          assert!(k < 64, READ_ERROR);
-          let prob = value.parse::<f64>().expect(READ_ERROR);
-          self.stop1[p][j][k] = prob.ln();
-        }
+  fn error(&self, message: &str) -> Error {
+    Error::model_format(&self.file, Some(self.line), message)
+  }
+}
+
+/**
+ * Helper method to write the gene/rgene tables (stored as log probabilities) to a training file.
+ */
+fn write_codon_file(path: &Path, values: &[Vec<Vec<Vec<f64>>>]) -> io::Result<()> {
+  let mut out = BufWriter::new(File::create(path)?);
+  for (p, bin) in values.iter().enumerate() {
+    writeln!(out, "{}", p + 26)?;
+    for rows in bin {
+      for row in rows {
+        writeln!(out, "{}", format_probabilities(row))?;
       }
     }
   }
+  out.flush()
+}
+
+/**
+ * Helper method to write the noncoding/start/stop tables (stored as log probabilities) to a training file,
+ * the header of every CG bin is prefixed with the specified string.
+ */
+fn write_matrix_file(path: &Path, header_prefix: &str, values: &[Vec<Vec<f64>>]) -> io::Result<()> {
+  let mut out = BufWriter::new(File::create(path)?);
+  for (p, bin) in values.iter().enumerate() {
+    writeln!(out, "{}{}", header_prefix, p + 26)?;
+    for row in bin {
+      writeln!(out, "{}", format_probabilities(row))?;
+    }
+  }
+  out.flush()
+}
+
+/**
+ * Helper method to convert a row of log probabilities back to a tab separated line of probabilities.
+ */
+fn format_probabilities(row: &[f64]) -> String {
+  row
+    .iter()
+    .map(|v| format!("{:.6}", v.exp().max(MIN_WRITTEN_PROB)))
+    .collect::<Vec<String>>()
+    .join("\t")
 }
 
 impl HMM {
   /**
    * This method will yield the HMM struct based on the specified train file path
    */
-  pub fn from_file(train_file: &str) -> HMM {
-    let mut result = HMM {
+  pub fn from_file(train_file: &str) -> Result<HMM, Error> {
+    let file = File::open(train_file).map_err(|source| Error::Io {
+      path: train_file.to_string(),
+      source,
+    })?;
+    HMM::from_reader(BufReader::new(file), train_file)
+  }
+
+  /**
+   * This method will yield the HMM struct based on the contents of a train file, the name is only used in error
+   * messages.
+   */
+  pub fn from_reader<R: BufRead>(reader: R, name: &str) -> Result<HMM, Error> {
+    let mut result = HMM::empty();
+    result.load_transition(reader, name)?;
+    Ok(result)
+  }
+
+  /**
+   * This method will build an HMM struct with all values set to zero.
+   */
+  pub fn empty() -> HMM {
+    HMM {
       initial_state: vec![0.0; 29],
 
       tr: vec![0.0; 14],
@@ -313,49 +566,63 @@ impl HMM {
       e_dist: vec![0.0; 6],
       s1_dist: vec![0.0; 6],
       e1_dist: vec![0.0; 6],
-    };
-    result.load_transition(&train_file);
-    result
+    }
   }
 
   /**
-   * This method will read the contents of the specified training file path into the HMM struct
+   * This method will read the contents of a training file into the HMM struct
    */
-  fn load_transition(&mut self, train_file: &str) {
-    let file = File::open(train_file).expect("An error occured while opening the training file.");
-    let reader = BufReader::new(file);
-
+  fn load_transition<R: BufRead>(&mut self, reader: R, name: &str) -> Result<(), Error> {
+    let mut line_count = 0;
     for (index, line) in reader.lines().enumerate() {
-      let line = line.expect("Something went wrong while reading the training file.");
+      line_count = index + 1;
+      let error = |message: &str| Error::model_format(name, Some(index + 1), message);
+      let line = line.map_err(|e| error(&e.to_string()))?;
       /* transition */
       if index > 0 && index < 15 {
         let tup: (String, f64) =
-          parse_string(&line).expect("Unable to process 'Transition' from training file.");
-        let tr = tr2int(&tup.0);
+          parse_string(&line).map_err(|_| error("Unable to process 'Transition'"))?;
+        let tr = tr2int(&tup.0).ok_or_else(|| error(&format!("Unknown transition {}", tup.0)))?;
         self.tr[tr] = tup.1.ln();
       }
       /* transition MI */
       else if index > 15 && index < 32 {
         let tup: (char, char, f64) =
-          parse_string(&line).expect("Unable to process 'TransitionMI' from training file.");
-        let start = nt2int(tup.0);
-        let end = nt2int(tup.1);
+          parse_string(&line).map_err(|_| error("Unable to process 'TransitionMI'"))?;
+        let (start, end) = nucleotide_pair(tup.0, tup.1).ok_or_else(|| error("Unknown nucleotide"))?;
         self.tr_m_i[start][end] = tup.2.ln();
       }
       /* transition II */
       else if index > 32 && index < 49 {
         let tup: (char, char, f64) =
-          parse_string(&line).expect("Unable to process 'TransitionII' from training file.");
-        let start = nt2int(tup.0);
-        let end = nt2int(tup.1);
+          parse_string(&line).map_err(|_| error("Unable to process 'TransitionII'"))?;
+        let (start, end) = nucleotide_pair(tup.0, tup.1).ok_or_else(|| error("Unknown nucleotide"))?;
         self.tr_i_i[start][end] = tup.2.ln();
       }
       /* PI */
       else if index > 49 {
         let tup: (String, f64) =
-          parse_string(&line).expect("Unable to process 'PI' from training file.");
-        self.initial_state[index - 50] = tup.1.ln();
+          parse_string(&line).map_err(|_| error("Unable to process 'PI'"))?;
+        let state = self
+          .initial_state
+          .get_mut(index - 50)
+          .ok_or_else(|| error("Too many initial state probabilities"))?;
+        *state = tup.1.ln();
       }
     }
+    if line_count < 50 + self.initial_state.len() {
+      return Err(Error::model_format(name, Some(line_count), "unexpected end of file"));
+    }
+    Ok(())
+  }
+}
+
+/**
+ * Helper method to convert a pair of nucleotides to integers, None is returned for any nucleotide other than ACGT.
+ */
+fn nucleotide_pair(a: char, b: char) -> Option<(usize, usize)> {
+  match (nt2int(a), nt2int(b)) {
+    (start, end) if start < 4 && end < 4 => Some((start, end)),
+    _ => None,
   }
 }
@@ -0,0 +1,508 @@
+use super::annotation::CdsFeature;
+use super::dna_helpers::{nt2int, trinucleotide};
+use super::train::{Train, NUM_CG_BINS};
+use rayon::prelude::*;
+
+/*
+ * training.rs
+ * ===========
+ * This file contains the training pipeline, which estimates the global (CG binned) training tables from a genome
+ * and its CDS annotation. The estimated tables are stored in a Train struct, which can be written to disk with
+ * Train::write_to_dir.
+ */
+
+/**
+ * Weight (in pseudo-observations) of the genome-wide distribution when smoothing the distribution of a single
+ * CG bin, bins without any training data will simply get the genome-wide distribution.
+ */
+const PRIOR_WEIGHT: f64 = 20.0;
+
+/**
+ * The smallest probability assigned to any table entry.
+ */
+const MIN_PROB: f64 = 0.000001;
+
+/**
+ * Minimum number of sites used to fit the Gaussian score distributions of a CG bin, sites of neighbouring bins
+ * are included until this number is reached.
+ */
+const MIN_PWM_SITES: usize = 50;
+
+/**
+ * Maximum number of random (non-annotated) sites of every kind used to fit the background score distributions.
+ */
+const MAX_RANDOM_SITES: usize = 50000;
+
+/**
+ * Half the width of the window used to determine the CG bin of start/stop sites.
+ */
+const SITE_CG_WINDOW: usize = 100;
+
+/**
+ * Minimum length of the region used to determine the CG bin of non-coding regions.
+ */
+const NONCODING_CG_WINDOW: usize = 200;
+
+const START_CODONS: &[&str] = &["ATG", "GTG", "TTG"];
+const STOP_CODONS: &[&str] = &["TAA", "TAG", "TGA"];
+const START_CODONS_1: &[&str] = &["CAT", "CAC", "CAA"];
+const STOP_CODONS_1: &[&str] = &["TTA", "CTA", "TCA"];
+
+/**
+ * Summary of the data that was used for training.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct TrainingSummary {
+  pub genes_used: usize,
+  pub genes_skipped: usize,
+  pub genes_per_bin: Vec<usize>,
+}
+
+/**
+ * The four kinds of start/stop sites of the model, see viterbi.rs for the windows that are scored for each of them.
+ */
+#[derive(Clone, Copy)]
+enum SiteKind {
+  Start,
+  Stop,
+  Start1,
+  Stop1,
+}
+
+/**
+ * A start/stop site, t is the 0-based position of the first nucleotide of the codon on the forward strand.
+ */
+struct Site {
+  seq: usize,
+  t: usize,
+  cg: usize,
+}
+
+/**
+ * Raw counts for all tables of a Train struct.
+ */
+struct Counts {
+  trans: Vec<Vec<Vec<Vec<f64>>>>,
+  rtrans: Vec<Vec<Vec<Vec<f64>>>>,
+  noncoding: Vec<Vec<Vec<f64>>>,
+  start: Vec<Vec<Vec<f64>>>,
+  stop: Vec<Vec<Vec<f64>>>,
+  start1: Vec<Vec<Vec<f64>>>,
+  stop1: Vec<Vec<Vec<f64>>>,
+}
+
+/**
+ * This method will estimate all CG binned training tables from the given genome sequences (id, sequence) and
+ * their CDS annotation. Every gene (and non-coding region) contributes to the CG bin of its own CG content, so
+ * a single genome populates the bins around its average CG content, the remaining bins fall back to the
+ * genome-wide estimates.
+ */
+pub fn train_from_annotation(
+  genome: &[(String, String)],
+  features: &[CdsFeature],
+) -> (Train, TrainingSummary) {
+  let sequences: Vec<Vec<char>> = genome
+    .iter()
+    .map(|(_, seq)| seq.chars().map(|c| c.to_ascii_uppercase()).collect())
+    .collect();
+  let mut counts = Counts::new();
+  let mut summary = TrainingSummary {
+    genes_per_bin: vec![0; NUM_CG_BINS],
+    ..Default::default()
+  };
+  let mut true_sites: Vec<Vec<Site>> = vec![vec![], vec![], vec![], vec![]];
+
+  /*
+   * Coding tables and true start/stop sites
+   */
+  let mut coding_mask: Vec<Vec<bool>> = sequences.iter().map(|s| vec![false; s.len()]).collect();
+  for feature in features {
+    let seq_index = match find_sequence(genome, &feature.seqid) {
+      Some(index) => index,
+      None => {
+        summary.genes_skipped += 1;
+        continue;
+      }
+    };
+    let seq = &sequences[seq_index];
+    if feature.end > seq.len() || feature.start < 1 {
+      summary.genes_skipped += 1;
+      continue;
+    }
+    // 0-based, inclusive coordinates of the gene on the forward strand
+    let left = feature.start - 1;
+    let right = feature.end - 1;
+    for masked in coding_mask[seq_index][left..=right].iter_mut() {
+      *masked = true;
+    }
+    if !is_complete_gene(seq, left, right, feature.forward) {
+      summary.genes_skipped += 1;
+      continue;
+    }
+
+    let cg = cg_bin(&seq[left..=right]);
+    summary.genes_used += 1;
+    summary.genes_per_bin[cg] += 1;
+
+    if feature.forward {
+      // M1 follows the start codon, the states then cycle through M1..M6
+      for k in (left + 3)..=(right - 3) {
+        count_emission(&mut counts.trans[cg][(k - left - 3) % 6], seq, k);
+      }
+      count_window(&mut counts.start[cg], seq, left as isize - 30);
+      count_window(&mut counts.stop[cg], seq, (right - 2) as isize - 60);
+      true_sites[SiteKind::Start as usize].push(Site { seq: seq_index, t: left, cg });
+      true_sites[SiteKind::Stop as usize].push(Site { seq: seq_index, t: right - 2, cg });
+    } else {
+      // on the reverse strand the gene always ends in M6', so the states are counted from the end
+      for k in (left + 3)..=(right - 3) {
+        count_emission(&mut counts.rtrans[cg][5 - (right - 3 - k) % 6], seq, k);
+      }
+      count_window(&mut counts.start1[cg], seq, left as isize);
+      count_window(&mut counts.stop1[cg], seq, (right - 2) as isize - 30);
+      true_sites[SiteKind::Start1 as usize].push(Site { seq: seq_index, t: left, cg });
+      true_sites[SiteKind::Stop1 as usize].push(Site { seq: seq_index, t: right - 2, cg });
+    }
+  }
+
+  /*
+   * Non-coding table, every stretch without any annotated CDS counts towards the bin of its (extended) region
+   */
+  for (seq_index, seq) in sequences.iter().enumerate() {
+    let mask = &coding_mask[seq_index];
+    let mut t = 0;
+    while t < seq.len() {
+      if mask[t] {
+        t += 1;
+        continue;
+      }
+      let region_start = t;
+      while t < seq.len() && !mask[t] {
+        t += 1;
+      }
+      let (from, to) = extend_region(region_start, t, NONCODING_CG_WINDOW, seq.len());
+      let cg = cg_bin(&seq[from..to]);
+      for k in (region_start + 1)..t {
+        let (a, b) = (nt2int(seq[k - 1]), nt2int(seq[k]));
+        if a < 4 && b < 4 {
+          counts.noncoding[cg][a][b] += 1.0;
+        }
+      }
+    }
+  }
+
+  let mut train = counts.into_train();
+
+  /*
+   * Gaussian score distributions of true and random start/stop sites
+   */
+  let random_sites = find_random_sites(&sequences, &true_sites);
+  let kinds = [SiteKind::Start, SiteKind::Stop, SiteKind::Start1, SiteKind::Stop1];
+  let dists: Vec<Vec<Vec<f64>>> = kinds
+    .par_iter()
+    .map(|&kind| {
+      (0..NUM_CG_BINS)
+        .map(|p| {
+          let table = match kind {
+            SiteKind::Start => &train.start[p],
+            SiteKind::Stop => &train.stop[p],
+            SiteKind::Start1 => &train.start1[p],
+            SiteKind::Stop1 => &train.stop1[p],
+          };
+          let (h_mean, h_sd) = fit_scores(&sequences, &true_sites[kind as usize], p, table, kind);
+          let (r_mean, r_sd) = fit_scores(&sequences, &random_sites[kind as usize], p, table, kind);
+          vec![h_sd, h_mean, gaussian_norm(h_sd), r_sd, r_mean, gaussian_norm(r_sd)]
+        })
+        .collect()
+    })
+    .collect();
+  let mut dists = dists.into_iter();
+  train.s_dist = dists.next().unwrap();
+  train.e_dist = dists.next().unwrap();
+  train.s1_dist = dists.next().unwrap();
+  train.e1_dist = dists.next().unwrap();
+
+  (train, summary)
+}
+
+impl Counts {
+  fn new() -> Counts {
+    Counts {
+      trans: vec![vec![vec![vec![0.0; 4]; 16]; 6]; NUM_CG_BINS],
+      rtrans: vec![vec![vec![vec![0.0; 4]; 16]; 6]; NUM_CG_BINS],
+      noncoding: vec![vec![vec![0.0; 4]; 4]; NUM_CG_BINS],
+      start: vec![vec![vec![0.0; 64]; 61]; NUM_CG_BINS],
+      stop: vec![vec![vec![0.0; 64]; 61]; NUM_CG_BINS],
+      start1: vec![vec![vec![0.0; 64]; 61]; NUM_CG_BINS],
+      stop1: vec![vec![vec![0.0; 64]; 61]; NUM_CG_BINS],
+    }
+  }
+
+  /**
+   * Converts the raw counts into the log probabilities of a Train struct, every row of every bin is smoothed
+   * towards the genome-wide distribution of that row.
+   */
+  fn into_train(self) -> Train {
+    let mut train = Train::empty();
+    train.trans = smooth_codon_table(&self.trans);
+    train.rtrans = smooth_codon_table(&self.rtrans);
+    train.noncoding = smooth_table(&self.noncoding);
+    train.start = smooth_table(&self.start);
+    train.stop = smooth_table(&self.stop);
+    train.start1 = smooth_table(&self.start1);
+    train.stop1 = smooth_table(&self.stop1);
+    train
+  }
+}
+
+/**
+ * Helper method to smooth a table of the form [bin][row][outcome].
+ */
+fn smooth_table(counts: &[Vec<Vec<f64>>]) -> Vec<Vec<Vec<f64>>> {
+  let rows = counts[0].len();
+  let global: Vec<Vec<f64>> = (0..rows)
+    .map(|r| {
+      let total: Vec<f64> = (0..counts[0][r].len())
+        .map(|k| counts.iter().map(|bin| bin[r][k]).sum())
+        .collect();
+      laplace(&total)
+    })
+    .collect();
+  counts
+    .iter()
+    .map(|bin| {
+      bin
+        .iter()
+        .zip(global.iter())
+        .map(|(row, prior)| smooth_row(row, prior))
+        .collect()
+    })
+    .collect()
+}
+
+/**
+ * Helper method to smooth a table of the form [bin][state][context][outcome].
+ */
+fn smooth_codon_table(counts: &[Vec<Vec<Vec<f64>>>]) -> Vec<Vec<Vec<Vec<f64>>>> {
+  let flattened: Vec<Vec<Vec<f64>>> = counts
+    .iter()
+    .map(|bin| bin.iter().flatten().cloned().collect())
+    .collect();
+  smooth_table(&flattened)
+    .into_iter()
+    .map(|bin| bin.chunks(16).map(|chunk| chunk.to_vec()).collect())
+    .collect()
+}
+
+/**
+ * Helper method to compute the Laplace smoothed distribution of a row of counts.
+ */
+fn laplace(row: &[f64]) -> Vec<f64> {
+  let total: f64 = row.iter().sum::<f64>() + row.len() as f64;
+  row.iter().map(|c| (c + 1.0) / total).collect()
+}
+
+/**
+ * Helper method to compute the log probabilities of a row of counts, smoothed towards the prior distribution.
+ */
+fn smooth_row(row: &[f64], prior: &[f64]) -> Vec<f64> {
+  let total: f64 = row.iter().sum::<f64>() + PRIOR_WEIGHT;
+  row
+    .iter()
+    .zip(prior.iter())
+    .map(|(c, p)| ((c + PRIOR_WEIGHT * p) / total).max(MIN_PROB).ln())
+    .collect()
+}
+
+/**
+ * Helper method to count the emission of the nucleotide at position k given the two preceding nucleotides.
+ */
+fn count_emission(table: &mut [Vec<f64>], seq: &[char], k: usize) {
+  let (from0, from, to) = (nt2int(seq[k - 2]), nt2int(seq[k - 1]), nt2int(seq[k]));
+  if from0 < 4 && from < 4 && to < 4 {
+    table[from0 * 4 + from][to] += 1.0;
+  }
+}
+
+/**
+ * Helper method to count the trinucleotides of a 61 position window starting at the specified offset.
+ */
+fn count_window(table: &mut [Vec<f64>], seq: &[char], offset: isize) {
+  for (j, row) in table.iter_mut().enumerate() {
+    if let Some(idx) = trinucleotide_at(seq, offset + j as isize) {
+      row[idx] += 1.0;
+    }
+  }
+}
+
+/**
+ * Helper method to get the trinucleotide index at the specified position, None is returned when the trinucleotide
+ * is not fully contained in the sequence or contains a nucleotide other than ACGT.
+ */
+fn trinucleotide_at(seq: &[char], pos: isize) -> Option<usize> {
+  if pos < 0 || pos as usize + 2 >= seq.len() {
+    return None;
+  }
+  let pos = pos as usize;
+  if seq[pos..pos + 3].iter().any(|&c| nt2int(c) == 4) {
+    return None;
+  }
+  Some(trinucleotide(&seq[pos], &seq[pos + 1], &seq[pos + 2]))
+}
+
+/**
+ * Helper method to check whether an annotated gene has a valid start and stop codon and a length that is a
+ * multiple of three.
+ */
+fn is_complete_gene(seq: &[char], left: usize, right: usize, forward: bool) -> bool {
+  if right < left + 8 || !(right - left + 1).is_multiple_of(3) {
+    return false;
+  }
+  let first: String = seq[left..left + 3].iter().collect();
+  let last: String = seq[right - 2..=right].iter().collect();
+  if forward {
+    START_CODONS.contains(&first.as_str()) && STOP_CODONS.contains(&last.as_str())
+  } else {
+    STOP_CODONS_1.contains(&first.as_str()) && START_CODONS_1.contains(&last.as_str())
+  }
+}
+
+/**
+ * Helper method to compute the CG bin of a region, using the same binning as dna_helpers::get_prob_from_cg.
+ */
+fn cg_bin(region: &[char]) -> usize {
+  if region.is_empty() {
+    return 0;
+  }
+  let cg = region.iter().filter(|&&c| c == 'C' || c == 'G').count();
+  let percentage = ((cg as f64 / region.len() as f64) * 100.0).floor() as i32 - 26;
+  percentage.clamp(0, NUM_CG_BINS as i32 - 1) as usize
+}
+
+/**
+ * Helper method to extend the region [from, to) symmetrically until it is at least min_len long.
+ */
+fn extend_region(from: usize, to: usize, min_len: usize, len: usize) -> (usize, usize) {
+  if to - from >= min_len {
+    return (from, to);
+  }
+  let extra = (min_len - (to - from)) / 2 + 1;
+  (from.saturating_sub(extra), (to + extra).min(len))
+}
+
+/**
+ * Helper method to find the sequence belonging to a feature, the sequence id may also match one of the
+ * |-separated parts of a FASTA id (e.g. gi|49175990|ref|NC_000913.2|) or, when both contain a single sequence,
+ * any id.
+ */
+pub(crate) fn find_sequence(genome: &[(String, String)], seqid: &str) -> Option<usize> {
+  genome
+    .iter()
+    .position(|(id, _)| id == seqid)
+    .or_else(|| {
+      genome
+        .iter()
+        .position(|(id, _)| id.split('|').any(|part| part == seqid))
+    })
+    .or(if genome.len() == 1 { Some(0) } else { None })
+}
+
+/**
+ * Helper method to collect random (non-annotated) occurrences of the start/stop codons of every site kind, at
+ * most MAX_RANDOM_SITES sites are kept for every kind.
+ */
+fn find_random_sites(sequences: &[Vec<char>], true_sites: &[Vec<Site>]) -> Vec<Vec<Site>> {
+  let codon_sets = [START_CODONS, STOP_CODONS, STOP_CODONS_1, START_CODONS_1];
+  codon_sets
+    .iter()
+    .enumerate()
+    .map(|(kind, codons)| {
+      let annotated: std::collections::HashSet<(usize, usize)> =
+        true_sites[kind].iter().map(|site| (site.seq, site.t)).collect();
+      let mut sites: Vec<Site> = Vec::new();
+      for (seq_index, seq) in sequences.iter().enumerate() {
+        for t in 0..seq.len().saturating_sub(2) {
+          let codon: String = seq[t..t + 3].iter().collect();
+          if codons.contains(&codon.as_str()) && !annotated.contains(&(seq_index, t)) {
+            sites.push(Site { seq: seq_index, t, cg: 0 });
+          }
+        }
+      }
+      let step = (sites.len() / MAX_RANDOM_SITES).max(1);
+      sites
+        .into_iter()
+        .step_by(step)
+        .map(|site| {
+          let seq = &sequences[site.seq];
+          let (from, to) = extend_region(site.t, site.t + 3, 2 * SITE_CG_WINDOW, seq.len());
+          Site {
+            cg: cg_bin(&seq[from..to]),
+            ..site
+          }
+        })
+        .collect()
+    })
+    .collect()
+}
+
+/**
+ * Helper method to compute the score of a site, this is identical to the score computed in viterbi.rs (the
+ * negated sum of the log probabilities of the trinucleotides in the window around the site). None is returned when
+ * the window is not fully contained in the sequence.
+ */
+fn site_score(seq: &[char], t: usize, table: &[Vec<f64>], kind: SiteKind) -> Option<f64> {
+  let t = t as isize;
+  let (offsets, row_offset): (std::ops::RangeInclusive<isize>, isize) = match kind {
+    SiteKind::Start | SiteKind::Stop1 => (-30..=30, 30),
+    SiteKind::Stop => (-60..=-3, 60),
+    SiteKind::Start1 => (3..=60, -3),
+  };
+  let mut score = 0.0;
+  for i in offsets {
+    score -= table[(i + row_offset) as usize][trinucleotide_at(seq, t + i)?];
+  }
+  Some(score)
+}
+
+/**
+ * Helper method to fit a Gaussian distribution (mean, standard deviation) on the scores of the sites in CG bin p,
+ * sites of neighbouring bins are included until at least MIN_PWM_SITES sites are used.
+ */
+fn fit_scores(
+  sequences: &[Vec<char>],
+  sites: &[Site],
+  p: usize,
+  table: &[Vec<f64>],
+  kind: SiteKind,
+) -> (f64, f64) {
+  let mut distance = 0;
+  let mut selected: Vec<&Site>;
+  loop {
+    selected = sites
+      .iter()
+      .filter(|site| (site.cg as isize - p as isize).abs() <= distance)
+      .collect();
+    if selected.len() >= MIN_PWM_SITES || distance >= NUM_CG_BINS as isize {
+      break;
+    }
+    distance += 1;
+  }
+  let scores: Vec<f64> = selected
+    .iter()
+    .filter_map(|site| site_score(&sequences[site.seq], site.t, table, kind))
+    .collect();
+  if scores.is_empty() {
+    return (0.0, 1.0);
+  }
+  let n = scores.len() as f64;
+  let mean = scores.iter().sum::<f64>() / n;
+  let variance = scores.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n;
+  (mean, variance.sqrt().max(1.0))
+}
+
+/**
+ * Helper method to compute the normalisation factor of a Gaussian distribution with the given standard deviation.
+ */
+fn gaussian_norm(sd: f64) -> f64 {
+  1.0 / (sd * (2.0 * std::f64::consts::PI).sqrt())
+}
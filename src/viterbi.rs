@@ -1,9 +1,14 @@
 use super::constants::*;
-use super::dna_helpers::{get_protein, get_rc_dna, nt2int, trinucleotide};
+use super::dna_helpers::{complement_nt, encode_bases, get_protein, get_rc_dna, trinucleotide_nts};
+use super::config::{PosteriorMode, PredictionConfig, MIN_SEQUENCE_LENGTH};
+use super::genetic_code::{codon_index, GeneticCode};
+use super::posterior::{forward_backward, BasePosteriors, GenePosterior};
 use super::train::{Train, HMM};
 use rayon::prelude::*;
+use std::borrow::Cow;
+use std::ops::{Index, IndexMut};
 
-/**
+/*
  * viterbi.rs
  * ==========
  * This file contains the viterbi::viterbi function used for running an HMM on a specified input sequence
@@ -13,11 +18,22 @@ use rayon::prelude::*;
 /**
  * The following structs are used for storing the predictions output by viterbi.
  */
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Prediction {
 	pub head: String,
 	pub outs: Vec<Out>,
+
+	/* per base coding posteriors, only computed with PosteriorMode::Bases */
+	pub posteriors: Option<BasePosteriors>,
+
+	/* length of the sequence when it was predicted as a circular sequence */
+	pub circular_length: Option<usize>,
+
+	/* CG bin of the model used for the sequence (see dna_helpers::get_cg_bin) */
+	pub cg: usize,
 }
 
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Out {
 	pub dna_start_t: usize,
 	pub dna_end_t: usize,
@@ -30,275 +46,758 @@ pub struct Out {
 	pub dna: String,
 
 	pub forward: bool,
+
+	/* gene or sequence shorter than the thresholds of FragGeneScan 1.31 (see config.rs) */
+	pub below_threshold: bool,
+
+	/* the gene runs off the sequence without a start codon (partial_start) or stop codon (partial_end) */
+	pub partial_start: bool,
+	pub partial_end: bool,
+
+	/* only computed when posteriors are enabled in the config */
+	pub posterior: Option<GenePosterior>,
 }
 
 /**
  * This function will run the viterbi algorithm for the specified HMM and Train structs on the given sequence,
- * the parameter wholegenome specifies whether a whole genome sequence was provided and the parameter cg gives
- * the CG score of the sequence (see dna_helpers.rs), the head parameter contains the identifier of the sequence.
+ * the parameter cg gives the CG score of the sequence (see dna_helpers.rs), the head parameter contains the
+ * identifier of the sequence. The optional quality parameter holds the Phred quality score of every base of the
+ * sequence, low quality bases get softened match emissions and more likely indel transitions. The config specifies
+ * whether a whole genome sequence was provided, the genetic code used for recognizing stop codons and translating
+ * the predicted genes, the start codons and whether (and how far) the start codons of whole genome predictions
+ * are refined.
  */
 pub fn viterbi(
 	hmm: &HMM,
 	train: &Train,
-	sequence: &String,
-	wholegenome: bool,
+	sequence: &str,
 	cg: usize,
-	head: &String,
+	head: &str,
+	quality: Option<&[u8]>,
+	config: &PredictionConfig,
 ) -> Prediction {
-	let log53: f64 = (0.53 as f64).ln();
-	let log16: f64 = (0.16 as f64).ln();
-	let log30: f64 = (0.30 as f64).ln();
-	let log25: f64 = (0.25 as f64).ln();
-	let log95: f64 = (0.95 as f64).ln();
-	let log54: f64 = (0.54 as f64).ln();
-	let log83: f64 = (0.83 as f64).ln();
-	let log07: f64 = (0.07 as f64).ln();
-	let max_dbl = f64::INFINITY;
-
-	let gene_len;
+	let wholegenome = config.wholegenome();
+	let code = config.genetic_code();
+	let refine_window = config.refine_window();
 
 	let mut dna_id = 0;
 
-	let mut temp_i = [0; 6];
-	let mut temp_i_1 = [0; 6];
+	let gene_len = config.min_gene_length();
+	let refine = wholegenome && refine_window.is_some();
+
+	/* the sequence is encoded once with a byte per base, for both the recursion and the posteriors */
+	let nts = encode_bases(sequence);
+	let len_seq = nts.len();
+	if len_seq < 3 {
+		/* too short to contain a single codon */
+		return Prediction {
+			head: head.to_string(),
+			outs: Vec::new(),
+			posteriors: None,
+			circular_length: None,
+			cg,
+		};
+	}
 
-	let mut refine = false;
+	/* the DNA of the genes is taken from the sequence, so positions are characters (non-ASCII ones are written as N) */
+	let sequence: Cow<str> = match sequence.is_ascii() {
+		true => Cow::Borrowed(sequence),
+		false => Cow::Owned(sequence.chars().map(|c| if c.is_ascii() { c } else { 'N' }).collect()),
+	};
+	let codon = |a: usize, b: usize, c: usize| codon_index(nts[a] as usize, nts[b] as usize, nts[c] as usize);
+	let error_prob: Option<Vec<f64>> =
+		quality.map(|quality| quality.iter().map(|&q| phred_error_prob(q)).collect());
+	let recursion = Recursion::new(hmm, config, &nts, error_prob.as_deref());
+
+	/*
+	 * Long sequences are decoded with checkpoints, which only keeps a few columns of the matrices in memory.
+	 * The posteriors need the full matrices.
+	 */
+	let (vpath, path_alpha, matrix) =
+		if len_seq > config.max_matrix_length() && config.posteriors() == PosteriorMode::Off {
+			let (vpath, path_alpha) = recursion.checkpointed_path();
+			(vpath, path_alpha, None)
+		} else {
+			let mut matrix = Matrix::full(len_seq);
+			recursion.init(&mut matrix);
+			for t in 1..len_seq {
+				recursion.column(&mut matrix, t);
+			}
+			let (vpath, path_alpha) = recursion.backtrack(&matrix);
+			/* the matrices are only kept for the posteriors */
+			(vpath, path_alpha, (config.posteriors() != PosteriorMode::Off).then_some(matrix))
+		};
 
-	if wholegenome {
-		gene_len = 120;
-		refine = true;
-	} else {
-		gene_len = 60;
-	}
+	let mut prediction = Prediction {
+		head: head.to_string(),
+		outs: Vec::new(),
+		posteriors: None,
+		circular_length: None,
+		cg,
+	};
 
-	let len_seq = sequence.chars().count();
-	let mut alpha = vec![vec![0.0; len_seq]; NUM_STATE];
-	let mut path: Vec<Vec<i8>> = vec![vec![0; len_seq]; NUM_STATE];
-	let mut vpath = vec![0; len_seq];
-
-	alpha.par_iter_mut().enumerate().for_each(|(i, alpha)| {
-		// This uses the closure parameter instead.
-		alpha[0] = -1.0 * hmm.initial_state[i];
-	});
-
-	let sequence: &Vec<char> = &sequence.chars().collect();
-
-	/* stop state */
-	if sequence[0].to_ascii_uppercase() == 'T'
-		&& ((sequence[1].to_ascii_uppercase() == 'A' && sequence[2].to_ascii_uppercase() == 'A')
-			|| (sequence[1].to_ascii_uppercase() == 'A' && sequence[2].to_ascii_uppercase() == 'G')
-			|| (sequence[1].to_ascii_uppercase() == 'G' && sequence[2].to_ascii_uppercase() == 'A'))
-	{
-		alpha[E_STATE][0] = max_dbl;
-		alpha[E_STATE][1] = max_dbl;
-		path[E_STATE][1] = E_STATE as i8;
-		path[E_STATE][2] = E_STATE as i8;
-
-		alpha[M6_STATE][2] = max_dbl;
-		alpha[M5_STATE][1] = max_dbl;
-		alpha[M4_STATE][0] = max_dbl;
-		alpha[M3_STATE][2] = max_dbl;
-		alpha[M2_STATE][1] = max_dbl;
-		alpha[M1_STATE][0] = max_dbl;
-
-		if sequence[1].to_ascii_uppercase() == 'A' && sequence[2].to_ascii_uppercase() == 'A' {
-			alpha[E_STATE][2] = alpha[E_STATE][2] - log53;
-		} else if sequence[1].to_ascii_uppercase() == 'A' && sequence[2].to_ascii_uppercase() == 'G'
-		{
-			alpha[E_STATE][2] = alpha[E_STATE][2] - log16;
-		} else if sequence[1].to_ascii_uppercase() == 'G' && sequence[2].to_ascii_uppercase() == 'A'
+	let mut codon_start = 0;
+	let mut start_t: isize = -1;
+
+	let mut insert: [usize; 100] = [0; 100];
+	let mut delete: [usize; 100] = [0; 100];
+
+	let mut start_orf = 0;
+	let mut prev_match = 0;
+
+	let mut insert_id = 0;
+	let mut delete_id = 0;
+	let mut end_t: usize;
+
+	let mut final_score;
+	let mut frame;
+
+	let mut dna_start_t_withstop = 0;
+	let mut dna_start_t = 0;
+	/* first state of the current gene, the start (S) or reverse stop (S') state unless the gene is partial */
+	let mut first_state = 0;
+
+	for t in 0..len_seq {
+		if codon_start == 0
+			&& start_t < 0
+			&& ((vpath[t] >= M1_STATE && vpath[t] <= M6_STATE)
+				|| (vpath[t] >= M1_STATE_1 && vpath[t] <= M6_STATE_1)
+				|| vpath[t] == S_STATE
+				|| vpath[t] == S_STATE_1)
 		{
-			alpha[E_STATE][2] = alpha[E_STATE][2] - log30;
+			dna_start_t_withstop = t + 1;
+			dna_start_t = t + 1;
+			start_t = t as isize + 1;
+			first_state = vpath[t];
+			//introduce dna_start_t_withstop YY July 2018
 		}
-	}
 
-	if sequence[2].to_ascii_uppercase() == 'A'
-		&& ((sequence[0].to_ascii_uppercase() == 'T' && sequence[1].to_ascii_uppercase() == 'T')
-			|| (sequence[0].to_ascii_uppercase() == 'C' && sequence[1].to_ascii_uppercase() == 'T')
-			|| (sequence[0].to_ascii_uppercase() == 'T' && sequence[1].to_ascii_uppercase() == 'C'))
-	{
-		alpha[S_STATE_1][0] = max_dbl;
-		alpha[S_STATE_1][1] = max_dbl;
-		alpha[S_STATE_1][2] = alpha[S_STATE][0];
-		path[S_STATE_1][1] = S_STATE_1 as i8;
-		path[S_STATE_1][2] = S_STATE_1 as i8;
-
-		alpha[M3_STATE_1][2] = max_dbl;
-		alpha[M6_STATE_1][2] = max_dbl;
-
-		if sequence[0].to_ascii_uppercase() == 'T' && sequence[1].to_ascii_uppercase() == 'T' {
-			alpha[S_STATE_1][2] = alpha[S_STATE_1][2] - log53;
-		} else if sequence[0].to_ascii_uppercase() == 'C' && sequence[1].to_ascii_uppercase() == 'T'
-		{
-			alpha[S_STATE_1][2] = alpha[S_STATE_1][2] - log16;
-		} else if sequence[0].to_ascii_uppercase() == 'T' && sequence[1].to_ascii_uppercase() == 'C'
+		if codon_start == 0
+			&& (vpath[t] == M1_STATE
+				|| vpath[t] == M4_STATE
+				|| vpath[t] == M1_STATE_1
+				|| vpath[t] == M4_STATE_1)
 		{
-			alpha[S_STATE_1][2] = alpha[S_STATE_1][2] - log30;
-		}
-	}
-	/******************************************************************/
-	/*  fill out the rest of the columns                              */
-	/******************************************************************/
-	let mut num_n = 0;
-	for t in 1..len_seq {
-		let mut from = nt2int(sequence[t - 1]);
-		let mut from0: usize;
-		if t > 2 {
-			from0 = nt2int(sequence[t - 2]);
-		} else {
-			from0 = 2;
-		}
-		let mut to = nt2int(sequence[t]);
+			insert.par_iter_mut().for_each(|p| *p = 0);
 
-		/* if DNA is other than ACGT, do it later */
-		if from == 4 {
-			from = 2;
-		}
-		if from0 == 4 {
-			from0 = 2;
-		}
-		if to == 4 {
-			to = 2;
-			num_n += 1;
-		} else {
-			num_n = 0;
-		}
-		let from2 = from0 * 4 + from;
+			delete.par_iter_mut().for_each(|p| *p = 0);
 
-		/******************/
-		/* M state        */
-		/******************/
-		if t != 0 {
-			for i in M1_STATE..=M6_STATE {
-				if alpha[i][t] < max_dbl {
-					let mut j;
-					if i == M1_STATE {
-						/* from M state */
-						j = M6_STATE;
-						alpha[i][t] =
-							alpha[j][t - 1] - hmm.tr[TR_GG] - hmm.tr[TR_MM] - hmm.e_m[0][from2][to];
-						path[i][t] = j as i8;
+			insert_id = 0;
+			delete_id = 0;
+			dna_id = 0;
+			dna_start_t_withstop = t + 1; //Ye April 21, 2016
+			dna_start_t = t + 1;
+			if (vpath[t] == M1_STATE_1 || vpath[t] == M4_STATE_1)
+				&& t > 2 {
+					dna_start_t_withstop = t - 2;
+				}
+			//printf("Note start dna: t = %d, dna_id %d, dna_f_id %d, add %c\n", t, dna_id, dna_f_id, sequence[t]);
+			start_orf = t + 1;
+			prev_match = vpath[t];
 
-						/* from D state */
-						if !wholegenome {
-							for j in (M1_STATE..=M5_STATE).rev() {
-								let mut num_d: isize = -10;
-								if j >= i {
-									num_d = i as isize - j as isize + 6;
-								} else if j + 1 < i {
-									num_d = i as isize - j as isize;
-								}
-								if num_d > 0 {
-									let temp_alpha = alpha[j][t - 1]
-										- hmm.tr[TR_MD] - hmm.e_m[0][from2][to] - log25
-										* (num_d as f64 - 1.0) - hmm.tr[TR_DD]
-										* (num_d as f64 - 2.0) - hmm.tr[TR_DM];
-									if temp_alpha < alpha[i][t] {
-										alpha[i][t] = temp_alpha;
-										path[i][t] = j as i8;
-									}
-								}
-							}
-						}
+			if vpath[t] < M6_STATE {
+				codon_start = 1;
+			} else {
+				codon_start = -1;
+			}
+		} else if codon_start != 0
+			&& (vpath[t] == E_STATE || vpath[t] == E_STATE_1 || t == len_seq - 1)
+		{
+			/* the end (E) and reverse start (E') states are missing when the gene runs off the end of the sequence */
+			let complete_end = vpath[t] == E_STATE || vpath[t] == E_STATE_1;
+			if complete_end {
+				end_t = t + 3;
+			} else {
+				//end_t=t+1;
+				/* FGS1.12 start: remove incomplete codon */
+				let mut temp_t = t;
+				while vpath[temp_t] != M1_STATE
+					&& vpath[temp_t] != M4_STATE
+					&& vpath[temp_t] != M1_STATE_1
+					&& vpath[temp_t] != M4_STATE_1
+				{
+					dna_id -= 1;
+					temp_t -= 1;
+				}
+				end_t = temp_t; //??? YY July 2018
+				 /* FGS1.12 end: remove incomplete codon */
+			}
 
-						/* from Start state */
-						let temp_alpha = alpha[S_STATE][t - 1] - hmm.e_m[0][from2][to];
-						if temp_alpha < alpha[i][t] {
-							alpha[i][t] = temp_alpha;
-							path[i][t] = S_STATE as i8;
-						}
-					} else {
-						/*i ==M2-M6*/
+			if dna_id > gene_len {
+				let below_threshold =
+					dna_id <= config.classic_min_gene_length() || len_seq <= MIN_SEQUENCE_LENGTH;
+				//these three lines moved here from outside of the loop above, YY July 23, 2018
+				final_score = (path_alpha[end_t - 4]
+					- path_alpha[(start_t + 2) as usize])
+					/ ((end_t as isize - start_t - 5) as f64);
+				frame = start_orf % 3;
+				if frame == 0 {
+					frame = 3;
+				}
 
-						/* from M state */
-						j = i - 1;
-						alpha[i][t] =
-							alpha[j][t - 1] - hmm.tr[TR_MM] - hmm.e_m[i - M1_STATE][from2][to];
-						path[i][t] = j as i8;
+				if codon_start == 1 {
+					if start_t == dna_start_t as isize - 3 {
+						//add complete start codon to dna, Ye April 21, 2016
+						dna_start_t -= 3;
+					}
+					if refine {
+						//add refinement of the start codons here, Ye, April 16, 2016
+						let start_old = start_t;
+						let mut s: isize = 0;
+						//find the optimal start codon upstream of the start codon, up to the first in-frame stop codon
+						let mut e_save = 0.0;
+						let mut s_save = 0; //initialization, YY July 25 2018
 
-						/* from D state */
-						if !wholegenome {
-							for j in (M1_STATE..=M6_STATE).rev() {
-								let mut num_d: isize = -10;
-								if j >= i {
-									num_d = i as isize - j as isize + 6;
-								} else if j + 1 < i {
-									num_d = i as isize - j as isize;
-								}
-								if num_d > 0 {
-									let temp_alpha = alpha[j][t - 1]
-										- hmm.tr[TR_MD] - hmm.e_m[i - M1_STATE][from2]
-										[to] - log25 * (num_d as f64 - 1.0) - hmm.tr
-										[TR_DD]
-										* (num_d as f64 - 2.0) - hmm.tr[TR_DM];
-									if temp_alpha < alpha[i][t] {
-										alpha[i][t] = temp_alpha;
-										path[i][t] = j as i8;
-									}
-								}
+						while !code.is_stop(codon(
+							(start_old - 1 - s) as usize,
+							(start_old - s) as usize,
+							(start_old + 1 - s) as usize,
+						)) && start_old - 1 - s - 35 >= 0
+							&& refine_window.is_some_and(|window| s as usize <= window)
+						{
+							if config.is_start(codon(
+								(start_old - 1 - s) as usize,
+								(start_old - s) as usize,
+								(start_old + 1 - s) as usize,
+							)) {
+								let utr = &nts[(start_old - 1 - s - 30) as usize
+									..(start_old - 1 - s - 30 + 63) as usize];
+								let freq_sum = -(0..61)
+									.map(|j| train.start[cg][j][trinucleotide_nts(utr[j], utr[j + 1], utr[j + 2])])
+									.sum::<f64>();
+								if s == 0 {
+									e_save = freq_sum;
+									s_save = 0;
+								} else if freq_sum < e_save {
+									e_save = freq_sum;
+									s_save = -s;
+								} //positive chain, upstream s_save = -1 * 3
 							}
+							s += 3;
+						}
+						//update start_t YY July 2018
+						if s_save != 0 {
+							dna_start_t = (dna_start_t as isize + s_save) as usize;
 						}
 					}
 
-					/* from I state */
-					if i == M1_STATE {
-						j = I6_STATE;
-					} else {
-						j = I1_STATE + (i - M1_STATE - 1);
-					}
+					let dna_end_t = end_t;
 
-					/* to avoid stop codon */
-					if t < 2 {
-					} else if (i == M2_STATE || i == M5_STATE)
-						&& sequence[temp_i[j - I1_STATE]].to_ascii_uppercase() == 'T'
-						&& t < len_seq - 1 && ((sequence[t].to_ascii_uppercase() == 'A'
-						&& sequence[t + 1].to_ascii_uppercase() == 'A')
-						|| (sequence[t].to_ascii_uppercase() == 'A'
-							&& sequence[t + 1].to_ascii_uppercase() == 'G')
-						|| (sequence[t].to_ascii_uppercase() == 'G'
-							&& sequence[t + 1].to_ascii_uppercase() == 'A'))
-					{
-					} else if (i == M3_STATE || i == M6_STATE)
-						&& temp_i[j - I1_STATE] as isize - 1 > 0
-						&& sequence[temp_i[j - I1_STATE] - 1].to_ascii_uppercase() == 'T'
-						&& ((sequence[temp_i[j - I1_STATE]].to_ascii_uppercase() == 'A'
-							&& sequence[t].to_ascii_uppercase() == 'A')
-							|| (sequence[temp_i[j - I1_STATE]].to_ascii_uppercase() == 'A'
-								&& sequence[t].to_ascii_uppercase() == 'G')
-							|| (sequence[temp_i[j - I1_STATE]].to_ascii_uppercase() == 'G'
-								&& sequence[t].to_ascii_uppercase() == 'A'))
-					{
-					} else {
-						let temp_alpha = alpha[j][t - 1] - hmm.tr[TR_IM] - log25;
-						if temp_alpha < alpha[i][t] {
-							alpha[i][t] = temp_alpha;
-							path[i][t] = j as i8;
+					let protein = get_protein(
+						&sequence[dna_start_t - 1..dna_end_t].chars().collect::<Vec<char>>(),
+						true,
+						config,
+					);
+
+					let mut out = Out {
+						dna_start_t,
+						dna_end_t,
+						frame,
+						final_score,
+						insert: Vec::new(),
+						delete: Vec::new(),
+						protein: protein.iter().collect::<String>(),
+						forward: true,
+						below_threshold,
+						partial_start: first_state != S_STATE,
+						partial_end: !complete_end,
+						posterior: None,
+						dna: sequence[dna_start_t - 1..dna_end_t].to_string(),
+					};
+
+					out.insert.extend_from_slice(&insert[..insert_id]);
+					out.delete.extend_from_slice(&delete[..delete_id]);
+					prediction.outs.push(out);
+				} else if codon_start == -1 {
+					if refine {
+						//add refinement of the start codons here, Ye, April 16, 2016
+						let end_old = end_t; //reverse
+						let mut s = 0;
+						//find the optimal start codon upstream of the start codon, up to the first in-frame stop codon
+						let mut e_save = 0.0;
+						let mut s_save = 0; //initialization, YY July 25, 2018
+						while !code.is_reverse_stop(codon(end_old - 3 + s, end_old - 2 + s, end_old - 1 + s))
+							&& end_old + s >= 33
+							&& end_old - 2 + s + 35 < len_seq
+							&& refine_window.is_some_and(|window| s <= window)
+						{
+							if config.is_reverse_start(codon(end_old - 3 + s, end_old - 2 + s, end_old - 1 + s)) {
+								let utr = &nts[(end_old - 3 + s - 30)..(end_old - 3 + s - 30 + 64)];
+								let freq_sum = -(0..61)
+									.map(|j| train.stop1[cg][j][trinucleotide_nts(utr[j], utr[j + 1], utr[j + 2])])
+									.sum::<f64>();
+								if s == 0 || freq_sum < e_save {
+									e_save = freq_sum;
+									s_save = s;
+								} //negative chain, s_save = s, add, YY July 2018
+							}
+							s += 3;
 						}
+						//update end_t
+						end_t = end_old + s_save;
 					}
-				}
-			}
-		}
 
-		/******************/
-		/* I state        */
-		/******************/
-		if t != 0 {
-			for i in I1_STATE..=I6_STATE {
-				let mut j;
-				/* from I state */
-				j = i;
-				alpha[i][t] = alpha[j][t - 1] - hmm.tr[TR_II] - hmm.tr_i_i[from][to];
-				path[i][t] = j as i8;
+					let dna_end_t = end_t;
+
+					//update dna before calling get_protein, YY July 2018
+					//use dna_end_t & dna_start_w_withstop to avoid incomplete codons & include start/stop codons
+
+					let dna: Vec<char> = sequence[dna_start_t_withstop - 1..dna_end_t].chars().collect();
+					let protein = get_protein(
+						&dna,
+						false,
+						config,
+					); //YY July 18, 2018, introduce adjust
+
+					let dna1_out = get_rc_dna(&dna);
+
+					let mut out = Out {
+						dna_start_t: dna_start_t_withstop,
+						dna_end_t,
+						frame,
+						final_score,
+						insert: Vec::new(),
+						delete: Vec::new(),
+						protein: protein.iter().collect::<String>(),
+						forward: false,
+						below_threshold,
+						partial_start: !complete_end,
+						partial_end: first_state != S_STATE_1,
+						posterior: None,
+						dna: dna1_out.iter().collect::<String>(),
+					};
+
+					out.insert.extend_from_slice(&insert[..insert_id]);
+					out.delete.extend_from_slice(&delete[..delete_id]);
+					prediction.outs.push(out);
+				}
+			}
+			codon_start = 0;
+			start_t = -1;
+			dna_id = 0;
+		} else if codon_start != 0
+			&& ((vpath[t] >= M1_STATE && vpath[t] <= M6_STATE)
+				|| (vpath[t] >= M1_STATE_1 && vpath[t] <= M6_STATE_1))
+			&& (vpath[t] as i8) - (prev_match as i8) < 6
+		{
+			let out_nt = if vpath[t] < prev_match {
+				vpath[t] + 6 - prev_match
+			} else {
+				vpath[t] - prev_match
+			};
+			for kk in 0..out_nt {
+				/* for deleted nt in reads */
+				dna_id += 1;
+				//printf("dna_id %d, dna-len %d\n", dna_id, strlen(dna));
+				if kk > 0 {
+					delete[delete_id] = t + 1 ;
+					delete_id += 1;
+				}
+			}
+			//printf("dna_id %d, add %d %c dna-len %d\n", dna_id, t, sequence[t], strlen(dna));
+			prev_match = vpath[t];
+		} else if codon_start != 0
+			&& ((vpath[t] >= I1_STATE && vpath[t] <= I6_STATE)
+				|| (vpath[t] >= I1_STATE_1 && vpath[t] <= I6_STATE_1))
+		{
+			insert[insert_id] = t + 1 ;
+			insert_id += 1;
+		} else if codon_start != 0 && vpath[t] == R_STATE {
+			/* for long NNNNNNNNN, pretend R state */
+			codon_start = 0;
+			start_t = -1;
+			dna_id = 0;
+		}
+	}
+
+	if let Some(matrix) = &matrix {
+		let posteriors =
+			forward_backward(hmm, &nts, error_prob.as_deref(), config, &matrix.alpha, &matrix.path);
+		for out in &mut prediction.outs {
+			out.posterior = Some(posteriors.gene(out));
+		}
+		if config.posteriors() == PosteriorMode::Bases {
+			prediction.posteriors = Some(posteriors.into_bases());
+		}
+	}
+	prediction
+}
+
+/**
+ * Number of columns kept in memory while filling in the matrices with checkpoints: a column reads the previous
+ * column (and the path of up to five columns back) and writes up to two columns ahead.
+ */
+const RING_COLUMNS: usize = 8;
+
+/**
+ * A row of the viterbi matrices, either holding every column of the sequence or only the last columns (a ring
+ * buffer with a power of two length, column t is stored at t modulo the length).
+ */
+#[derive(Clone)]
+pub(crate) struct Window<T> {
+	data: Vec<T>,
+	mask: usize,
+}
+
+impl<T: Copy> Window<T> {
+	fn full(len: usize, value: T) -> Window<T> {
+		Window {
+			data: vec![value; len],
+			mask: usize::MAX,
+		}
+	}
+
+	fn ring(len: usize, value: T) -> Window<T> {
+		Window {
+			data: vec![value; len],
+			mask: len - 1,
+		}
+	}
+}
+
+impl<T> Index<usize> for Window<T> {
+	type Output = T;
+
+	#[inline]
+	fn index(&self, t: usize) -> &T {
+		&self.data[t & self.mask]
+	}
+}
+
+impl<T> IndexMut<usize> for Window<T> {
+	#[inline]
+	fn index_mut(&mut self, t: usize) -> &mut T {
+		&mut self.data[t & self.mask]
+	}
+}
+
+/**
+ * The state of the viterbi recursion: the alpha (cost) and path matrices, the last insertions and the length of the
+ * current run of unknown bases.
+ */
+#[derive(Clone)]
+pub(crate) struct Matrix {
+	pub alpha: Vec<Window<f64>>,
+	pub path: Vec<Window<i8>>,
+	temp_i: [usize; 6],
+	temp_i_1: [usize; 6],
+	num_n: usize,
+	ring: bool,
+}
+
+impl Matrix {
+	fn full(len_seq: usize) -> Matrix {
+		Matrix::new(Window::full(len_seq, 0.0), Window::full(len_seq, 0), false)
+	}
+
+	fn ring(columns: usize) -> Matrix {
+		Matrix::new(Window::ring(columns, 0.0), Window::ring(columns, 0), true)
+	}
+
+	fn new(alpha: Window<f64>, path: Window<i8>, ring: bool) -> Matrix {
+		Matrix {
+			alpha: vec![alpha; NUM_STATE],
+			path: vec![path; NUM_STATE],
+			temp_i: [0; 6],
+			temp_i_1: [0; 6],
+			num_n: 0,
+			ring,
+		}
+	}
+
+	/*
+	 * Helper method to copy the recursion state and the columns from..to of another matrix.
+	 */
+	fn restore(&mut self, other: &Matrix, from: usize, to: usize) {
+		for (row, other_row) in self.alpha.iter_mut().zip(&other.alpha) {
+			for t in from..to {
+				row[t] = other_row[t];
+			}
+		}
+		for (row, other_row) in self.path.iter_mut().zip(&other.path) {
+			for t in from..to {
+				row[t] = other_row[t];
+			}
+		}
+		self.temp_i = other.temp_i;
+		self.temp_i_1 = other.temp_i_1;
+		self.num_n = other.num_n;
+	}
+}
+
+/**
+ * The parameters of the viterbi recursion for a single sequence.
+ */
+#[derive(Clone, Copy)]
+struct Recursion<'a> {
+	hmm: &'a HMM,
+	config: &'a PredictionConfig,
+	code: &'a GeneticCode,
+	wholegenome: bool,
+	/* the encoded sequence (see dna_helpers::encode_bases) */
+	nts: &'a [u8],
+	error_prob: Option<&'a [f64]>,
+	len_seq: usize,
+	log53: f64,
+	log16: f64,
+	log30: f64,
+	log25: f64,
+	log95: f64,
+	log54: f64,
+	log83: f64,
+	log07: f64,
+	max_dbl: f64,
+}
+
+impl<'a> Recursion<'a> {
+	fn new(
+		hmm: &'a HMM,
+		config: &'a PredictionConfig,
+		nts: &'a [u8],
+		error_prob: Option<&'a [f64]>,
+	) -> Recursion<'a> {
+		Recursion {
+			hmm,
+			config,
+			code: config.genetic_code(),
+			wholegenome: config.wholegenome(),
+			nts,
+			error_prob,
+			len_seq: nts.len(),
+			log53: 0.53_f64.ln(),
+			log16: 0.16_f64.ln(),
+			log30: 0.30_f64.ln(),
+			log25: 0.25_f64.ln(),
+			log95: 0.95_f64.ln(),
+			log54: 0.54_f64.ln(),
+			log83: 0.83_f64.ln(),
+			log07: 0.07_f64.ln(),
+			max_dbl: f64::INFINITY,
+		}
+	}
+
+	/*
+	 * Fills in the first column of the matrices (and the codon states starting at the first base).
+	 */
+	fn init(&self, matrix: &mut Matrix) {
+		let Recursion { hmm, code, nts, log53, log16, log30, max_dbl, .. } = *self;
+		let Matrix { alpha, path, .. } = matrix;
+		let codon = |a: usize, b: usize, c: usize| codon_index(nts[a] as usize, nts[b] as usize, nts[c] as usize);
+
+		alpha.par_iter_mut().enumerate().for_each(|(i, alpha)| {
+			alpha[0] = -hmm.initial_state[i];
+		});
+
+
+		/* stop state */
+		if code.is_stop(codon(0, 1, 2)) {
+			alpha[E_STATE][0] = max_dbl;
+			alpha[E_STATE][1] = max_dbl;
+			path[E_STATE][1] = E_STATE as i8;
+			path[E_STATE][2] = E_STATE as i8;
+
+			alpha[M6_STATE][2] = max_dbl;
+			alpha[M5_STATE][1] = max_dbl;
+			alpha[M4_STATE][0] = max_dbl;
+			alpha[M3_STATE][2] = max_dbl;
+			alpha[M2_STATE][1] = max_dbl;
+			alpha[M1_STATE][0] = max_dbl;
+
+			alpha[E_STATE][2] -=
+				stop_codon_prob(nts[0], nts[1], nts[2], [log53, log16, log30]);
+		}
+
+		if code.is_reverse_stop(codon(0, 1, 2)) {
+			alpha[S_STATE_1][0] = max_dbl;
+			alpha[S_STATE_1][1] = max_dbl;
+			alpha[S_STATE_1][2] = alpha[S_STATE][0];
+			path[S_STATE_1][1] = S_STATE_1 as i8;
+			path[S_STATE_1][2] = S_STATE_1 as i8;
+
+			alpha[M3_STATE_1][2] = max_dbl;
+			alpha[M6_STATE_1][2] = max_dbl;
+
+			alpha[S_STATE_1][2] -= stop_codon_prob(
+				complement_nt(nts[2]),
+				complement_nt(nts[1]),
+				complement_nt(nts[0]),
+				[log53, log16, log30],
+			);
+		}
+	}
+
+	/*
+	 * Fills in column t (t > 0) of the matrices, columns t + 1 and t + 2 are partially filled in for the codon
+	 * states.
+	 */
+	fn column(&self, matrix: &mut Matrix, t: usize) {
+		let Recursion {
+			hmm,
+			config,
+			code,
+			wholegenome,
+			nts,
+			error_prob,
+			len_seq,
+			log53: _,
+			log16,
+			log30,
+			log25,
+			log95,
+			log54,
+			log83,
+			log07,
+			max_dbl,
+		} = *self;
+		let Matrix {
+			alpha,
+			path,
+			temp_i,
+			temp_i_1,
+			num_n,
+			ring,
+		} = matrix;
+		let codon = |a: usize, b: usize, c: usize| codon_index(nts[a] as usize, nts[b] as usize, nts[c] as usize);
+		if *ring && t + 2 < len_seq {
+			/* the columns of a ring buffer are reused */
+			for (alpha, path) in alpha.iter_mut().zip(path.iter_mut()) {
+				alpha[t + 2] = 0.0;
+				path[t + 2] = 0;
+			}
+		}
+
+
+		/* if DNA is other than ACGT, do it later */
+		if nts[t] == 4 {
+			*num_n += 1;
+		} else {
+			*num_n = 0;
+		}
+		let Column {
+			from,
+			to,
+			e_m,
+			e_m_1,
+			tr_mi,
+			tr_md,
+		} = Column::new(hmm, nts, t, error_prob);
+
+		/******************/
+		/* M state        */
+		/******************/
+		if t != 0 {
+			for i in M1_STATE..=M6_STATE {
+				if alpha[i][t] < max_dbl {
+					let mut j;
+					if i == M1_STATE {
+						/* from M state */
+						j = M6_STATE;
+						alpha[i][t] = alpha[j][t - 1] - hmm.tr[TR_GG] - hmm.tr[TR_MM] - e_m[0];
+						path[i][t] = j as i8;
+
+						/* from D state */
+						if !wholegenome {
+							for j in (M1_STATE..=M5_STATE).rev() {
+								let mut num_d: isize = -10;
+								if j >= i {
+									num_d = i as isize - j as isize + 6;
+								} else if j + 1 < i {
+									num_d = i as isize - j as isize;
+								}
+								if num_d > 0 {
+									let temp_alpha = alpha[j][t - 1]
+										- tr_md - e_m[0] - log25
+										* (num_d as f64 - 1.0) - hmm.tr[TR_DD]
+										* (num_d as f64 - 2.0) - hmm.tr[TR_DM];
+									if temp_alpha < alpha[i][t] {
+										alpha[i][t] = temp_alpha;
+										path[i][t] = j as i8;
+									}
+								}
+							}
+						}
+
+						/* from Start state */
+						let temp_alpha = alpha[S_STATE][t - 1] - e_m[0];
+						if temp_alpha < alpha[i][t] {
+							alpha[i][t] = temp_alpha;
+							path[i][t] = S_STATE as i8;
+						}
+					} else {
+						/*i ==M2-M6*/
+
+						/* from M state */
+						j = i - 1;
+						alpha[i][t] = alpha[j][t - 1] - hmm.tr[TR_MM] - e_m[i - M1_STATE];
+						path[i][t] = j as i8;
+
+						/* from D state */
+						if !wholegenome {
+							for j in (M1_STATE..=M6_STATE).rev() {
+								let mut num_d: isize = -10;
+								if j >= i {
+									num_d = i as isize - j as isize + 6;
+								} else if j + 1 < i {
+									num_d = i as isize - j as isize;
+								}
+								if num_d > 0 {
+									let temp_alpha = alpha[j][t - 1]
+										- tr_md - e_m[i - M1_STATE] - log25
+										* (num_d as f64 - 1.0) - hmm.tr[TR_DD]
+										* (num_d as f64 - 2.0) - hmm.tr[TR_DM];
+									if temp_alpha < alpha[i][t] {
+										alpha[i][t] = temp_alpha;
+										path[i][t] = j as i8;
+									}
+								}
+							}
+						}
+					}
+
+					/* from I state */
+					if i == M1_STATE {
+						j = I6_STATE;
+					} else {
+						j = I1_STATE + (i - M1_STATE - 1);
+					}
+
+					/* to avoid stop codon */
+					let stop_codon = t < 2
+						|| ((i == M2_STATE || i == M5_STATE)
+							&& t < len_seq - 1
+							&& code.is_stop(codon(temp_i[j - I1_STATE], t, t + 1)))
+						|| ((i == M3_STATE || i == M6_STATE)
+							&& temp_i[j - I1_STATE] as isize - 1 > 0
+							&& code.is_stop(codon(temp_i[j - I1_STATE] - 1, temp_i[j - I1_STATE], t)));
+					if !stop_codon {
+						let temp_alpha = alpha[j][t - 1] - hmm.tr[TR_IM] - log25;
+						if temp_alpha < alpha[i][t] {
+							alpha[i][t] = temp_alpha;
+							path[i][t] = j as i8;
+						}
+					}
+				}
+			}
+		}
+
+		/******************/
+		/* I state        */
+		/******************/
+		if t != 0 {
+			for i in I1_STATE..=I6_STATE {
+				let mut j;
+				/* from I state */
+				j = i;
+				alpha[i][t] = alpha[j][t - 1] - hmm.tr[TR_II] - hmm.tr_i_i[from][to];
+				path[i][t] = j as i8;
 
 				/* from M state */
 				j = i - I1_STATE + M1_STATE;
-				let temp_alpha;
-				if i == I6_STATE {
-					temp_alpha =
-						alpha[j][t - 1] - hmm.tr[TR_GG] - hmm.tr[TR_MI] - hmm.tr_m_i[from][to];
+				let temp_alpha = if i == I6_STATE {
+					alpha[j][t - 1] - hmm.tr[TR_GG] - tr_mi - hmm.tr_m_i[from][to]
 				} else {
-					temp_alpha = alpha[j][t - 1] - hmm.tr[TR_MI] - hmm.tr_m_i[from][to];
-				}
+					alpha[j][t - 1] - tr_mi - hmm.tr_m_i[from][to]
+				};
 				if temp_alpha < alpha[i][t] {
 					alpha[i][t] = temp_alpha;
 					path[i][t] = j as i8;
@@ -315,27 +814,17 @@ pub fn viterbi(
 		for i in M1_STATE_1..=M6_STATE_1 {
 			let mut j;
 			if (i == M1_STATE_1 || i == M4_STATE_1)
-				&& t >= 3 && ((sequence[t - 3].to_ascii_uppercase() == 'T'
-				&& sequence[t - 2].to_ascii_uppercase() == 'T'
-				&& sequence[t - 1].to_ascii_uppercase() == 'A')
-				|| (sequence[t - 3].to_ascii_uppercase() == 'C'
-					&& sequence[t - 2].to_ascii_uppercase() == 'T'
-					&& sequence[t - 1].to_ascii_uppercase() == 'A')
-				|| (sequence[t - 3].to_ascii_uppercase() == 'T'
-					&& sequence[t - 2].to_ascii_uppercase() == 'C'
-					&& sequence[t - 1].to_ascii_uppercase() == 'A'))
+				&& t >= 3 && code.is_reverse_stop(codon(t - 3, t - 2, t - 1))
 			{
 				/* from Start state  since this is actually stop codon in minus strand */
-				alpha[i][t] = alpha[S_STATE_1][t - 1] - hmm.e_m_1[i - M1_STATE_1][from2][to];
+				alpha[i][t] = alpha[S_STATE_1][t - 1] - e_m_1[i - M1_STATE_1];
 				path[i][t] = S_STATE_1 as i8;
 			} else {
 				if t != 0 {
 					if i == M1_STATE_1 {
 						/* from M state */
 						j = M6_STATE_1;
-						alpha[i][t] = alpha[j][t - 1]
-							- hmm.tr[TR_GG] - hmm.tr[TR_MM]
-							- hmm.e_m_1[0][from2][to];
+						alpha[i][t] = alpha[j][t - 1] - hmm.tr[TR_GG] - hmm.tr[TR_MM] - e_m_1[0];
 						path[i][t] = j as i8;
 
 						/* from D state */
@@ -349,7 +838,7 @@ pub fn viterbi(
 								}
 								if num_d > 0 {
 									let temp_alpha = alpha[j][t - 1]
-										- hmm.tr[TR_MD] - hmm.e_m_1[0][from2][to]
+										- tr_md - e_m_1[0]
 										- log25 * (num_d as f64 - 1.0) - hmm.tr[TR_DD]
 										* (num_d as f64 - 2.0) - hmm.tr[TR_DM];
 									if temp_alpha < alpha[i][t] {
@@ -362,8 +851,7 @@ pub fn viterbi(
 					} else {
 						/* from M state */
 						j = i - 1;
-						alpha[i][t] =
-							alpha[j][t - 1] - hmm.tr[TR_MM] - hmm.e_m_1[i - M1_STATE_1][from2][to];
+						alpha[i][t] = alpha[j][t - 1] - hmm.tr[TR_MM] - e_m_1[i - M1_STATE_1];
 						path[i][t] = j as i8;
 
 						/* from D state */
@@ -377,10 +865,9 @@ pub fn viterbi(
 								}
 								if num_d > 0 {
 									let temp_alpha = alpha[j][t - 1]
-										- hmm.tr[TR_MD] - hmm.e_m_1[i - M1_STATE_1]
-										[from2][to] - log25 * (num_d as f64 - 1.0)
-										- hmm.tr[TR_DD] * (num_d as f64 - 2.0) - hmm
-										.tr[TR_DM];
+										- tr_md - e_m_1[i - M1_STATE_1]
+										- log25 * (num_d as f64 - 1.0) - hmm.tr[TR_DD]
+										* (num_d as f64 - 2.0) - hmm.tr[TR_DM];
 									if temp_alpha < alpha[i][t] {
 										alpha[i][t] = temp_alpha;
 										path[i][t] = j as i8;
@@ -398,27 +885,18 @@ pub fn viterbi(
 					}
 
 					/* to avoid stop codon */
-					if t < 2 || t == len_seq - 1 {
-					} else if (i == M2_STATE_1 || i == M5_STATE_1)
-						&& sequence[t + 1].to_ascii_uppercase() == 'A'
-						&& ((sequence[temp_i_1[j - I1_STATE_1]].to_ascii_uppercase() == 'T'
-							&& sequence[t].to_ascii_uppercase() == 'T')
-							|| (sequence[temp_i_1[j - I1_STATE_1]].to_ascii_uppercase() == 'C'
-								&& sequence[t].to_ascii_uppercase() == 'T')
-							|| (sequence[temp_i_1[j - I1_STATE_1]].to_ascii_uppercase() == 'T'
-								&& sequence[t].to_ascii_uppercase() == 'C'))
-					{
-					} else if (i == M3_STATE_1 || i == M6_STATE_1)
-						&& sequence[t].to_ascii_uppercase() == 'A'
-						&& temp_i_1[j - I1_STATE_1] as isize - 1 > 0
-						&& ((sequence[temp_i_1[j - I1_STATE_1] - 1].to_ascii_uppercase() == 'T'
-							&& sequence[temp_i_1[j - I1_STATE_1]].to_ascii_uppercase() == 'T')
-							|| (sequence[temp_i_1[j - I1_STATE_1] - 1].to_ascii_uppercase() == 'C'
-								&& sequence[temp_i_1[j - I1_STATE_1]].to_ascii_uppercase() == 'T')
-							|| (sequence[temp_i_1[j - I1_STATE_1] - 1].to_ascii_uppercase() == 'T'
-								&& sequence[temp_i_1[j - I1_STATE_1]].to_ascii_uppercase() == 'C'))
-					{
-					} else {
+					let stop_codon = t < 2
+						|| t == len_seq - 1
+						|| ((i == M2_STATE_1 || i == M5_STATE_1)
+							&& code.is_reverse_stop(codon(temp_i_1[j - I1_STATE_1], t, t + 1)))
+						|| ((i == M3_STATE_1 || i == M6_STATE_1)
+							&& temp_i_1[j - I1_STATE_1] as isize - 1 > 0
+							&& code.is_reverse_stop(codon(
+								temp_i_1[j - I1_STATE_1] - 1,
+								temp_i_1[j - I1_STATE_1],
+								t,
+							)));
+					if !stop_codon {
 						let temp_alpha = alpha[j][t - 1] - hmm.tr[TR_IM] - log25;
 						if temp_alpha < alpha[i][t] {
 							alpha[i][t] = temp_alpha;
@@ -446,13 +924,11 @@ pub fn viterbi(
 					&& path[S_STATE_1][t - 5] != R_STATE as i8
 				{
 					j = i - I1_STATE_1 + M1_STATE_1;
-					let temp_alpha;
-					if i == I6_STATE_1 {
-						temp_alpha =
-							alpha[j][t - 1] - hmm.tr[TR_GG] - hmm.tr[TR_MI] - hmm.tr_m_i[from][to];
+					let temp_alpha = if i == I6_STATE_1 {
+						alpha[j][t - 1] - hmm.tr[TR_GG] - tr_mi - hmm.tr_m_i[from][to]
 					} else {
-						temp_alpha = alpha[j][t - 1] - hmm.tr[TR_MI] - hmm.tr_m_i[from][to];
-					}
+						alpha[j][t - 1] - tr_mi - hmm.tr_m_i[from][to]
+					};
 					if temp_alpha < alpha[i][t] {
 						alpha[i][t] = temp_alpha;
 						path[i][t] = j as i8;
@@ -492,15 +968,7 @@ pub fn viterbi(
 			alpha[E_STATE][t] = max_dbl;
 			path[E_STATE][t] = NOSTATE;
 
-			if t < len_seq - 2
-				&& sequence[t].to_ascii_uppercase() == 'T'
-				&& ((sequence[t + 1].to_ascii_uppercase() == 'A'
-					&& sequence[t + 2].to_ascii_uppercase() == 'A')
-					|| (sequence[t + 1].to_ascii_uppercase() == 'A'
-						&& sequence[t + 2].to_ascii_uppercase() == 'G')
-					|| (sequence[t + 1].to_ascii_uppercase() == 'G'
-						&& sequence[t + 2].to_ascii_uppercase() == 'A'))
-			{
+			if t < len_seq - 2 && code.is_stop(codon(t, t + 1, t + 2)) {
 				alpha[E_STATE][t + 2] = max_dbl;
 				/* transition from frame4,frame5,and frame6 */
 				let mut temp_alpha = alpha[M6_STATE][t - 1] - hmm.tr[TR_GE];
@@ -528,76 +996,11 @@ pub fn viterbi(
 				alpha[M2_STATE][t + 1] = max_dbl;
 				alpha[M1_STATE][t] = max_dbl;
 
-				if sequence[t + 1].to_ascii_uppercase() == 'A'
-					&& sequence[t + 2].to_ascii_uppercase() == 'A'
-				{
-					alpha[E_STATE][t + 2] = alpha[E_STATE][t + 2] - log54;
-				} else if sequence[t + 1].to_ascii_uppercase() == 'A'
-					&& sequence[t + 2].to_ascii_uppercase() == 'G'
-				{
-					alpha[E_STATE][t + 2] = alpha[E_STATE][t + 2] - log16;
-				} else if sequence[t + 1].to_ascii_uppercase() == 'G'
-					&& sequence[t + 2].to_ascii_uppercase() == 'A'
-				{
-					alpha[E_STATE][t + 2] = alpha[E_STATE][t + 2] - log30;
-				}
+				alpha[E_STATE][t + 2] -=
+					stop_codon_prob(nts[t], nts[t + 1], nts[t + 2], [log54, log16, log30]);
 
 				/* adjustment based on probability distribution */
-				let mut start_freq = 0.0;
-
-				let mut sub_sum = 0.0;
-
-				if t >= 60 {
-					/* bug reported by Yu-Wei */
-					start_freq -= (3..=60)
-						.map(|i| {
-							let i = -(i as isize);
-							let mut result = 0.0;
-							if (t as isize) + i + 2 < len_seq as isize {
-								let idx: usize = (i + 60) as usize;
-								result = hmm.tr_e[idx][trinucleotide(
-									&sequence[(t as isize + i) as usize],
-									&sequence[(t as isize + i + 1) as usize],
-									&sequence[(t as isize + i + 2) as usize],
-								)];
-							}
-							result
-						})
-						.sum::<f64>();
-				} else {
-					sub_sum += (3..=t)
-						.map(|i| {
-							let i = -(i as isize);
-							let mut result = 0.0;
-							if t as isize + i + 2 < len_seq as isize {
-								let idx: usize = (i + 60) as usize;
-								result = hmm.tr_e[idx][trinucleotide(
-									&sequence[(t as isize + i) as usize],
-									&sequence[(t as isize + i + 1) as usize],
-									&sequence[(t as isize + i + 2) as usize],
-								)];
-							}
-							result
-						})
-						.sum::<f64>();
-
-					sub_sum = sub_sum * 58.0 / (-3.0 + t as f64 + 1.0);
-					start_freq -= sub_sum;
-				}
-
-				let h_kd = hmm.e_dist[2]
-					* (-1.0 * (start_freq - hmm.e_dist[1]).powi(2) / (2.0 * hmm.e_dist[0].powi(2)))
-						.exp();
-				let r_kd = hmm.e_dist[5]
-					* (-1.0 * (start_freq - hmm.e_dist[4]).powi(2) / (2.0 * hmm.e_dist[3].powi(2)))
-						.exp();
-				let mut p_kd = h_kd / (h_kd + r_kd);
-				if p_kd < 0.01 {
-					p_kd = 0.01;
-				} else if p_kd > 0.99 {
-					p_kd = 0.99;
-				}
-				alpha[E_STATE][t + 2] = alpha[E_STATE][t + 2] - p_kd.ln();
+				alpha[E_STATE][t + 2] -= stop_adjustment(hmm, nts, t).ln();
 			}
 		}
 
@@ -609,15 +1012,7 @@ pub fn viterbi(
 			alpha[S_STATE_1][t] = max_dbl;
 			path[S_STATE_1][t] = NOSTATE;
 
-			if t < len_seq - 2
-				&& sequence[t + 2].to_ascii_uppercase() == 'A'
-				&& ((sequence[t].to_ascii_uppercase() == 'T'
-					&& sequence[t + 1].to_ascii_uppercase() == 'T')
-					|| (sequence[t].to_ascii_uppercase() == 'C'
-						&& sequence[t + 1].to_ascii_uppercase() == 'T')
-					|| (sequence[t].to_ascii_uppercase() == 'T'
-						&& sequence[t + 1].to_ascii_uppercase() == 'C'))
-			{
+			if t < len_seq - 2 && code.is_reverse_stop(codon(t, t + 1, t + 2)) {
 				alpha[S_STATE_1][t] = max_dbl;
 				path[S_STATE_1][t] = R_STATE as i8;
 				alpha[S_STATE_1][t + 1] = max_dbl;
@@ -640,51 +1035,15 @@ pub fn viterbi(
 				alpha[M3_STATE_1][t + 2] = max_dbl;
 				alpha[M6_STATE_1][t + 2] = max_dbl;
 
-				if sequence[t].to_ascii_uppercase() == 'T'
-					&& sequence[t + 1].to_ascii_uppercase() == 'T'
-				{
-					alpha[S_STATE_1][t + 2] = alpha[S_STATE_1][t + 2] - log54;
-				} else if sequence[t].to_ascii_uppercase() == 'C'
-					&& sequence[t + 1].to_ascii_uppercase() == 'T'
-				{
-					alpha[S_STATE_1][t + 2] = alpha[S_STATE_1][t + 2] - log16;
-				} else if sequence[t].to_ascii_uppercase() == 'T'
-					&& sequence[t + 1].to_ascii_uppercase() == 'C'
-				{
-					alpha[S_STATE_1][t + 2] = alpha[S_STATE_1][t + 2] - log30;
-				}
+				alpha[S_STATE_1][t + 2] -= stop_codon_prob(
+					complement_nt(nts[t + 2]),
+					complement_nt(nts[t + 1]),
+					complement_nt(nts[t]),
+					[log54, log16, log30],
+				);
 
 				/* adjustment based on probability distribution */
-				let mut start_freq = 0.0;
-				start_freq -= (3..=60)
-					.map(|i: i32| {
-						let i = i as usize;
-						let mut result: f64 = 0.0;
-						if t + i + 2 < len_seq {
-							result = hmm.tr_s_1[i - 3][trinucleotide(
-								&sequence[t + i],
-								&sequence[t + i + 1],
-								&sequence[t + i + 2],
-							)];
-						}
-						result
-					})
-					.sum::<f64>();
-				let h_kd = hmm.s1_dist[2]
-					* (-1.0 * (start_freq - hmm.s1_dist[1]).powi(2)
-						/ (2.0 * hmm.s1_dist[0].powi(2)))
-					.exp();
-				let r_kd = hmm.s1_dist[5]
-					* (-1.0 * (start_freq - hmm.s1_dist[4]).powi(2)
-						/ (2.0 * hmm.s1_dist[3].powi(2)))
-					.exp();
-				let mut p_kd = h_kd / (h_kd + r_kd);
-				if p_kd < 0.01 {
-					p_kd = 0.01;
-				} else if p_kd > 0.99 {
-					p_kd = 0.99;
-				}
-				alpha[S_STATE_1][t + 2] = alpha[S_STATE_1][t + 2] - (p_kd).ln();
+				alpha[S_STATE_1][t + 2] -= reverse_stop_adjustment(hmm, nts, t).ln();
 			}
 		}
 
@@ -695,13 +1054,7 @@ pub fn viterbi(
 			alpha[S_STATE][t] = max_dbl;
 			path[S_STATE][t] = NOSTATE;
 
-			if t < len_seq - 2
-				&& sequence[t + 1].to_ascii_uppercase() == 'T'
-				&& sequence[t + 2].to_ascii_uppercase() == 'G'
-				&& (sequence[t].to_ascii_uppercase() == 'A'
-					|| sequence[t].to_ascii_uppercase() == 'G'
-					|| sequence[t].to_ascii_uppercase() == 'T')
-			{
+			if t < len_seq - 2 && config.is_start(codon(t, t + 1, t + 2)) {
 				alpha[S_STATE][t] = max_dbl;
 				alpha[S_STATE][t + 1] = max_dbl;
 				alpha[S_STATE][t + 2] = alpha[R_STATE][t - 1] - hmm.tr[TR_RS];
@@ -721,58 +1074,15 @@ pub fn viterbi(
 					path[S_STATE][t] = E_STATE_1 as i8;
 				}
 
-				if sequence[t].to_ascii_uppercase() == 'A' {
-					alpha[S_STATE][t + 2] = alpha[S_STATE][t + 2] - log83;
-				} else if sequence[t].to_ascii_uppercase() == 'G' {
-					alpha[S_STATE][t + 2] = alpha[S_STATE][t + 2] - (0.10 as f64).ln();
-				} else if sequence[t].to_ascii_uppercase() == 'T' {
-					alpha[S_STATE][t + 2] = alpha[S_STATE][t + 2] - log07;
-				}
+				alpha[S_STATE][t + 2] -= start_codon_prob(
+					nts[t],
+					nts[t + 1],
+					nts[t + 2],
+					[log83, 0.10_f64.ln(), log07],
+				);
 
 				/* adjustment based on probability distribution */
-				let mut start_freq = 0.0;
-				let mut sub_sum = 0.0;
-
-				if t >= 30 {
-					for i in 0..=60 {
-						let i = i as isize - 30;
-						if t as isize + i + 2 < len_seq as isize {
-							start_freq -= hmm.tr_s[(i + 30) as usize][trinucleotide(
-								&sequence[(t as isize + i) as usize],
-								&sequence[(t as isize + i + 1) as usize],
-								&sequence[(t as isize + i + 2) as usize],
-							)];
-						}
-					}
-				} else {
-					let mut i = -1 * t as isize;
-					while i <= 30 {
-						if t as isize + i + 2 < len_seq as isize {
-							sub_sum += hmm.tr_s[(i + 30) as usize][trinucleotide(
-								&sequence[(t as isize + i) as usize],
-								&sequence[(t as isize + i + 1) as usize],
-								&sequence[(t as isize + i + 2) as usize],
-							)];
-						}
-						i += 1;
-					}
-					sub_sum = sub_sum * 61.0 / (30.0 + t as f64 + 1.0);
-					start_freq -= sub_sum;
-				}
-
-				let h_kd = hmm.s_dist[2]
-					* (-1.0 * (start_freq - hmm.s_dist[1]).powi(2) / (2.0 * hmm.s_dist[0].powi(2)))
-						.exp();
-				let r_kd = hmm.s_dist[5]
-					* (-1.0 * (start_freq - hmm.s_dist[4]).powi(2) / (2.0 * hmm.s_dist[3].powi(2)))
-						.exp();
-				let mut p_kd = h_kd / (h_kd + r_kd);
-				if p_kd < 0.01 {
-					p_kd = 0.01;
-				} else if p_kd > 0.99 {
-					p_kd = 0.99;
-				}
-				alpha[S_STATE][t + 2] = alpha[S_STATE][t + 2] - (p_kd).ln();
+				alpha[S_STATE][t + 2] -= start_adjustment(hmm, nts, t).ln();
 			}
 		}
 
@@ -784,13 +1094,7 @@ pub fn viterbi(
 			alpha[E_STATE_1][t] = max_dbl;
 			path[E_STATE_1][t] = NOSTATE;
 
-			if t < len_seq - 2
-				&& sequence[t].to_ascii_uppercase() == 'C'
-				&& sequence[t + 1].to_ascii_uppercase() == 'A'
-				&& (sequence[t + 2].to_ascii_uppercase() == 'T'
-					|| sequence[t + 2].to_ascii_uppercase() == 'C'
-					|| sequence[t + 2].to_ascii_uppercase() == 'A')
-			{
+			if t < len_seq - 2 && config.is_reverse_start(codon(t, t + 1, t + 2)) {
 				/* transition from frame6 */
 				alpha[E_STATE_1][t + 2] = alpha[M6_STATE_1][t - 1] - hmm.tr[TR_GE];
 				path[E_STATE_1][t] = M6_STATE_1 as i8;
@@ -799,65 +1103,18 @@ pub fn viterbi(
 				path[E_STATE_1][t + 1] = E_STATE_1 as i8;
 				path[E_STATE_1][t + 2] = E_STATE_1 as i8;
 
-				if sequence[t + 2].to_ascii_uppercase() == 'T' {
-					alpha[E_STATE_1][t + 2] = alpha[E_STATE_1][t + 2] - log83;
-				} else if sequence[t + 2].to_ascii_uppercase() == 'C' {
-					alpha[E_STATE_1][t + 2] = alpha[E_STATE_1][t + 2] - (0.10 as f64).ln();
-				} else if sequence[t + 2].to_ascii_uppercase() == 'A' {
-					alpha[E_STATE_1][t + 2] = alpha[E_STATE_1][t + 2] - log07;
-				}
+				alpha[E_STATE_1][t + 2] -= start_codon_prob(
+					complement_nt(nts[t + 2]),
+					complement_nt(nts[t + 1]),
+					complement_nt(nts[t]),
+					[log83, 0.10_f64.ln(), log07],
+				);
 
 				/* adjustment based on probability distribution */
-				let mut start_freq = 0.0;
-
-				let mut sub_sum = 0.0;
-
-				if t >= 30 {
-					for i in 0..=60 {
-						let i = i as isize - 30;
-						if t as isize + i + 2 < len_seq as isize {
-							start_freq -= hmm.tr_e_1[(i + 30) as usize][trinucleotide(
-								&sequence[(t as isize + i) as usize],
-								&sequence[(t as isize + i + 1) as usize],
-								&sequence[(t as isize + i + 2) as usize],
-							)];
-						}
-					}
-				} else {
-					let mut i = -1 * t as isize;
-					while i <= 30 {
-						if t as isize + i + 2 < len_seq as isize {
-							sub_sum += hmm.tr_e_1[(i + 30) as usize][trinucleotide(
-								&sequence[(t as isize + i) as usize],
-								&sequence[(t as isize + i + 1) as usize],
-								&sequence[(t as isize + i + 2) as usize],
-							)];
-						}
-						i += 1;
-					}
-					sub_sum = sub_sum * 61.0 / (30.0 + t as f64 + 1.0);
-					start_freq -= sub_sum;
-				}
-
-				let h_kd = hmm.e1_dist[2]
-					* (-1.0 * (start_freq - hmm.e1_dist[1]).powi(2)
-						/ (2.0 * hmm.e1_dist[0].powi(2)))
-					.exp();
-				let r_kd = hmm.e1_dist[5]
-					* (-1.0 * (start_freq - hmm.e1_dist[4]).powi(2)
-						/ (2.0 * hmm.e1_dist[3].powi(2)))
-					.exp();
-				let mut p_kd = h_kd / (h_kd + r_kd);
-
-				if p_kd < 0.01 {
-					p_kd = 0.01;
-				} else if p_kd > 0.99 {
-					p_kd = 0.99;
-				}
-				alpha[E_STATE_1][t + 2] = alpha[E_STATE_1][t + 2] - (p_kd).ln();
+				alpha[E_STATE_1][t + 2] -= reverse_start_adjustment(hmm, nts, t).ln();
 			}
 		}
-		if num_n > 9 {
+		if *num_n > 9 {
 			alpha
 				.par_iter_mut()
 				.zip(path.par_iter_mut())
@@ -871,321 +1128,374 @@ pub fn viterbi(
 		}
 	}
 
-	/***********************************************************/
-	/* backtrack array to find the optimal path                */
-	/***********************************************************/
-
-	let mut prediction = Prediction {
-		head: head.to_string(),
-		outs: Vec::new(),
-	};
-
-	/* find the state for sequence[N] with the highest probability */
-	let mut prob = f64::INFINITY;
-	for i in 0..NUM_STATE {
-		if alpha[i][len_seq - 1] < prob {
-			prob = alpha[i][len_seq - 1];
-			vpath[len_seq - 1] = i;
+	/*
+	 * Gets the state for nts[N] with the highest probability.
+	 */
+	fn final_state(&self, matrix: &Matrix) -> usize {
+		let mut prob = f64::INFINITY;
+		let mut state = 0;
+		for (i, alpha) in matrix.alpha.iter().enumerate() {
+			if alpha[self.len_seq - 1] < prob {
+				prob = alpha[self.len_seq - 1];
+				state = i;
+			}
 		}
+		state
 	}
 
-	/* backtrack the optimal path */
-	for t in (0..=(len_seq - 2)).rev() {
-		vpath[t] = path[vpath[t + 1]][t + 1] as usize;
+	/*
+	 * Backtracks the full matrices to find the optimal path, returns the state of every base on the path and the
+	 * alpha of that state.
+	 */
+	fn backtrack(&self, matrix: &Matrix) -> (Vec<usize>, Vec<f64>) {
+		let len_seq = self.len_seq;
+		let mut vpath = vec![0; len_seq];
+		vpath[len_seq - 1] = self.final_state(matrix);
+		for t in (0..=(len_seq - 2)).rev() {
+			vpath[t] = matrix.path[vpath[t + 1]][t + 1] as usize;
+		}
+		let path_alpha = (0..len_seq).map(|t| matrix.alpha[vpath[t]][t]).collect();
+		(vpath, path_alpha)
 	}
 
-	let mut codon_start = 0;
-	let mut start_t: isize = -1;
-
-	let mut insert: [usize; 100] = [0; 100];
-	let mut delete: [usize; 100] = [0; 100];
-
-	let mut start_orf = 0;
-	let mut prev_match = 0;
+	/*
+	 * Finds the same optimal path as backtrack, without keeping the full matrices in memory. The first pass only
+	 * keeps a checkpoint of the last columns every segment (about sqrt(len_seq) columns), the path is then
+	 * backtracked one segment at a time (from the end of the sequence) by filling in the segment again from its
+	 * checkpoint.
+	 */
+	fn checkpointed_path(&self) -> (Vec<usize>, Vec<f64>) {
+		let len_seq = self.len_seq;
+		let segment = ((len_seq as f64).sqrt() as usize).max(RING_COLUMNS);
+
+		let mut matrix = Matrix::ring(RING_COLUMNS);
+		self.init(&mut matrix);
+		let mut checkpoints = Vec::new();
+		for t in 1..len_seq {
+			if (t - 1) % segment == 0 {
+				checkpoints.push(matrix.clone());
+			}
+			self.column(&mut matrix, t);
+		}
 
-	let mut insert_id = 0;
-	let mut delete_id = 0;
-	let mut end_t: usize;
+		let mut vpath = vec![0; len_seq];
+		let mut path_alpha = vec![0.0; len_seq];
+		vpath[len_seq - 1] = self.final_state(&matrix);
+		path_alpha[len_seq - 1] = matrix.alpha[vpath[len_seq - 1]][len_seq - 1];
+
+		let mut columns = Matrix::ring((segment + RING_COLUMNS).next_power_of_two());
+		for (k, checkpoint) in checkpoints.iter().enumerate().rev() {
+			/* the checkpoint holds the columns before iteration start, the segment ends at column end */
+			let start = 1 + k * segment;
+			let end = (start + segment).min(len_seq - 1);
+			columns.restore(checkpoint, (start + 1).saturating_sub(RING_COLUMNS), (start + 2).min(len_seq));
+			for t in start..=end {
+				self.column(&mut columns, t);
+			}
+			for t in (start - 1..end).rev() {
+				vpath[t] = columns.path[vpath[t + 1]][t + 1] as usize;
+				path_alpha[t] = columns.alpha[vpath[t]][t];
+			}
+		}
+		(vpath, path_alpha)
+	}
+}
 
-	let mut final_score;
-	let mut frame;
 
-	let mut dna_start_t_withstop = 0;
-	let mut dna_start_t = 0;
+/**
+ * The parameters of the HMM which depend on the base at t (t > 0), shared by viterbi and the forward-backward
+ * algorithm in posterior.rs: the match emissions and indel transitions into the base, weighted by the error
+ * probability of the base if available. Bases other than ACGT are treated as G.
+ */
+pub(crate) struct Column {
+	pub from: usize,
+	pub to: usize,
+	pub e_m: [f64; 6],
+	pub e_m_1: [f64; 6],
+	pub tr_mi: f64,
+	pub tr_md: f64,
+}
 
-	for t in 0..len_seq {
-		if codon_start == 0
-			&& start_t < 0
-			&& ((vpath[t] >= M1_STATE && vpath[t] <= M6_STATE)
-				|| (vpath[t] >= M1_STATE_1 && vpath[t] <= M6_STATE_1)
-				|| vpath[t] == S_STATE
-				|| vpath[t] == S_STATE_1)
-		{
-			dna_start_t_withstop = t + 1;
-			dna_start_t = t + 1;
-			start_t = t as isize + 1;
-			//introduce dna_start_t_withstop YY July 2018
+impl Column {
+	pub fn new(hmm: &HMM, nts: &[u8], t: usize, error_prob: Option<&[f64]>) -> Column {
+		let mut from = nts[t - 1] as usize;
+		let mut from0: usize;
+		if t > 2 {
+			from0 = nts[t - 2] as usize;
+		} else {
+			from0 = 2;
 		}
+		let mut to = nts[t] as usize;
 
-		if codon_start == 0
-			&& (vpath[t] == M1_STATE
-				|| vpath[t] == M4_STATE
-				|| vpath[t] == M1_STATE_1
-				|| vpath[t] == M4_STATE_1)
-		{
-			insert.par_iter_mut().for_each(|p| *p = 0);
-
-			delete.par_iter_mut().for_each(|p| *p = 0);
+		if from == 4 {
+			from = 2;
+		}
+		if from0 == 4 {
+			from0 = 2;
+		}
+		if to == 4 {
+			to = 2;
+		}
+		let from2 = from0 * 4 + from;
 
-			insert_id = 0;
-			delete_id = 0;
-			dna_id = 0;
-			dna_start_t_withstop = t + 1; //Ye April 21, 2016
-			dna_start_t = t + 1;
-			if vpath[t] == M1_STATE_1 || vpath[t] == M4_STATE_1 {
-				if t > 2 {
-					dna_start_t_withstop = t - 2;
-				}
+		let mut e_m: [f64; 6] = std::array::from_fn(|k| hmm.e_m[k][from2][to]);
+		let mut e_m_1: [f64; 6] = std::array::from_fn(|k| hmm.e_m_1[k][from2][to]);
+		let mut tr_mi = hmm.tr[TR_MI];
+		let mut tr_md = hmm.tr[TR_MD];
+		if let Some(error_prob) = error_prob {
+			let error_prob = error_prob[t];
+			for emission in e_m.iter_mut().chain(e_m_1.iter_mut()) {
+				*emission = soften_emission(*emission, error_prob);
 			}
-			//printf("Note start dna: t = %d, dna_id %d, dna_f_id %d, add %c\n", t, dna_id, dna_f_id, sequence[t]);
-			start_orf = t + 1;
-			prev_match = vpath[t];
+			tr_mi = raise_indel(tr_mi, error_prob);
+			tr_md = raise_indel(tr_md, error_prob);
+		}
+		Column {
+			from,
+			to,
+			e_m,
+			e_m_1,
+			tr_mi,
+			tr_md,
+		}
+	}
+}
 
-			if vpath[t] < M6_STATE {
-				codon_start = 1;
-			} else {
-				codon_start = -1;
-			}
-		} else if codon_start != 0
-			&& (vpath[t] == E_STATE || vpath[t] == E_STATE_1 || t == len_seq - 1)
-		{
-			if vpath[t] == E_STATE || vpath[t] == E_STATE_1 {
-				end_t = t + 3;
-			} else {
-				//end_t=t+1;
-				/* FGS1.12 start: remove incomplete codon */
-				let mut temp_t = t;
-				while vpath[temp_t] != M1_STATE
-					&& vpath[temp_t] != M4_STATE
-					&& vpath[temp_t] != M1_STATE_1
-					&& vpath[temp_t] != M4_STATE_1
-				{
-					dna_id -= 1;
-					temp_t -= 1;
-				}
-				end_t = temp_t; //??? YY July 2018
-				 /* FGS1.12 end: remove incomplete codon */
-			}
+/**
+ * Quality scores below this value are treated as this value, since the probability of a sequencing error is never
+ * higher than that of a random base.
+ */
+const MIN_ERROR_QUALITY: f64 = 1.25;
 
-			if dna_id > gene_len {
-				//these three lines moved here from outside of the loop above, YY July 23, 2018
-				final_score = (alpha[vpath[(end_t - 4) as usize]][(end_t - 4) as usize]
-					- alpha[vpath[(start_t + 2) as usize]][(start_t + 2) as usize])
-					/ ((end_t as isize - start_t - 5) as f64);
-				frame = start_orf % 3;
-				if frame == 0 {
-					frame = 3;
-				}
+/**
+ * Fraction of the error probability of a base which is added to the probability of an indel transition into it.
+ */
+const QUALITY_INDEL_WEIGHT: f64 = 0.1;
 
-				if codon_start == 1 {
-					if start_t == dna_start_t as isize - 3 {
-						//add complete start codon to dna, Ye April 21, 2016
-						dna_start_t -= 3;
-					}
-					if refine {
-						//add refinement of the start codons here, Ye, April 16, 2016
-						let start_old = start_t;
-						let mut codon =
-							sequence[(start_old - 1) as usize..(start_old + 2) as usize].to_vec();
-						let mut s = 0;
-						//find the optimal start codon within 30bp up- and downstream of start codon
-						let mut e_save = 0.0;
-						let mut s_save = 0; //initialization, YY July 25 2018
+/**
+ * Converts a Phred quality score to the probability of a sequencing error at that base.
+ */
+fn phred_error_prob(quality: u8) -> f64 {
+	10.0_f64.powf(-(quality as f64).max(MIN_ERROR_QUALITY) / 10.0)
+}
 
-						let mut c = codon[0..3].iter().collect::<String>();
-						while (!(c != "TAA" || c != "TAG" || c != "TGA"))
-							&& (start_old - 1 - s - 35 >= 0)
-						{
-							if c != "ATG" || c != "GTG" || c != "TTG" {
-								let utr = sequence[(start_old - 1 - s - 30) as usize
-									..(start_old - 1 - s - 30 + 63) as usize]
-									.to_vec();
-								//printf("check s=%d, codon %s\n", s, codon);
-								let freq_sum = -(0..61)
-									.into_par_iter()
-									.map(|j| {
-										let j = j as usize;
-										let idx = trinucleotide(&utr[j], &utr[j + 1], &utr[j + 2]);
-										train.start[cg][j][idx]
-									})
-									.sum::<f64>();
-								if s == 0 {
-									e_save = freq_sum;
-									s_save = 0;
-								} else if freq_sum < e_save {
-									e_save = freq_sum;
-									s_save = -1 * s;
-								} //positive chain, upstream s_save = -1 * 3
-								 //printf("s=%d freq_sum %lf\n", s, freq_sum);
-								 //getchar();
-							}
-							s += 3;
-							codon = sequence
-								[(start_old - s - 1) as usize..(start_old - s + 2) as usize]
-								.to_vec();
-							c = codon[0..3].iter().collect::<String>();
-						}
-						//update start_t YY July 2018
-						if s_save != 0 {
-							dna_start_t = (dna_start_t as isize + s_save) as usize;
-						}
-					}
+/**
+ * Mixes a (log) match emission probability with a uniform emission, weighted by the error probability of the base.
+ */
+fn soften_emission(emission: f64, error_prob: f64) -> f64 {
+	((1.0 - error_prob) * emission.exp() + error_prob * 0.25).ln()
+}
 
-					let dna_end_t = end_t;
+/**
+ * Raises a (log) indel transition probability by the error probability of the base.
+ */
+fn raise_indel(transition: f64, error_prob: f64) -> f64 {
+	(transition.exp() + QUALITY_INDEL_WEIGHT * error_prob).min(0.5).ln()
+}
 
-					let protein = get_protein(
-						&sequence[dna_start_t - 1..dna_end_t].to_vec(),
-						true,
-						wholegenome,
-					);
+/**
+ * Helper method to get the (log) probability of the encoded stop codon a-b-c (in forward direction) from the
+ * probabilities of TAA, TAG and TGA. Stop codons which only occur in other genetic codes get the probability of TAG,
+ * the least frequent of the three.
+ */
+pub(crate) fn stop_codon_prob(a: u8, b: u8, c: u8, probs: [f64; 3]) -> f64 {
+	match [a, b, c] {
+		/* TAA */
+		[3, 0, 0] => probs[0],
+		/* TGA */
+		[3, 2, 0] => probs[2],
+		_ => probs[1],
+	}
+}
 
-					let mut out = Out {
-						dna_start_t: dna_start_t,
-						dna_end_t: dna_end_t,
-						frame: frame,
-						final_score: final_score,
-						insert: Vec::new(),
-						delete: Vec::new(),
-						protein: protein.iter().collect::<String>(),
-						forward: true,
-						dna: sequence[dna_start_t - 1..dna_end_t]
-							.to_vec()
-							.iter()
-							.collect::<String>(),
-					};
+/**
+ * Helper method to get the (log) probability of the encoded start codon a-b-c (in forward direction) from the
+ * probabilities of ATG, GTG and TTG.
+ */
+pub(crate) fn start_codon_prob(a: u8, b: u8, c: u8, probs: [f64; 3]) -> f64 {
+	match [a, b, c] {
+		/* ATG */
+		[0, 3, 2] => probs[0],
+		/* GTG */
+		[2, 3, 2] => probs[1],
+		_ => probs[2],
+	}
+}
 
-					for i in 0..insert_id {
-						out.insert.push(insert[i])
-					}
-					for i in 0..delete_id {
-						out.delete.push(delete[i])
-					}
-					prediction.outs.push(out);
-				} else if codon_start == -1 {
-					if refine {
-						//add refinement of the start codons here, Ye, April 16, 2016
-						let end_old = end_t; //reverse
-						let mut codon = sequence[(end_t - 3) as usize..(end_t) as usize].to_vec();
-						let mut s = 0;
-						//find the optimal start codon within 30bp up- and downstream of start codon
-						let mut e_save = 0.0;
-						let mut s_save = 0; //initialization, YY July 25, 2018
-						let mut c = codon[0..3].iter().collect::<String>();
-						while (!(c != "TTA" || c != "CTA" || c != "TCA"))
-							&& (end_old - 2 + s + 35 < len_seq)
-						{
-							if c != "CAT" || c != "CAC" || c != "CAA" {
-								let utr = sequence[(end_old - 3 + s - 30) as usize
-									..(end_old - 3 + s - 30 + 64) as usize]
-									.to_vec();
-								//printf("check s=%d, codon %s\n", s, codon);
-								let freq_sum = -(0..61)
-									.into_par_iter()
-									.map(|j| {
-										let j = j as usize;
-										let idx = trinucleotide(&utr[j], &utr[j + 1], &utr[j + 2]);
-										train.stop1[cg][j][idx]
-									})
-									.sum::<f64>();
-								if s == 0 {
-									e_save = freq_sum;
-									s_save = s;
-								} else if freq_sum < e_save {
-									e_save = freq_sum;
-									s_save = s;
-								} //negative chain, s_save = s, add, YY July 2018
-							}
-							s += 3;
-							codon =
-								sequence[(end_t - 3 + s) as usize..(end_t + s) as usize].to_vec();
-							c = codon[0..3].iter().collect::<String>();
-						}
-						//update end_t
-						end_t = end_old + s_save;
-					}
+/**
+ * Helper method to get the probability that the stop codon at t ends a gene on the forward strand (END state),
+ * based on the distribution of the trinucleotides upstream of the stop codon.
+ */
+pub(crate) fn stop_adjustment(hmm: &HMM, nts: &[u8], t: usize) -> f64 {
+	let len_seq = nts.len();
+	let mut start_freq = 0.0;
+
+	let mut sub_sum = 0.0;
+
+	if t >= 60 {
+		/* bug reported by Yu-Wei */
+		start_freq -= (3..=60)
+			.map(|i| {
+				let i = -(i as isize);
+				let mut result = 0.0;
+				if (t as isize) + i + 2 < len_seq as isize {
+					let idx: usize = (i + 60) as usize;
+					result = hmm.tr_e[idx][trinucleotide_nts(
+						nts[(t as isize + i) as usize],
+						nts[(t as isize + i + 1) as usize],
+						nts[(t as isize + i + 2) as usize],
+					)];
+				}
+				result
+			})
+			.sum::<f64>();
+	} else {
+		sub_sum += (3..=t)
+			.map(|i| {
+				let i = -(i as isize);
+				let mut result = 0.0;
+				if t as isize + i + 2 < len_seq as isize {
+					let idx: usize = (i + 60) as usize;
+					result = hmm.tr_e[idx][trinucleotide_nts(
+						nts[(t as isize + i) as usize],
+						nts[(t as isize + i + 1) as usize],
+						nts[(t as isize + i + 2) as usize],
+					)];
+				}
+				result
+			})
+			.sum::<f64>();
 
-					let dna_end_t = end_t;
+		sub_sum = sub_sum * 58.0 / (-3.0 + t as f64 + 1.0);
+		start_freq -= sub_sum;
+	}
 
-					//update dna before calling get_protein, YY July 2018
-					//use dna_end_t & dna_start_w_withstop to avoid incomplete codons & include start/stop codons
+	let h_kd = hmm.e_dist[2]
+		* (-(start_freq - hmm.e_dist[1]).powi(2) / (2.0 * hmm.e_dist[0].powi(2)))
+			.exp();
+	let r_kd = hmm.e_dist[5]
+		* (-(start_freq - hmm.e_dist[4]).powi(2) / (2.0 * hmm.e_dist[3].powi(2)))
+			.exp();
+	(h_kd / (h_kd + r_kd)).clamp(0.01, 0.99)
+}
 
-					let protein = get_protein(
-						&sequence[dna_start_t_withstop - 1..dna_end_t].to_vec(),
-						false,
-						wholegenome,
-					); //YY July 18, 2018, introduce adjust
+/**
+ * Helper method to get the probability that the reverse stop codon at t ends a gene on the reverse strand (START'
+ * state), based on the distribution of the trinucleotides downstream of the codon.
+ */
+pub(crate) fn reverse_stop_adjustment(hmm: &HMM, nts: &[u8], t: usize) -> f64 {
+	let len_seq = nts.len();
+	let mut start_freq = 0.0;
+	start_freq -= (3..=60)
+		.map(|i: i32| {
+			let i = i as usize;
+			let mut result: f64 = 0.0;
+			if t + i + 2 < len_seq {
+				result = hmm.tr_s_1[i - 3][trinucleotide_nts(nts[t + i], nts[t + i + 1], nts[t + i + 2])];
+			}
+			result
+		})
+		.sum::<f64>();
+	let h_kd = hmm.s1_dist[2]
+		* (-(start_freq - hmm.s1_dist[1]).powi(2)
+			/ (2.0 * hmm.s1_dist[0].powi(2)))
+		.exp();
+	let r_kd = hmm.s1_dist[5]
+		* (-(start_freq - hmm.s1_dist[4]).powi(2)
+			/ (2.0 * hmm.s1_dist[3].powi(2)))
+		.exp();
+	(h_kd / (h_kd + r_kd)).clamp(0.01, 0.99)
+}
 
-					let dna1_out =
-						get_rc_dna(&sequence[dna_start_t_withstop - 1..dna_end_t].to_vec());
+/**
+ * Helper method to get the probability that the start codon at t starts a gene on the forward strand (START state),
+ * based on the distribution of the trinucleotides around the start codon.
+ */
+pub(crate) fn start_adjustment(hmm: &HMM, nts: &[u8], t: usize) -> f64 {
+	let len_seq = nts.len();
+	let mut start_freq = 0.0;
+	let mut sub_sum = 0.0;
+
+	if t >= 30 {
+		for i in 0..=60 {
+			let i = i as isize - 30;
+			if t as isize + i + 2 < len_seq as isize {
+				start_freq -= hmm.tr_s[(i + 30) as usize][trinucleotide_nts(
+					nts[(t as isize + i) as usize],
+					nts[(t as isize + i + 1) as usize],
+					nts[(t as isize + i + 2) as usize],
+				)];
+			}
+		}
+	} else {
+		let mut i = -(t as isize);
+		while i <= 30 {
+			if t as isize + i + 2 < len_seq as isize {
+				sub_sum += hmm.tr_s[(i + 30) as usize][trinucleotide_nts(
+					nts[(t as isize + i) as usize],
+					nts[(t as isize + i + 1) as usize],
+					nts[(t as isize + i + 2) as usize],
+				)];
+			}
+			i += 1;
+		}
+		sub_sum = sub_sum * 61.0 / (30.0 + t as f64 + 1.0);
+		start_freq -= sub_sum;
+	}
 
-					let mut out = Out {
-						dna_start_t: dna_start_t_withstop,
-						dna_end_t: dna_end_t,
-						frame: frame,
-						final_score: final_score,
-						insert: Vec::new(),
-						delete: Vec::new(),
-						protein: protein.iter().collect::<String>(),
-						forward: false,
-						dna: dna1_out.iter().collect::<String>(),
-					};
+	let h_kd = hmm.s_dist[2]
+		* (-(start_freq - hmm.s_dist[1]).powi(2) / (2.0 * hmm.s_dist[0].powi(2)))
+			.exp();
+	let r_kd = hmm.s_dist[5]
+		* (-(start_freq - hmm.s_dist[4]).powi(2) / (2.0 * hmm.s_dist[3].powi(2)))
+			.exp();
+	(h_kd / (h_kd + r_kd)).clamp(0.01, 0.99)
+}
 
-					for i in 0..insert_id {
-						out.insert.push(insert[i])
-					}
-					for i in 0..delete_id {
-						out.delete.push(delete[i])
-					}
-					prediction.outs.push(out);
-				}
-			}
-			codon_start = 0;
-			start_t = -1;
-			dna_id = 0;
-		} else if codon_start != 0
-			&& ((vpath[t] >= M1_STATE && vpath[t] <= M6_STATE)
-				|| (vpath[t] >= M1_STATE_1 && vpath[t] <= M6_STATE_1))
-			&& (vpath[t] as i8) - (prev_match as i8) < 6
-		{
-			let out_nt;
-			if vpath[t] < prev_match {
-				out_nt = vpath[t] + 6 - prev_match;
-			} else {
-				out_nt = vpath[t] - prev_match;
+/**
+ * Helper method to get the probability that the reverse start codon at t starts a gene on the reverse strand (END'
+ * state), based on the distribution of the trinucleotides around the codon.
+ */
+pub(crate) fn reverse_start_adjustment(hmm: &HMM, nts: &[u8], t: usize) -> f64 {
+	let len_seq = nts.len();
+	let mut start_freq = 0.0;
+
+	let mut sub_sum = 0.0;
+
+	if t >= 30 {
+		for i in 0..=60 {
+			let i = i as isize - 30;
+			if t as isize + i + 2 < len_seq as isize {
+				start_freq -= hmm.tr_e_1[(i + 30) as usize][trinucleotide_nts(
+					nts[(t as isize + i) as usize],
+					nts[(t as isize + i + 1) as usize],
+					nts[(t as isize + i + 2) as usize],
+				)];
 			}
-			for kk in 0..out_nt {
-				/* for deleted nt in reads */
-				dna_id += 1;
-				//printf("dna_id %d, dna-len %d\n", dna_id, strlen(dna));
-				if kk > 0 {
-					delete[delete_id] = (t + 1) as usize;
-					delete_id += 1;
-				}
+		}
+	} else {
+		let mut i = -(t as isize);
+		while i <= 30 {
+			if t as isize + i + 2 < len_seq as isize {
+				sub_sum += hmm.tr_e_1[(i + 30) as usize][trinucleotide_nts(
+					nts[(t as isize + i) as usize],
+					nts[(t as isize + i + 1) as usize],
+					nts[(t as isize + i + 2) as usize],
+				)];
 			}
-			//printf("dna_id %d, add %d %c dna-len %d\n", dna_id, t, sequence[t], strlen(dna));
-			prev_match = vpath[t];
-		} else if codon_start != 0
-			&& ((vpath[t] >= I1_STATE && vpath[t] <= I6_STATE)
-				|| (vpath[t] >= I1_STATE_1 && vpath[t] <= I6_STATE_1))
-		{
-			insert[insert_id] = (t + 1) as usize;
-			insert_id += 1;
-		} else if codon_start != 0 && vpath[t] == R_STATE {
-			/* for long NNNNNNNNN, pretend R state */
-			codon_start = 0;
-			start_t = -1;
-			dna_id = 0;
+			i += 1;
 		}
+		sub_sum = sub_sum * 61.0 / (30.0 + t as f64 + 1.0);
+		start_freq -= sub_sum;
 	}
-	prediction
+
+	let h_kd = hmm.e1_dist[2]
+		* (-(start_freq - hmm.e1_dist[1]).powi(2)
+			/ (2.0 * hmm.e1_dist[0].powi(2)))
+		.exp();
+	let r_kd = hmm.e1_dist[5]
+		* (-(start_freq - hmm.e1_dist[4]).powi(2)
+			/ (2.0 * hmm.e1_dist[3].powi(2)))
+		.exp();
+	(h_kd / (h_kd + r_kd)).clamp(0.01, 0.99)
 }
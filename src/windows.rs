@@ -0,0 +1,160 @@
+use super::posterior::BasePosteriors;
+use super::viterbi::{Out, Prediction};
+
+/*
+ * windows.rs
+ * ==========
+ * This file contains the helpers used for predicting a long sequence (e.g. a complete chromosome) in overlapping
+ * windows, so a single sequence can be spread over multiple threads. The cut between two consecutive windows lies
+ * in the middle of their overlap: a gene is taken from the window before the cut when its stop codon lies before
+ * the cut and from the window after the cut otherwise. The Viterbi path of both windows usually agrees well before
+ * the middle of the overlap, so the stitched predictions match the predictions of a single pass, except for genes
+ * longer than half of the overlap which span a cut.
+ *
+ * Circular sequences are predicted the same way: the start of the sequence is appended to its end, the cut at the
+ * start of the sequence lies in the middle of this copy. Genes spanning the origin end past the end of the sequence,
+ * like the circular convention of GFF3.
+ */
+
+/**
+ * A window of a sequence, the positions are 0-based and the end is exclusive. Only the predictions with a stop
+ * codon in keep_from..keep_to are kept, these ranges partition the sequence.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct SequenceWindow {
+  pub start: usize,
+  pub end: usize,
+  pub keep_from: usize,
+  pub keep_to: usize,
+}
+
+/**
+ * Splits a sequence of length len_seq into windows of window_length bp which overlap by overlap bp (overlap <
+ * window_length), the last window ends at the end of the sequence. A sequence which is not longer than
+ * window_length gives a single window.
+ */
+pub(crate) fn sequence_windows(len_seq: usize, window_length: usize, overlap: usize) -> Vec<SequenceWindow> {
+  if len_seq <= window_length {
+    return vec![SequenceWindow {
+      start: 0,
+      end: len_seq,
+      keep_from: 0,
+      keep_to: len_seq,
+    }];
+  }
+  let step = window_length - overlap;
+  let count = (len_seq - overlap).div_ceil(step);
+  (0..count)
+    .map(|k| {
+      let start = k * step;
+      let last = k + 1 == count;
+      SequenceWindow {
+        start,
+        end: if last { len_seq } else { start + window_length },
+        keep_from: if k == 0 { 0 } else { start + overlap / 2 },
+        keep_to: if last { len_seq } else { start + step + overlap / 2 },
+      }
+    })
+    .collect()
+}
+
+/**
+ * Stitches the predictions of the windows of a sequence (in the order of sequence_windows) into a single
+ * prediction of the whole sequence, the positions of the genes are moved from the window to the sequence.
+ */
+pub(crate) fn stitch_predictions(head: &str, windows: &[SequenceWindow], predictions: Vec<Prediction>) -> Prediction {
+  let cg = predictions.first().map_or(0, |prediction| prediction.cg);
+  let mut outs = Vec::new();
+  let mut posteriors: Option<BasePosteriors> = None;
+  for (window, prediction) in windows.iter().zip(predictions) {
+    for out in prediction.outs {
+      let out = shift_out(out, window.start as isize);
+      /* 0-based position of the last base of the stop codon */
+      let stop = if out.forward {
+        out.dna_end_t - 1
+      } else {
+        out.dna_start_t - 1
+      };
+      if (window.keep_from..window.keep_to).contains(&stop) {
+        outs.push(out);
+      }
+    }
+
+    if let Some(bases) = prediction.posteriors {
+      let stitched = posteriors.get_or_insert_with(|| BasePosteriors {
+        forward: Vec::new(),
+        reverse: Vec::new(),
+      });
+      let keep = (window.keep_from - window.start)..(window.keep_to - window.start);
+      stitched.forward.extend_from_slice(&bases.forward[keep.clone()]);
+      stitched.reverse.extend_from_slice(&bases.reverse[keep]);
+    }
+  }
+
+  Prediction {
+    head: head.to_string(),
+    outs,
+    posteriors,
+    circular_length: None,
+    cg,
+  }
+}
+
+/**
+ * Folds the prediction of a circular sequence of length len_seq, followed by a copy of its first wrap bases, back
+ * onto the circular sequence. The genes are kept when their stop codon lies in wrap / 2..len_seq + wrap / 2, genes
+ * which lie completely within the copy are moved to the start of the sequence.
+ */
+pub(crate) fn fold_circular(prediction: Prediction, len_seq: usize, wrap: usize) -> Prediction {
+  let cut = wrap / 2;
+  let mut outs = Vec::new();
+  for out in prediction.outs {
+    let stop = if out.forward {
+      out.dna_end_t - 1
+    } else {
+      out.dna_start_t - 1
+    };
+    if (cut..len_seq + cut).contains(&stop) {
+      outs.push(match out.dna_start_t > len_seq {
+        true => shift_out(out, -(len_seq as isize)),
+        false => out,
+      });
+    }
+  }
+  /* genes moved from the copy come before the other genes */
+  outs.sort_by_key(|out| out.dna_start_t);
+
+  let posteriors = prediction.posteriors.map(|bases| {
+    let fold = |posteriors: Vec<f64>| [&posteriors[len_seq..len_seq + cut], &posteriors[cut..len_seq]].concat();
+    BasePosteriors {
+      forward: fold(bases.forward),
+      reverse: fold(bases.reverse),
+    }
+  });
+
+  Prediction {
+    head: prediction.head,
+    outs,
+    posteriors,
+    circular_length: Some(len_seq),
+    cg: prediction.cg,
+  }
+}
+
+/**
+ * Helper method to move the (1-based) positions and the frame of a gene by offset bases.
+ */
+fn shift_out(mut out: Out, offset: isize) -> Out {
+  let shift = |position: usize| (position as isize + offset) as usize;
+  /* the frame is the position of the ORF modulo 3, with 3 instead of 0 */
+  out.frame = match (out.frame as isize + offset).rem_euclid(3) {
+    0 => 3,
+    frame => frame as usize,
+  };
+  out.dna_start_t = shift(out.dna_start_t);
+  out.dna_end_t = shift(out.dna_end_t);
+  for position in out.insert.iter_mut().chain(out.delete.iter_mut()) {
+    *position = shift(*position);
+  }
+  out
+}
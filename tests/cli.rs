@@ -0,0 +1,72 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+mod common;
+use common::{crate_dir, temp_path};
+
+/*
+ * cli.rs
+ * ======
+ * Tests that the fgsrs binary reports every class of error on stderr and exits with its own exit code (see
+ * Error::exit_code).
+ */
+
+/**
+ * Runs fgsrs with the specified arguments and input, no model directory is found unless it is given with -m.
+ */
+fn fgsrs(args: &[&str], input: &str) -> Output {
+  let mut child = Command::new(env!("CARGO_BIN_EXE_fgsrs"))
+    .args(args)
+    .env_remove("FGSRS_MODEL_DIR")
+    .env("HOME", std::env::temp_dir())
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .unwrap();
+  /* fgsrs may exit before it reads its input */
+  let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+  child.wait_with_output().unwrap()
+}
+
+fn assert_exit_code(args: &[&str], input: &str, code: i32, message: &str) {
+  let output = fgsrs(args, input);
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert_eq!(output.status.code(), Some(code), "{:?} exited with: {}", args, stderr);
+  assert!(stderr.contains(message), "{:?} reported: {}", args, stderr);
+}
+
+#[test]
+fn errors_exit_with_their_own_code() {
+  let models = crate_dir().join("train");
+  let models = models.to_str().unwrap();
+  let model = temp_path("malformed_model");
+  fs::write(&model, "garbage\n").unwrap();
+  let model = model.to_str().unwrap();
+  let (valid, malformed, malformed_fastq) = (">read1\nACGTACGTAC\n", "ACGTACGTAC\n", "@read1\nACGTACGTAC\n+\nIIII\n");
+
+  /* 0: success */
+  assert_exit_code(&["-w", "0", "-t", "complete", "-m", models], valid, 0, "");
+  /* 1: invalid command line argument */
+  assert_exit_code(&["-w", "2", "-t", "complete", "-m", models], valid, 1, "-w");
+  assert_exit_code(&["-w", "0", "-t", "complete", "-m", models, "--cg-bin", "44"], valid, 1, "");
+  /* 2: unreadable input */
+  let args = ["train", "-g", "/nonexistent/genome.fna", "-a", "/nonexistent/genome.gff", "-o", "/nonexistent/model"];
+  assert_exit_code(&args, "", 2, "Unable to read");
+  /* 3: missing model directory or model, the embedded models are used when no model directory is found */
+  if !cfg!(feature = "embedded-models") {
+    assert_exit_code(&["-w", "0", "-t", "complete", "-m", "/nonexistent/train"], valid, 3, "model directory");
+  }
+  assert_exit_code(&["-w", "0", "-t", "no_such_model", "-m", models], valid, 3, "no_such_model");
+  /* 4: malformed model file */
+  assert_exit_code(&["-w", "0", "-t", model, "-m", models], valid, 4, "Invalid model file");
+  /* 5: malformed FASTA or FASTQ input */
+  assert_exit_code(&["-w", "0", "-t", "complete", "-m", models], malformed, 5, "expected '>'");
+  assert_exit_code(&["-w", "0", "-t", "complete", "-m", models], malformed_fastq, 5, "quality scores");
+  /* 6: unwritable output */
+  let args = ["-w", "0", "-t", "complete", "-m", models, "-e", "/nonexistent/reads.out"];
+  assert_exit_code(&args, valid, 6, "Unable to write");
+
+  fs::remove_file(model).unwrap();
+}
@@ -0,0 +1,109 @@
+#![allow(dead_code)]
+
+use fgsrs::{GenePredictor, PredictionConfig, Train, HMM};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+ * common/mod.rs
+ * =============
+ * Helpers shared by the integration tests: the paths of the crate and its example output, predictors for the
+ * bundled models and deterministic pseudo-random sequences. Not every test uses every helper.
+ */
+
+pub fn crate_dir() -> PathBuf {
+  Path::new(env!("CARGO_MANIFEST_DIR")).to_path_buf()
+}
+
+pub fn example_path(name: &str) -> PathBuf {
+  crate_dir().join("example").join(name)
+}
+
+/**
+ * A path in the temporary directory which is unique to the running test binary.
+ */
+pub fn temp_path(name: &str) -> PathBuf {
+  std::env::temp_dir().join(format!("fgsrs-{}-{}", std::process::id(), name))
+}
+
+/**
+ * Builds a predictor for one of the models in the train/ folder.
+ */
+pub fn predictor(model: &str, config: PredictionConfig) -> GenePredictor {
+  let train_dir = crate_dir().join("train");
+  let train = Train::from_dir(&train_dir).unwrap();
+  let hmm = HMM::from_file(&train_dir.join(model).to_string_lossy()).unwrap();
+  GenePredictor::new(train, hmm, config.wholegenome()).with_config(config)
+}
+
+/**
+ * A linear congruential generator, so the random sequences of the tests are the same in every run.
+ */
+pub struct Random(u64);
+
+impl Random {
+  pub fn new(seed: u64) -> Random {
+    Random(seed)
+  }
+
+  /* a number in 0..n, taken from the high bits of the state as the low bits of an LCG are far from random */
+  pub fn below(&mut self, n: usize) -> usize {
+    self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    (((self.0 >> 32) * n as u64) >> 32) as usize
+  }
+
+  pub fn base(&mut self) -> char {
+    ['A', 'C', 'G', 'T'][self.below(4)]
+  }
+
+  pub fn bases(&mut self, length: usize) -> String {
+    (0..length).map(|_| self.base()).collect()
+  }
+}
+
+pub fn reverse_complement(seq: &str) -> String {
+  seq
+    .chars()
+    .rev()
+    .map(|base| match base {
+      'A' => 'T',
+      'C' => 'G',
+      'G' => 'C',
+      'T' => 'A',
+      other => other,
+    })
+    .collect()
+}
+
+/**
+ * The coding fragments of the 454 reads in the example output, in the order of the example.
+ */
+pub fn gene_fragments() -> Vec<String> {
+  fs::read_to_string(example_path("NC_000913-454-fgs.ffn"))
+    .unwrap()
+    .lines()
+    .filter(|line| !line.starts_with('>'))
+    .map(String::from)
+    .collect()
+}
+
+/**
+ * Builds a chromosome-like sequence from the first gene fragments, separated by pseudo-random intergenic regions,
+ * with a run of unknown bases after the 200th fragment. Returns the sequence and the positions (0-based, end
+ * exclusive) of the intergenic regions.
+ */
+pub fn chromosome(fragments: usize) -> (String, Vec<(usize, usize)>) {
+  let mut random = Random::new(42);
+  let mut sequence = String::new();
+  let mut spacers = Vec::new();
+  for (i, fragment) in gene_fragments().iter().take(fragments).enumerate() {
+    sequence.push_str(fragment);
+    let spacer = 20 + (i * 37) % 150;
+    spacers.push((sequence.len(), sequence.len() + spacer));
+    sequence.push_str(&random.bases(spacer));
+    if i == 200 {
+      sequence.push_str(&"N".repeat(15));
+    }
+  }
+  (sequence, spacers)
+}
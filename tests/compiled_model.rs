@@ -0,0 +1,79 @@
+use fgsrs::compiled_model::{
+  is_compiled_model, parse_compiled_model, read_compiled_model, write_compiled_model,
+};
+use fgsrs::{Train, HMM};
+use std::fs;
+use std::path::PathBuf;
+
+mod common;
+use common::{crate_dir, temp_path};
+
+/*
+ * compiled_model.rs
+ * =================
+ * Round-trip tests of the binary model format against the text model files in the train/ folder.
+ */
+
+const MODELS: &[&str] = &[
+  "complete",
+  "sanger_5",
+  "sanger_10",
+  "454_5",
+  "454_10",
+  "454_30",
+  "illumina_1",
+  "illumina_5",
+  "illumina_10",
+];
+
+fn train_dir() -> PathBuf {
+  crate_dir().join("train")
+}
+
+#[test]
+fn compiled_models_match_text_models() {
+  let train = Train::from_dir(&train_dir()).unwrap();
+  for model in MODELS {
+    let hmm = HMM::from_file(&train_dir().join(model).to_string_lossy()).unwrap();
+    let path = temp_path(&format!("{}.fgsm", model));
+    write_compiled_model(&path, &train, &hmm).unwrap();
+    assert!(is_compiled_model(&path));
+
+    let (compiled_train, compiled_hmm) = read_compiled_model(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(compiled_train, train, "Train tables of {} differ", model);
+    assert_eq!(compiled_hmm, hmm, "HMM of {} differs", model);
+  }
+}
+
+#[test]
+fn text_models_are_not_compiled_models() {
+  assert!(!is_compiled_model(&train_dir().join("complete")));
+  assert!(!is_compiled_model(&train_dir().join("gene")));
+}
+
+#[test]
+fn corrupted_models_are_rejected() {
+  let train = Train::from_dir(&train_dir()).unwrap();
+  let hmm = HMM::from_file(&train_dir().join("complete").to_string_lossy()).unwrap();
+  let path = temp_path("corrupted.fgsm");
+  write_compiled_model(&path, &train, &hmm).unwrap();
+  let bytes = fs::read(&path).unwrap();
+  fs::remove_file(&path).unwrap();
+  assert!(parse_compiled_model(&bytes, "complete.fgsm").is_ok());
+
+  // flipped payload bit
+  let mut corrupted = bytes.clone();
+  let last = corrupted.len() - 1;
+  corrupted[last] ^= 1;
+  assert!(parse_compiled_model(&corrupted, "complete.fgsm").is_err());
+
+  // unknown format version
+  let mut corrupted = bytes.clone();
+  corrupted[8] = corrupted[8].wrapping_add(1);
+  assert!(parse_compiled_model(&corrupted, "complete.fgsm").is_err());
+
+  // truncated file
+  assert!(parse_compiled_model(&bytes[..bytes.len() - 8], "complete.fgsm").is_err());
+  assert!(parse_compiled_model(&bytes[..10], "complete.fgsm").is_err());
+}
@@ -0,0 +1,158 @@
+use bzip2::write::BzEncoder;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use fgsrs::helpers::{create_file_if_not_exists, OutputFile};
+use fgsrs::input::{decompress, read_input_files, Record, SequenceReader};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::Command;
+
+mod common;
+use common::{crate_dir, example_path, temp_path};
+
+/*
+ * compression.rs
+ * ==============
+ * Tests that gzip, bzip2 and zstd compressed input is detected from its magic bytes, on standard input as well as
+ * in -i files, and that the .gz and .zst outputs are complete streams once they have been finished.
+ */
+
+const FASTA: &str = ">read1 first\nACGTACGTAC\nGGCC\n>read2\nTTTTAAAA\n";
+const FASTQ: &str = "@read1\nACGTACGT\n+\nIIIIIIII\n@read2\nGGCCA\n+\n!!!!!\n";
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+  let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+  encoder.write_all(data).unwrap();
+  encoder.finish().unwrap()
+}
+
+fn bzip2(data: &[u8]) -> Vec<u8> {
+  let mut encoder = BzEncoder::new(Vec::new(), bzip2::Compression::default());
+  encoder.write_all(data).unwrap();
+  encoder.finish().unwrap()
+}
+
+fn zstd(data: &[u8]) -> Vec<u8> {
+  zstd::encode_all(data, 0).unwrap()
+}
+
+fn records(data: Vec<u8>) -> Vec<(String, String, Option<Vec<u8>>)> {
+  let reader = decompress(std::io::Cursor::new(data), "test input").unwrap();
+  SequenceReader::new(reader, "test input")
+    .unwrap()
+    .map(|record| {
+      let record: Record = record.unwrap();
+      (record.id, record.seq, record.quality)
+    })
+    .collect()
+}
+
+#[test]
+fn compressed_input_is_detected() {
+  for input in [FASTA, FASTQ] {
+    let plain = records(input.as_bytes().to_vec());
+    assert_eq!(plain.len(), 2);
+    assert_eq!(records(gzip(input.as_bytes())), plain);
+    assert_eq!(records(bzip2(input.as_bytes())), plain);
+    assert_eq!(records(zstd(input.as_bytes())), plain);
+  }
+
+  /* concatenated gzip and bzip2 members (e.g. from cat or parallel compressors) are read completely */
+  let (first, second) = FASTA.split_at(FASTA.find(">read2").unwrap());
+  let plain = records(FASTA.as_bytes().to_vec());
+  assert_eq!(records([gzip(first.as_bytes()), gzip(second.as_bytes())].concat()), plain);
+  assert_eq!(records([bzip2(first.as_bytes()), bzip2(second.as_bytes())].concat()), plain);
+}
+
+#[test]
+fn compressed_input_files_are_read() {
+  let files = [
+    ("reads.fa.gz", gzip(FASTA.as_bytes())),
+    ("reads.fq.bz2", bzip2(FASTQ.as_bytes())),
+    ("reads.fa.zst", zstd(FASTA.as_bytes())),
+    ("reads.fq", FASTQ.as_bytes().to_vec()),
+  ];
+  let paths: Vec<String> = files
+    .iter()
+    .map(|(name, data)| {
+      let path = temp_path(name);
+      fs::write(&path, data).unwrap();
+      path.to_str().unwrap().to_string()
+    })
+    .collect();
+  let ids: Vec<String> = read_input_files(paths.clone()).map(|record| record.unwrap().id).collect();
+  assert_eq!(ids, ["read1", "read2"].repeat(4));
+  for path in paths {
+    fs::remove_file(path).unwrap();
+  }
+}
+
+#[test]
+fn compressed_outputs_are_complete_after_finish() {
+  let data = FASTA.repeat(1000);
+  for name in ["output.txt", "output.txt.gz", "output.txt.zst"] {
+    let path = temp_path(name);
+    let mut file = create_file_if_not_exists(path.to_str().unwrap()).unwrap();
+    match (&file, name) {
+      (OutputFile::Plain(_), "output.txt") | (OutputFile::Gzip(_), "output.txt.gz") => {}
+      (OutputFile::Zstd(_), "output.txt.zst") => {}
+      _ => panic!("wrong output file type for {}", name),
+    }
+    file.write_all(data.as_bytes()).unwrap();
+    file.finish().unwrap();
+
+    let written = fs::read(&path).unwrap();
+    let decompressed = match name {
+      "output.txt.gz" => {
+        /* the gzip trailer holds the CRC32 and the length of the uncompressed data */
+        let trailer = &written[written.len() - 8..];
+        assert_eq!(u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]), data.len() as u32);
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&written[..]).read_to_end(&mut decompressed).unwrap();
+        decompressed
+      }
+      "output.txt.zst" => zstd::decode_all(&written[..]).unwrap(),
+      _ => written,
+    };
+    assert_eq!(String::from_utf8(decompressed).unwrap(), data);
+    fs::remove_file(path).unwrap();
+  }
+}
+
+#[test]
+fn fgsrs_reads_and_writes_compressed_files() {
+  let ffn = fs::read_to_string(example_path("NC_000913-454-fgs.ffn")).unwrap();
+  let reads: String = ffn.lines().take(200).map(|line| format!("{}\n", line)).collect();
+  let input = temp_path("reads.fna.zst");
+  fs::write(&input, zstd(reads.as_bytes())).unwrap();
+  let run = |protein: &Path, metadata: &Path| {
+    let status = Command::new(env!("CARGO_BIN_EXE_fgsrs"))
+      .args(["-w", "0", "-t", "454_10", "-m"])
+      .arg(crate_dir().join("train"))
+      .arg("-i")
+      .arg(&input)
+      .arg("-a")
+      .arg(protein)
+      .arg("-e")
+      .arg(metadata)
+      .status()
+      .unwrap();
+    assert!(status.success());
+  };
+
+  let (protein, metadata) = (temp_path("reads.faa"), temp_path("reads.out"));
+  let (protein_gz, metadata_zst) = (temp_path("reads.faa.gz"), temp_path("reads.out.zst"));
+  run(&protein, &metadata);
+  run(&protein_gz, &metadata_zst);
+
+  let mut decompressed = String::new();
+  GzDecoder::new(fs::File::open(&protein_gz).unwrap()).read_to_string(&mut decompressed).unwrap();
+  assert_eq!(decompressed, fs::read_to_string(&protein).unwrap());
+  let decompressed = zstd::decode_all(fs::File::open(&metadata_zst).unwrap()).unwrap();
+  assert_eq!(String::from_utf8(decompressed).unwrap(), fs::read_to_string(&metadata).unwrap());
+
+  for path in [input, protein, metadata, protein_gz, metadata_zst] {
+    fs::remove_file(path).unwrap();
+  }
+}
@@ -0,0 +1,94 @@
+use fgsrs::annotation::{read_annotation, CdsFeature};
+use fgsrs::evaluation::{evaluate, read_predictions};
+use fgsrs::Error;
+use std::fs;
+
+mod common;
+use common::{example_path, temp_path};
+
+/*
+ * evaluation.rs
+ * =============
+ * Tests of the evaluation of predictions against the reference annotations in the example/ folder. The predictions
+ * in the example/ folder are made on NC_000913.2, whereas the reference annotations are those of NC_000913.3, so
+ * their positions only agree up to the first difference between both versions of the genome. Annotations with
+ * invalid coordinates are rejected with the line of the feature.
+ */
+
+fn example(name: &str) -> String {
+  example_path(name).display().to_string()
+}
+
+fn first_genes(features: Vec<CdsFeature>, end: usize) -> Vec<CdsFeature> {
+  features.into_iter().filter(|feature| feature.end <= end).collect()
+}
+
+#[test]
+fn reference_matches_itself() {
+  let reference = read_annotation(&example("NC_000913.ptt")).unwrap();
+  let report = evaluate(&reference, &reference);
+  assert_eq!(report.reference_genes, 4140);
+  assert_eq!(report.genes.matched_reference, report.reference_genes);
+  /* a few genes share their stop codon with another gene, only one of their starts is matched */
+  assert!(report.starts.exact >= report.starts.matched - 5);
+  assert_eq!(report.nucleotides.overlap, report.nucleotides.reference_coding);
+  assert_eq!(report.strand_frame.same_frame, report.reference_genes);
+}
+
+#[test]
+fn ptt_and_gff_references_agree() {
+  let ptt = read_annotation(&example("NC_000913.ptt")).unwrap();
+  let gff = read_annotation(&example("NC_000913.gff")).unwrap();
+  let report = evaluate(&gff, &ptt);
+  /* the GFF3 annotation also contains the pseudogenes, which are missing from the PTT file */
+  assert_eq!(report.genes.matched_predicted, ptt.len());
+  assert!(report.genes.sensitivity > 0.95);
+}
+
+#[test]
+fn metadata_and_gff_predictions_agree() {
+  let reference = first_genes(read_annotation(&example("NC_000913.ptt")).unwrap(), 200_000);
+  let metadata = first_genes(read_predictions(&example("NC_000913-fgs.out")).unwrap(), 200_000);
+  let gff = first_genes(read_predictions(&example("NC_000913-fgs.gff")).unwrap(), 200_000);
+  assert_eq!(metadata, gff);
+
+  let report = evaluate(&reference, &metadata);
+  assert!(report.genes.sensitivity > 0.85);
+  assert!(report.genes.specificity > 0.85);
+  assert!(report.starts.accuracy > 0.8);
+  assert!(report.nucleotides.sensitivity > 0.9);
+}
+
+#[test]
+fn invalid_coordinates_are_input_format_errors() {
+  let annotations = [
+    ("start0.gff", "##gff-version 3\nchr\ttest\tCDS\t0\t99\t.\t+\t0\tID=a\n", 2),
+    (
+      "reversed.gff",
+      "##gff-version 3\nchr\ttest\tCDS\t1\t99\t.\t+\t0\tID=a\nchr\ttest\tCDS\t300\t200\t.\t-\t0\t.\n",
+      3,
+    ),
+    ("start0.ptt", "genome - 1..1000\n1 proteins\nLocation\tStrand\tLength\n0..99\t+\t32\n", 4),
+    (
+      "start0.gbk",
+      concat!(
+        "LOCUS       chr 1000 bp\nFEATURES             Location/Qualifiers\n",
+        "     gene            1..99\n     CDS             complement(join(0..50,\n",
+        "                     60..99))\n                     /product=\"a\"\nORIGIN\n"
+      ),
+      4,
+    ),
+    ("start0.out", ">chr\n1\t99\t+\t1\t1.0\tI:\tD:\n0\t99\t+\t1\t1.0\tI:\tD:\n", 3),
+  ];
+  for (name, annotation, line) in annotations {
+    let path = temp_path(name);
+    fs::write(&path, annotation).unwrap();
+    match read_predictions(path.to_str().unwrap()) {
+      Err(Error::InputFormat { record, .. }) => {
+        assert_eq!(record, format!("at line {} of {}", line, path.display()), "{}", name)
+      }
+      other => panic!("expected an input format error for {}, got {:?}", name, other),
+    }
+    fs::remove_file(path).unwrap();
+  }
+}
@@ -0,0 +1,94 @@
+use fgsrs::dna_helpers::nt2int;
+use fgsrs::genetic_code::codon_index;
+use fgsrs::GeneticCode;
+
+/*
+ * genetic_code.rs
+ * ===============
+ * Tests of the NCBI translation tables: table 11 translates like the hardcoded tables of FragGeneScan 1.31, and
+ * tables 4 and 25 reassign the TGA stop codon.
+ */
+
+/* the translation of every codon (in ACGT order) by FragGeneScan 1.31 on the forward and the reverse strand */
+const FGS_CODON_CODE: &str = "KNKNTTTTRSRSIIMIQHQHPPPPRRRRLLLLEDEDAAAAGGGGVVVV*Y*YSSSS*CWCLFLFX";
+const FGS_ANTI_CODON_CODE: &str = "FVLICGRSSAPTYDHNLVLMWGRRSAPT*EQKFVLICGRSSAPTYDHNLVLI*GRRSAPT*EQKX";
+
+fn codon(codon: &str) -> usize {
+  let nts: Vec<usize> = codon.chars().map(nt2int).collect();
+  codon_index(nts[0], nts[1], nts[2])
+}
+
+fn codons() -> Vec<String> {
+  let bases = ['A', 'C', 'G', 'T'];
+  let mut codons = Vec::new();
+  for a in bases {
+    for b in bases {
+      for c in bases {
+        codons.push([a, b, c].iter().collect());
+      }
+    }
+  }
+  codons
+}
+
+#[test]
+fn table_11_matches_fraggenescan() {
+  let code = GeneticCode::default();
+  assert_eq!(code.id(), 11);
+  let forward: String = (0..65).map(|index| code.translate(index)).collect();
+  let reverse: String = (0..65).map(|index| code.translate_reverse(index)).collect();
+  assert_eq!(forward, FGS_CODON_CODE);
+  assert_eq!(reverse, FGS_ANTI_CODON_CODE);
+
+  let starts: Vec<String> = codons().into_iter().filter(|c| code.is_start(codon(c))).collect();
+  assert_eq!(starts, ["ATG", "GTG", "TTG"]);
+  let reverse_starts: Vec<String> = codons().into_iter().filter(|c| code.is_reverse_start(codon(c))).collect();
+  assert_eq!(reverse_starts, ["CAA", "CAC", "CAT"]);
+  let stops: Vec<String> = codons().into_iter().filter(|c| code.is_stop(codon(c))).collect();
+  assert_eq!(stops, ["TAA", "TAG", "TGA"]);
+  let reverse_stops: Vec<String> = codons().into_iter().filter(|c| code.is_reverse_stop(codon(c))).collect();
+  assert_eq!(reverse_stops, ["CTA", "TCA", "TTA"]);
+}
+
+#[test]
+fn table_4_translates_tga_as_tryptophan() {
+  let code = GeneticCode::from_id(4).unwrap();
+  assert_eq!(code.translate(codon("TGA")), 'W');
+  assert_eq!(code.translate_reverse(codon("TCA")), 'W');
+  assert!(!code.is_stop(codon("TGA")) && !code.is_reverse_stop(codon("TCA")));
+  assert!(code.is_stop(codon("TAA")) && code.is_stop(codon("TAG")));
+  /* table 4 also starts with CTG, ATT, ATC and ATA, which are not modeled */
+  let starts: Vec<String> = codons().into_iter().filter(|c| code.is_start(codon(c))).collect();
+  assert_eq!(starts, ["ATG", "GTG", "TTG"]);
+}
+
+#[test]
+fn table_25_translates_tga_as_glycine() {
+  let code = GeneticCode::from_id(25).unwrap();
+  assert_eq!(code.translate(codon("TGA")), 'G');
+  assert_eq!(code.translate_reverse(codon("TCA")), 'G');
+  assert!(!code.is_stop(codon("TGA")));
+  let stops: Vec<String> = codons().into_iter().filter(|c| code.is_stop(codon(c))).collect();
+  assert_eq!(stops, ["TAA", "TAG"]);
+  assert_eq!(code.translate(codon("ATG")), 'M');
+}
+
+#[test]
+fn codons_with_other_characters_get_index_64() {
+  assert_eq!(codon("ACG"), 6);
+  assert_eq!(codon("acg"), 6);
+  for other in ["NCG", "ANG", "ACN", "AC-", "RYK"] {
+    assert_eq!(codon(other), 64);
+  }
+  let code = GeneticCode::default();
+  assert_eq!(code.translate(64), 'X');
+  assert_eq!(code.translate_reverse(64), 'X');
+  assert!(!code.is_start(64) && !code.is_stop(64) && !code.is_reverse_stop(64));
+}
+
+#[test]
+fn unsupported_tables_are_rejected() {
+  for id in [0, 7, 8, 27, 28, 31, 34] {
+    assert!(GeneticCode::from_id(id).is_err(), "table {} should be rejected", id);
+  }
+}
@@ -0,0 +1,376 @@
+use fgsrs::dna_helpers::{get_protein, get_rc_dna};
+use fgsrs::PredictionConfig;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+mod common;
+use common::{crate_dir, example_path, temp_path};
+
+/*
+ * golden.rs
+ * =========
+ * Regression tests against the output of FragGeneScan 1.31 in the example/ folder. The fgsrs binary is run on the
+ * example inputs and its .out, .faa, .ffn and .gff output are compared to the golden files: the coordinates,
+ * strands, frames, insertions and deletions of every gene and its translation and DNA sequence. The scores are not
+ * compared, as the C tool rounds them differently.
+ *
+ * The inputs (NC_000913.fna, NC_000913-454.fna and contigs.fna from the example folder of FragGeneScan 1.31) are
+ * too large to be kept in this repository, so the tests which need them are ignored by default. Copy the inputs into
+ * example/ and run them with `cargo test --test golden -- --ignored`, a missing input fails the test.
+ *
+ * The sample in tests/data is run by default: the first 300 reads of the 454 example, cut to the genes predicted on
+ * them by FragGeneScan 1.31 (the fragments in example/NC_000913-454-fgs.ffn, on the strand of the read). Its
+ * expected output was written by fgsrs, after checking that its genes, frames, insertions, deletions and DNA match
+ * the original port of FragGeneScan 1.31 and that its translations follow get_protein of FragGeneScan 1.31.
+ */
+
+/**
+ * An example run of FragGeneScan 1.31, its input is DIR/INPUT and its output is in DIR/OUTPUT.{out,faa,ffn,gff}
+ * (when present).
+ */
+struct Example {
+  dir: &'static str,
+  input: &'static str,
+  output: &'static str,
+  wholegenome: &'static str,
+  model: &'static str,
+}
+
+const NC_000913: Example = Example {
+  dir: "example",
+  input: "NC_000913.fna",
+  output: "NC_000913-fgs",
+  wholegenome: "1",
+  model: "complete",
+};
+
+const NC_000913_454: Example = Example {
+  dir: "example",
+  input: "NC_000913-454.fna",
+  output: "NC_000913-454-fgs",
+  wholegenome: "0",
+  model: "454_10",
+};
+
+const NC_000913_454_SAMPLE: Example = Example {
+  dir: "tests/data",
+  input: "NC_000913-454-sample.fna",
+  output: "NC_000913-454-sample-fgs",
+  wholegenome: "0",
+  model: "454_10",
+};
+
+const CONTIGS: Example = Example {
+  dir: "example",
+  input: "contigs.fna",
+  output: "contigs-fgs",
+  wholegenome: "1",
+  model: "complete",
+};
+
+/**
+ * The records of an output file, keyed by the header of the FASTA output (e.g. r2.1_1_91_-), with the compared
+ * fields of every record.
+ */
+type Records = BTreeMap<String, Vec<(&'static str, String)>>;
+
+/* the number of divergences shown when an example fails */
+const MAX_REPORTED: usize = 25;
+
+/**
+ * A difference between the golden output and the output of fgsrs.
+ */
+#[derive(Debug, PartialEq)]
+enum Divergence {
+  Missing {
+    file: String,
+    record: String,
+  },
+  Extra {
+    file: String,
+    record: String,
+  },
+  Field {
+    file: String,
+    record: String,
+    field: &'static str,
+    expected: String,
+    actual: String,
+  },
+}
+
+impl fmt::Display for Divergence {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Divergence::Missing { file, record } => write!(f, "{}\tmissing\t{}", file, record),
+      Divergence::Extra { file, record } => write!(f, "{}\textra\t{}", file, record),
+      Divergence::Field {
+        file,
+        record,
+        field,
+        expected,
+        actual,
+      } => write!(f, "{}\t{}\t{}\texpected {}\tgot {}", file, record, field, expected, actual),
+    }
+  }
+}
+
+/**
+ * Reads the genes of a metadata (.out) file, keyed like the FASTA output, with the frame, insertions and deletions
+ * of every gene.
+ */
+fn read_metadata(path: &Path) -> Records {
+  let mut genes = BTreeMap::new();
+  let mut head = String::new();
+  for line in fs::read_to_string(path).unwrap().lines() {
+    if let Some(header) = line.strip_prefix('>') {
+      head = header.split_whitespace().next().unwrap_or("").to_string();
+      continue;
+    }
+    let columns: Vec<&str> = line.split('\t').collect();
+    if columns.len() < 7 {
+      continue;
+    }
+    genes.insert(
+      format!("{}_{}_{}_{}", head, columns[0], columns[1], columns[2]),
+      vec![
+        ("frame", columns[3].to_string()),
+        ("insertions", columns[5].to_string()),
+        ("deletions", columns[6].to_string()),
+      ],
+    );
+  }
+  genes
+}
+
+/**
+ * Reads the records of a FASTA (.faa or .ffn) file.
+ */
+fn read_fasta(path: &Path) -> Records {
+  let mut records = Records::new();
+  let mut head = String::new();
+  for line in fs::read_to_string(path).unwrap().lines() {
+    match line.strip_prefix('>') {
+      Some(header) => {
+        head = header.to_string();
+        records.insert(head.clone(), vec![("sequence", String::new())]);
+      }
+      None => records.get_mut(&head).unwrap()[0].1.push_str(line.trim_end()),
+    }
+  }
+  records
+}
+
+/**
+ * Reads the CDS features of a GFF3 file, keyed by their ID, without the score column and the other attributes (the
+ * partial attribute of fgsrs --partial is not in FragGeneScan 1.31).
+ */
+fn read_gff(path: &Path) -> Records {
+  let mut features = BTreeMap::new();
+  for line in fs::read_to_string(path).unwrap().lines() {
+    let columns: Vec<&str> = line.split('\t').collect();
+    if line.starts_with('#') || columns.len() < 9 {
+      continue;
+    }
+    let id = columns[8].split(';').find_map(|attribute| attribute.strip_prefix("ID=")).unwrap_or("");
+    features.insert(
+      id.to_string(),
+      vec![
+        ("seqid", columns[0].to_string()),
+        ("type", columns[2].to_string()),
+        ("start", columns[3].to_string()),
+        ("end", columns[4].to_string()),
+        ("strand", columns[6].to_string()),
+        ("phase", columns[7].to_string()),
+      ],
+    );
+  }
+  features
+}
+
+/**
+ * Compares the records of a golden file with those written by fgsrs, field by field.
+ */
+fn diff_records(
+  file: &str,
+  expected: &Records,
+  actual: &Records,
+  divergences: &mut Vec<Divergence>,
+) {
+  for (record, fields) in expected {
+    let actual_fields = match actual.get(record) {
+      Some(actual_fields) => actual_fields,
+      None => {
+        divergences.push(Divergence::Missing {
+          file: file.to_string(),
+          record: record.clone(),
+        });
+        continue;
+      }
+    };
+    for ((field, expected), (_, actual)) in fields.iter().zip(actual_fields) {
+      if expected != actual {
+        divergences.push(Divergence::Field {
+          file: file.to_string(),
+          record: record.clone(),
+          field,
+          expected: expected.clone(),
+          actual: actual.clone(),
+        });
+      }
+    }
+  }
+  for record in actual.keys().filter(|record| !expected.contains_key(*record)) {
+    divergences.push(Divergence::Extra {
+      file: file.to_string(),
+      record: record.clone(),
+    });
+  }
+}
+
+/**
+ * Runs fgsrs with the default options on the input of an example and compares its output to the golden files
+ * which are present (not every example has all four).
+ */
+fn run_example(example: &Example) -> Vec<Divergence> {
+  let dir = crate_dir().join(example.dir);
+  let input = dir.join(example.input);
+  assert!(
+    input.exists(),
+    "{} not found, copy it from the example folder of FragGeneScan 1.31",
+    input.display()
+  );
+  let prefix = temp_path(example.output);
+  let mut command = Command::new(env!("CARGO_BIN_EXE_fgsrs"));
+  command
+    .arg("-w")
+    .arg(example.wholegenome)
+    .arg("-t")
+    .arg(example.model)
+    .arg("-m")
+    .arg(crate_dir().join("train"))
+    .arg("-i")
+    .arg(&input)
+    .arg("-o")
+    .arg(&prefix);
+  let status = command.status().unwrap();
+  assert!(status.success(), "fgsrs failed on {}", example.input);
+
+  let mut divergences = Vec::new();
+  for extension in &["out", "faa", "ffn", "gff"] {
+    let read = match *extension {
+      "out" => read_metadata,
+      "gff" => read_gff,
+      _ => read_fasta,
+    };
+    let file = format!("{}.{}", example.output, extension);
+    let golden = dir.join(&file);
+    let output = PathBuf::from(format!("{}.{}", prefix.display(), extension));
+    if golden.exists() {
+      diff_records(&file, &read(&golden), &read(&output), &mut divergences);
+    }
+    fs::remove_file(&output).unwrap();
+  }
+  divergences
+}
+
+/**
+ * Runs an example and fails with a report of the (first MAX_REPORTED) divergences from the golden files.
+ */
+fn check_example(example: &Example) {
+  let divergences = run_example(example);
+  let report: Vec<String> = divergences.iter().take(MAX_REPORTED).map(|d| d.to_string()).collect();
+  assert!(
+    divergences.is_empty(),
+    "{} divergences from FragGeneScan 1.31 on {}:\n{}",
+    divergences.len(),
+    example.input,
+    report.join("\n")
+  );
+}
+
+#[test]
+#[ignore = "needs example/NC_000913.fna from FragGeneScan 1.31"]
+fn golden_complete_genome() {
+  check_example(&NC_000913);
+}
+
+#[test]
+#[ignore = "needs example/NC_000913-454.fna from FragGeneScan 1.31"]
+fn golden_454_reads() {
+  check_example(&NC_000913_454);
+}
+
+#[test]
+fn golden_454_sample() {
+  check_example(&NC_000913_454_SAMPLE);
+}
+
+#[test]
+#[ignore = "needs example/contigs.fna from FragGeneScan 1.31"]
+fn golden_contigs() {
+  check_example(&CONTIGS);
+}
+
+#[test]
+fn golden_translations_match() {
+  let dna = read_fasta(&example_path("NC_000913-454-fgs.ffn"));
+  let proteins = read_fasta(&example_path("NC_000913-454-fgs.faa"));
+  let config = PredictionConfig::new(false);
+  /* the DNA output is on the strand of the gene, reverse genes are translated from the forward strand */
+  let translated = dna
+    .iter()
+    .filter(|(_, fields)| fields[0].1.len() >= 3)
+    .map(|(head, fields)| {
+      let forward = head.ends_with('+');
+      let dna: Vec<char> = match forward {
+        true => fields[0].1.chars().collect(),
+        false => get_rc_dna(&fields[0].1.chars().collect::<Vec<char>>()),
+      };
+      let protein: String = get_protein(&dna, forward, &config).into_iter().collect();
+      (head.clone(), vec![("sequence", protein)])
+    })
+    .collect();
+
+  let mut divergences = Vec::new();
+  diff_records("NC_000913-454-fgs.faa", &proteins, &translated, &mut divergences);
+  let report: Vec<String> = divergences.iter().take(MAX_REPORTED).map(|d| d.to_string()).collect();
+  assert!(divergences.is_empty(), "{} divergences:\n{}", divergences.len(), report.join("\n"));
+}
+
+#[test]
+fn divergences_are_reported_per_field() {
+  let expected = read_metadata(&example_path("NC_000913-454-fgs.out"));
+  let mut actual = read_metadata(&example_path("NC_000913-454-fgs.out"));
+  let mut divergences = Vec::new();
+  diff_records("out", &expected, &actual, &mut divergences);
+  assert!(divergences.is_empty());
+
+  actual.get_mut("r2.1_1_91_-").unwrap()[1].1 = String::from("I:");
+  actual.remove("r1.1_2_79_-");
+  actual.insert(String::from("r1.1_2_80_-"), Vec::new());
+  diff_records("out", &expected, &actual, &mut divergences);
+  assert_eq!(
+    divergences,
+    vec![
+      Divergence::Missing {
+        file: String::from("out"),
+        record: String::from("r1.1_2_79_-"),
+      },
+      Divergence::Field {
+        file: String::from("out"),
+        record: String::from("r2.1_1_91_-"),
+        field: "insertions",
+        expected: String::from("I:38,"),
+        actual: String::from("I:"),
+      },
+      Divergence::Extra {
+        file: String::from("out"),
+        record: String::from("r1.1_2_80_-"),
+      },
+    ]
+  );
+}
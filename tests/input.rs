@@ -0,0 +1,115 @@
+use fgsrs::input::{Record, SequenceReader};
+use fgsrs::Error;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+mod common;
+use common::{crate_dir, example_path, temp_path};
+
+/*
+ * input.rs
+ * ========
+ * Tests of the FASTA and FASTQ readers: multiple records are parsed, malformed FASTQ records are reported as
+ * InputFormat errors and the quality scores of FASTQ input are only used with -q.
+ */
+
+fn read(input: &str) -> Vec<Result<Record, Error>> {
+  SequenceReader::new(input.as_bytes(), "test input").unwrap().collect()
+}
+
+fn input_error(input: &str) -> String {
+  match read(input).pop() {
+    Some(Err(Error::InputFormat { message, .. })) => message,
+    other => panic!("expected an input format error, got {:?}", other),
+  }
+}
+
+/* the first gene fragments of the 454 example, which are long enough to be predicted */
+fn reads() -> Vec<(String, String)> {
+  let ffn = fs::read_to_string(example_path("NC_000913-454-fgs.ffn")).unwrap();
+  let lines: Vec<&str> = ffn.lines().collect();
+  lines
+    .chunks(2)
+    .map(|record| (record[0][1..].replace('.', "_"), record[1].to_string()))
+    .filter(|(_, seq)| seq.len() > 80)
+    .take(40)
+    .collect()
+}
+
+fn fgsrs(args: &[&str], input: &Path) -> String {
+  let output = Command::new(env!("CARGO_BIN_EXE_fgsrs"))
+    .args(["-w", "0", "-t", "454_10", "-m"])
+    .arg(crate_dir().join("train"))
+    .args(args)
+    .stdin(fs::File::open(input).unwrap())
+    .output()
+    .unwrap();
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn fastq_records_are_parsed() {
+  let records = read("@read1 first\nACGTACGT\n+\nIIIIIIII\n\n@read2\nGGCC\n+read2\n!#5~\n");
+  let records: Vec<Record> = records.into_iter().map(Result::unwrap).collect();
+  assert_eq!(records.len(), 2);
+  assert_eq!((records[0].id.as_str(), records[0].seq.as_str()), ("read1", "ACGTACGT"));
+  assert_eq!(records[0].quality, Some(vec![40; 8]));
+  assert_eq!((records[1].id.as_str(), records[1].seq.as_str()), ("read2", "GGCC"));
+  assert_eq!(records[1].quality, Some(vec![0, 2, 20, 93]));
+
+  let records = read(">seq1 topology=circular\nACGT\nACGT\n>seq2\nGG\n");
+  let records: Vec<Record> = records.into_iter().map(Result::unwrap).collect();
+  assert_eq!(records.len(), 2);
+  assert_eq!(records[0].seq, "ACGTACGT");
+  assert!(records[0].circular && records[0].quality.is_none());
+  assert_eq!(records[1].seq, "GG");
+}
+
+#[test]
+fn malformed_fastq_records_are_errors() {
+  assert!(input_error("@read1\nACGT\n+\nIII\n").contains("4 bases but 3 quality scores"));
+  assert!(input_error("@read1\nACGT\n+\nIIIII\n").contains("4 bases but 5 quality scores"));
+  assert!(input_error("@read1\nACGT\n+\n").contains("missing quality line"));
+  assert!(input_error("@read1\nACGT\n").contains("missing '+' line"));
+  assert!(input_error("@read1\n").contains("missing sequence line"));
+  assert!(input_error("@read1\nACGT\nIIII\nIIII\n").contains("expected '+'"));
+  assert!(input_error("@read1\nACGT\n+\nII I\n").contains("outside of '!'..'~'"));
+  assert!(input_error("@read1\nACGT\n+\nIIII\nread2\nACGT\n+\nIIII\n").contains("expected '@'"));
+
+  /* the valid records before a malformed one are still read */
+  let records = read("@read1\nACGT\n+\nIIII\n@read2\nACGT\n+\nII\n");
+  assert!(records[0].is_ok());
+  match &records[1] {
+    Err(Error::InputFormat { record, .. }) => assert_eq!(record, "read2"),
+    other => panic!("expected an input format error, got {:?}", other),
+  }
+}
+
+#[test]
+fn quality_scores_are_only_used_with_q() {
+  let reads = reads();
+  let fasta: String = reads.iter().map(|(id, seq)| format!(">{}\n{}\n", id, seq)).collect();
+  /* every third base has a low quality score */
+  let fastq: String = reads
+    .iter()
+    .map(|(id, seq)| {
+      let quality: String = (0..seq.len()).map(|i| if i % 3 == 0 { '#' } else { 'I' }).collect();
+      format!("@{}\n{}\n+\n{}\n", id, seq, quality)
+    })
+    .collect();
+  let (fasta_path, fastq_path) = (temp_path("reads.fna"), temp_path("reads.fq"));
+  fs::write(&fasta_path, fasta).unwrap();
+  fs::write(&fastq_path, fastq).unwrap();
+
+  let from_fasta = fgsrs(&[], &fasta_path);
+  assert!(!from_fasta.is_empty());
+  assert_eq!(fgsrs(&[], &fastq_path), from_fasta);
+  assert_ne!(fgsrs(&["-q"], &fastq_path), from_fasta);
+  /* FASTA input has no quality scores to use */
+  assert_eq!(fgsrs(&["-q"], &fasta_path), from_fasta);
+
+  fs::remove_file(fasta_path).unwrap();
+  fs::remove_file(fastq_path).unwrap();
+}
@@ -0,0 +1,91 @@
+use fgsrs::input::Record;
+use fgsrs::model_selection::{read_name_platform, select_model, ReadStatistics};
+use fgsrs::simulation::Platform;
+
+/*
+ * model_selection.rs
+ * ==================
+ * Tests of the selection of the train model (-t auto) from the statistics of a sample of reads.
+ */
+
+fn record(id: &str, seq: &str, quality: Option<u8>) -> Record {
+  Record {
+    id: id.to_string(),
+    seq: seq.to_string(),
+    quality: quality.map(|quality| vec![quality; seq.len()]),
+    circular: false,
+    cg_content: None,
+  }
+}
+
+/* reads of the given lengths cut from a repetitive sequence without homopolymer runs */
+fn reads(lengths: &[usize], quality: Option<u8>) -> Vec<Record> {
+  let sequence = "ACGTTGCAGTCAGGATCCTAGCATGACTGA".repeat(100);
+  lengths
+    .iter()
+    .enumerate()
+    .map(|(i, &length)| record(&format!("read{}", i), &sequence[i % 30..i % 30 + length], quality))
+    .collect()
+}
+
+#[test]
+fn read_names_give_the_platform() {
+  assert_eq!(read_name_platform("M00123:45:000000000-A1B2C:1:1101:15589:1331"), Some(Platform::Illumina));
+  assert_eq!(read_name_platform("HWUSI-EAS100R:6:73:941:1973#0/1"), Some(Platform::Illumina));
+  assert_eq!(read_name_platform("GA8RIAI01A2J5E"), Some(Platform::Roche454));
+  assert_eq!(read_name_platform("r1.1"), None);
+  assert_eq!(read_name_platform("NC_000913.3"), None);
+}
+
+#[test]
+fn statistics_of_the_sample() {
+  let statistics = ReadStatistics::from_records(&reads(&[100, 100, 200, 200, 300], Some(30)));
+  assert_eq!(statistics.reads, 5);
+  assert_eq!(statistics.median_length, 200);
+  assert!((statistics.length_cv - 0.4157).abs() < 1e-3);
+  assert!((statistics.mean_error.unwrap() - 0.001).abs() < 1e-9);
+  assert_eq!(statistics.named_platform, None);
+  assert!(ReadStatistics::from_records(&reads(&[100], None)).mean_error.is_none());
+}
+
+#[test]
+fn models_are_selected_by_length_and_quality() {
+  let select = |lengths: &[usize], quality: Option<u8>| {
+    select_model(&ReadStatistics::from_records(&reads(lengths, quality)), false, None).model
+  };
+  assert_eq!(select(&[100; 20], None), "illumina_5");
+  assert_eq!(select(&[100; 20], Some(30)), "illumina_1");
+  assert_eq!(select(&[100; 20], Some(20)), "illumina_10");
+  assert_eq!(select(&[700; 20], Some(23)), "sanger_5");
+  assert_eq!(select(&[150, 250, 350, 450, 300, 200], None), "454_10");
+  assert_eq!(select(&[150, 250, 350, 450, 300, 200], Some(15)), "454_30");
+  assert_eq!(select(&[2500; 3], None), "complete");
+}
+
+#[test]
+fn hints_and_whole_genomes_override_the_statistics() {
+  let statistics = ReadStatistics::from_records(&reads(&[100; 20], Some(20)));
+  assert_eq!(select_model(&statistics, false, Some(Platform::Roche454)).model, "454_10");
+  assert_eq!(select_model(&statistics, false, Some(Platform::ErrorFree)).model, "complete");
+  assert_eq!(select_model(&statistics, true, None).model, "complete");
+
+  let named: Vec<Record> = (0..20).map(|i| record(&format!("GA8RIAI01A2J{:02}", i), "ACGTACGTAC", None)).collect();
+  let choice = select_model(&ReadStatistics::from_records(&named), false, None);
+  assert_eq!(choice.model, "454_10");
+  assert!(choice.reason.contains("named like 454 reads"));
+}
+
+#[test]
+fn homopolymer_errors_select_a_454_model() {
+  /* the reads have variable lengths and a lengthened homopolymer run every 30 bp */
+  let lengths: Vec<usize> = (0..50).map(|i| 150 + 3 * i).collect();
+  let mut sample = reads(&lengths, None);
+  for read in &mut sample {
+    for position in (0..read.seq.len()).step_by(30).rev() {
+      read.seq.insert_str(position, "AAAAA");
+    }
+  }
+  let choice = select_model(&ReadStatistics::from_records(&sample), false, None);
+  assert_eq!(choice.model, "454_30");
+  assert!(choice.reason.contains("homopolymer runs"));
+}
@@ -0,0 +1,170 @@
+use fgsrs::dna_helpers::{get_cg_bin, get_cg_content};
+use fgsrs::helpers::create_file_if_not_exists;
+use fgsrs::output::{print_prediction, OutputFiles};
+use fgsrs::{Out, Prediction};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+mod common;
+use common::{crate_dir, example_path, gene_fragments, temp_path};
+
+/*
+ * output.rs
+ * =========
+ * Tests that the metadata and GFF3 output are written in the format of FragGeneScan 1.31: the predictions in the
+ * .out files of the example/ folder are written again and compared byte for byte with the example output. The
+ * partial flags and CG bins of fgsrs are only written when they are requested.
+ */
+
+/**
+ * Reads the predictions of a metadata (.out) file, only the fields written to the metadata and GFF3 output are set.
+ */
+fn read_predictions(path: &Path) -> Vec<Prediction> {
+  let mut predictions: Vec<Prediction> = Vec::new();
+  for line in fs::read_to_string(path).unwrap().lines() {
+    if let Some(head) = line.strip_prefix('>') {
+      predictions.push(Prediction {
+        head: head.to_string(),
+        ..Prediction::default()
+      });
+      continue;
+    }
+    let columns: Vec<&str> = line.split('\t').collect();
+    let positions = |column: &str, prefix: &str| -> Vec<usize> {
+      column.strip_prefix(prefix).unwrap().split_terminator(',').map(|i| i.parse().unwrap()).collect()
+    };
+    predictions.last_mut().unwrap().outs.push(Out {
+      dna_start_t: columns[0].parse().unwrap(),
+      dna_end_t: columns[1].parse().unwrap(),
+      forward: columns[2] == "+",
+      frame: columns[3].parse().unwrap(),
+      final_score: columns[4].parse().unwrap(),
+      insert: positions(columns[5], "I:"),
+      delete: positions(columns[6], "D:"),
+      /* the partial flags are only written with --partial */
+      partial_start: true,
+      partial_end: true,
+      ..Out::default()
+    });
+  }
+  predictions
+}
+
+/**
+ * Writes the predictions to a metadata and a GFF3 file and returns their contents.
+ */
+fn write_predictions(name: &str, predictions: Vec<Prediction>) -> (String, String) {
+  let paths: Vec<PathBuf> = ["out", "gff", "faa"].iter().map(|ext| temp_path(&format!("{}.{}", name, ext))).collect();
+  let file = |path: &PathBuf| create_file_if_not_exists(path.to_str().unwrap()).unwrap();
+  let mut output = OutputFiles {
+    metadata: Some(file(&paths[0])),
+    protein: Some(file(&paths[2])),
+    ..OutputFiles::default()
+  };
+  output.set_gff(file(&paths[1])).unwrap();
+  let output = Mutex::new(output);
+  for prediction in predictions {
+    print_prediction(prediction, &output).unwrap();
+  }
+  drop(output);
+
+  let contents: Vec<String> = paths.iter().map(|path| fs::read_to_string(path).unwrap()).collect();
+  for path in paths {
+    fs::remove_file(path).unwrap();
+  }
+  (contents[0].clone(), contents[1].clone())
+}
+
+/**
+ * Runs fgsrs on the specified FASTA file with the specified options and returns the metadata and GFF3 output.
+ */
+fn fgsrs(name: &str, input: &Path, args: &[&str]) -> (String, String) {
+  let prefix = temp_path(name);
+  let output = Command::new(env!("CARGO_BIN_EXE_fgsrs"))
+    .args(["-w", "0", "-t", "454_10", "-m"])
+    .arg(crate_dir().join("train"))
+    .args(args)
+    .arg("-i")
+    .arg(input)
+    .arg("-o")
+    .arg(&prefix)
+    .output()
+    .unwrap();
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  let read = |ext: &str| {
+    let path = format!("{}.{}", prefix.display(), ext);
+    let contents = fs::read_to_string(&path).unwrap();
+    fs::remove_file(path).unwrap();
+    contents
+  };
+  let (metadata, gff) = (read("out"), read("gff"));
+  read("faa");
+  read("ffn");
+  (metadata, gff)
+}
+
+#[test]
+fn example_output_is_written_again_byte_for_byte() {
+  for example in ["NC_000913-fgs", "NC_000913-454-fgs", "contigs-fgs"] {
+    let (metadata, gff) = write_predictions(example, read_predictions(&example_path(&format!("{}.out", example))));
+    assert!(metadata == fs::read_to_string(example_path(&format!("{}.out", example))).unwrap(), "{}.out", example);
+    assert!(gff == fs::read_to_string(example_path(&format!("{}.gff", example))).unwrap(), "{}.gff", example);
+  }
+}
+
+#[test]
+fn partial_flags_are_only_written_with_partial() {
+  /* reads cut from the inside of the genes of the example, so most genes run off both ends of their read */
+  let input = temp_path("partial-reads.fna");
+  let reads: String = gene_fragments()
+    .iter()
+    .take(300)
+    .enumerate()
+    .map(|(i, fragment)| format!(">read{}\n{}\n", i, &fragment[15..fragment.len() - 15]))
+    .collect();
+  fs::write(&input, reads).unwrap();
+  let (metadata, gff) = fgsrs("default", &input, &[]);
+  let (partial_metadata, partial_gff) = fgsrs("partial", &input, &["--partial"]);
+  fs::remove_file(&input).unwrap();
+
+  assert!(!metadata.contains("partial=") && !gff.contains("partial="));
+  assert!(partial_metadata.lines().filter(|line| line.ends_with("\tpartial=11")).count() > 100);
+  assert!(partial_gff.lines().filter(|line| line.ends_with(";partial=11")).count() > 100);
+  let strip = |output: &str, separator: &str| {
+    output.lines().map(|line| format!("{}\n", line.split(separator).next().unwrap())).collect::<String>()
+  };
+  assert!(strip(&partial_metadata, "\tpartial=") == metadata);
+  assert!(strip(&partial_gff, ";partial=") == gff);
+}
+
+#[test]
+fn cg_bins_are_only_written_with_cg_bin() {
+  let input = crate_dir().join("tests/data/NC_000913-454-sample.fna");
+  let reads: Vec<(String, String)> = fs::read_to_string(&input)
+    .unwrap()
+    .lines()
+    .collect::<Vec<&str>>()
+    .chunks(2)
+    .map(|record| (record[0][1..].to_string(), record[1].to_string()))
+    .collect();
+  let headers = |name: &str, args: &[&str]| -> Vec<String> {
+    let (metadata, _) = fgsrs(name, &input, args);
+    metadata.lines().filter(|line| line.starts_with('>')).map(String::from).collect()
+  };
+  let expected = |cg_bin: &dyn Fn(&str) -> usize| -> Vec<String> {
+    reads.iter().map(|(id, seq)| format!(">{}\tcg_bin={}", id, cg_bin(seq))).collect()
+  };
+
+  let ids: Vec<String> = reads.iter().map(|(id, _)| format!(">{}", id)).collect();
+  assert_eq!(headers("no-cg-bin", &[]), ids);
+  let sequence_bins = expected(&|seq| get_cg_bin(get_cg_content(seq)));
+  assert_eq!(headers("cg-bin-sequence", &["--cg-bin", "sequence"]), sequence_bins);
+  /* the reads have no gc= in their header, so they fall back to their own CG content */
+  assert_eq!(headers("cg-bin-header", &["--cg-bin", "header"]), sequence_bins);
+  let all: String = reads.iter().map(|(_, seq)| seq.as_str()).collect();
+  let file_bin = get_cg_bin(get_cg_content(&all));
+  assert_eq!(headers("cg-bin-file", &["--cg-bin", "file"]), expected(&|_| file_bin));
+  assert_eq!(headers("cg-bin-26", &["--cg-bin", "26"]), expected(&|_| 26));
+}
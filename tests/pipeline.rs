@@ -0,0 +1,155 @@
+use fgsrs::helpers::create_file_if_not_exists;
+use fgsrs::input::Record;
+use fgsrs::output::OutputFiles;
+use fgsrs::pipeline::{run_pipeline, run_pipeline_with, CHUNKS_IN_FLIGHT, CHUNK_RECORDS};
+use fgsrs::{Error, GenePredictor, PredictionConfig};
+use std::collections::HashSet;
+use std::fs;
+use std::sync::mpsc::channel;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/*
+ * pipeline.rs
+ * ===========
+ * Tests that the streaming pipeline writes the predictions of more records than fit in its bounded buffers in input
+ * order with any number of threads, that the records of an input smaller than a chunk are spread over the threads and
+ * that a reader error stops the pipeline instead of deadlocking it.
+ */
+
+mod common;
+use common::temp_path;
+
+fn predictor() -> GenePredictor {
+  common::predictor("454_10", PredictionConfig::new(false))
+}
+
+/* short reads are skipped by viterbi, every 64th read is long enough to be predicted so workers take turns */
+fn reads(count: usize) -> impl Iterator<Item = Result<Record, Error>> + Send {
+  let sequence = "ATGAAACGCATTAGCACCACCATTACCACCACCATCACCATTACCACAGGTAACGGTGCGGGCTGACGCGTACAGGAAACACAGAAAAAAGCCCGCAC";
+  (0..count).map(move |i| {
+    Ok(Record {
+      id: format!("read{}", i),
+      seq: match i % 64 {
+        0 => sequence.to_string(),
+        _ => sequence[i % 50..i % 50 + 20].to_string(),
+      },
+      quality: None,
+      circular: false,
+      cg_content: None,
+    })
+  })
+}
+
+/**
+ * Runs the pipeline with the specified number of threads and returns the ids of the records in the order of the
+ * metadata output. The pipeline runs on its own thread, so a deadlock fails the test instead of hanging it.
+ */
+fn run<I>(name: &str, records: I, threads: usize, ordered: bool) -> (Result<(), Error>, Vec<String>)
+where
+  I: Iterator<Item = Result<Record, Error>> + Send + 'static,
+{
+  let (metadata, protein) = (temp_path(&format!("{}.out", name)), temp_path(&format!("{}.faa", name)));
+  let output = Mutex::new(OutputFiles {
+    protein: Some(create_file_if_not_exists(protein.to_str().unwrap()).unwrap()),
+    metadata: Some(create_file_if_not_exists(metadata.to_str().unwrap()).unwrap()),
+    ..OutputFiles::default()
+  });
+
+  let (sender, receiver) = channel();
+  thread::spawn(move || {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+    let result = pool.install(|| run_pipeline(records, &predictor(), &output, ordered));
+    drop(output);
+    sender.send(result).unwrap();
+  });
+  let result = receiver.recv_timeout(Duration::from_secs(300)).expect("the pipeline did not finish");
+
+  let ids = fs::read_to_string(&metadata)
+    .unwrap()
+    .lines()
+    .filter_map(|line| line.strip_prefix('>'))
+    .map(|line| line.split('\t').next().unwrap().to_string())
+    .collect();
+  fs::remove_file(metadata).unwrap();
+  fs::remove_file(protein).unwrap();
+  (result, ids)
+}
+
+#[test]
+fn output_is_in_input_order_with_any_number_of_threads() {
+  for threads in [1, 2, 4] {
+    let count = (CHUNKS_IN_FLIGHT * threads + 3) * CHUNK_RECORDS + 17;
+    let expected: Vec<String> = (0..count).map(|i| format!("read{}", i)).collect();
+    let (result, ids) = run(&format!("ordered-{}", threads), reads(count), threads, true);
+    assert!(result.is_ok());
+    assert!(ids == expected, "the output of {} threads is not in input order", threads);
+  }
+}
+
+#[test]
+fn unordered_output_has_every_record() {
+  let threads = 2;
+  let count = (CHUNKS_IN_FLIGHT * threads + 3) * CHUNK_RECORDS + 17;
+  let (result, mut ids) = run("unordered", reads(count), threads, false);
+  assert!(result.is_ok());
+  ids.sort_by_key(|id| id[4..].parse::<usize>().unwrap());
+  assert!(ids == (0..count).map(|i| format!("read{}", i)).collect::<Vec<String>>());
+}
+
+#[test]
+fn records_of_a_single_chunk_are_spread_over_the_threads() {
+  let predictor = predictor();
+  for ordered in [true, false] {
+    let metadata = temp_path(&format!("spread-{}.out", ordered));
+    let protein = temp_path(&format!("spread-{}.faa", ordered));
+    let output = Mutex::new(OutputFiles {
+      protein: Some(create_file_if_not_exists(protein.to_str().unwrap()).unwrap()),
+      metadata: Some(create_file_if_not_exists(metadata.to_str().unwrap()).unwrap()),
+      ..OutputFiles::default()
+    });
+    let threads = Mutex::new(HashSet::new());
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+    let result = pool.install(|| {
+      run_pipeline_with(
+        reads(CHUNK_RECORDS / 16),
+        |record| {
+          /* a slow prediction, so the other threads get to steal the remaining records of the chunk */
+          thread::sleep(Duration::from_millis(20));
+          threads.lock().unwrap().insert(rayon::current_thread_index());
+          predictor.predict(&record.id, &record.seq)
+        },
+        &output,
+        ordered,
+      )
+    });
+    assert!(result.is_ok());
+    drop(output);
+    let records = fs::read_to_string(&metadata).unwrap().lines().filter(|line| line.starts_with('>')).count();
+    assert_eq!(records, CHUNK_RECORDS / 16);
+    assert!(threads.into_inner().unwrap().len() > 1, "a single chunk was predicted by a single thread");
+    fs::remove_file(metadata).unwrap();
+    fs::remove_file(protein).unwrap();
+  }
+}
+
+#[test]
+fn reader_errors_stop_the_pipeline() {
+  let threads = 2;
+  /* an error in the first chunk, and an error after more chunks than fit in the pipeline */
+  for valid in [10, (CHUNKS_IN_FLIGHT * threads + 2) * CHUNK_RECORDS + 5] {
+    for ordered in [true, false] {
+      let records = reads(valid).chain(std::iter::once(Err(Error::InputFormat {
+        record: String::from("broken"),
+        message: String::from("truncated record"),
+      })));
+      let (result, ids) = run(&format!("error-{}-{}", valid, ordered), records, threads, ordered);
+      match result {
+        Err(Error::InputFormat { record, .. }) => assert_eq!(record, "broken"),
+        other => panic!("expected the reader error, got {:?}", other),
+      }
+      assert!(ids.len() <= valid);
+    }
+  }
+}
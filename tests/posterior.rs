@@ -0,0 +1,105 @@
+use fgsrs::{PosteriorMode, Prediction, PredictionConfig};
+
+mod common;
+use common::{chromosome, predictor};
+
+/*
+ * posterior.rs
+ * ============
+ * Tests that the forward-backward posteriors are probabilities, that a base is not coding on both strands with a
+ * total probability above 1, that the genes of the Viterbi path get a high posterior and that predicting a sequence
+ * in windows or across its origin gives the same posteriors as a single pass.
+ */
+
+fn predict(model: &str, config: PredictionConfig, sequence: &str) -> Prediction {
+  let predictor = predictor(model, config.clone());
+  match config.circular() {
+    true => predictor.predict_circular("seq", sequence, None),
+    false => predictor.predict("seq", sequence),
+  }
+}
+
+fn base_config(wholegenome: bool) -> PredictionConfig {
+  PredictionConfig::new(wholegenome).with_posteriors(PosteriorMode::Bases)
+}
+
+fn max_difference(a: &[f64], b: &[f64]) -> f64 {
+  assert_eq!(a.len(), b.len());
+  a.iter().zip(b).map(|(a, b)| (a - b).abs()).fold(0.0, f64::max)
+}
+
+#[test]
+fn posteriors_are_probabilities() {
+  let (sequence, _) = chromosome(200);
+  for (model, wholegenome) in [("complete", true), ("454_10", false)] {
+    let prediction = predict(model, base_config(wholegenome), &sequence);
+    let bases = prediction.posteriors.as_ref().unwrap();
+    assert_eq!(bases.forward.len(), sequence.len());
+    assert_eq!(bases.reverse.len(), sequence.len());
+    for (forward, reverse) in bases.forward.iter().zip(&bases.reverse) {
+      assert!((0.0..=1.0).contains(forward) && (0.0..=1.0).contains(reverse));
+      /* a base is coding on at most one strand, up to the rounding of the -log sums */
+      assert!(forward + reverse <= 1.0 + 1e-6, "{} + {} > 1", forward, reverse);
+    }
+
+    assert!(!prediction.outs.is_empty());
+    for out in &prediction.outs {
+      let posterior = out.posterior.unwrap();
+      for probability in [posterior.coding, posterior.start, posterior.stop] {
+        assert!((0.0..=1.0).contains(&probability));
+      }
+    }
+  }
+}
+
+#[test]
+fn genes_of_the_viterbi_path_have_a_high_posterior() {
+  let (sequence, spacers) = chromosome(200);
+  let prediction = predict("complete", base_config(true), &sequence);
+  let coding: Vec<f64> = prediction.outs.iter().map(|out| out.posterior.unwrap().coding).collect();
+  let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+  assert!(mean(&coding) > 0.9, "mean gene posterior {}", mean(&coding));
+  assert!(coding.iter().all(|&posterior| posterior > 0.5));
+
+  /* the pseudo-random spacers are mostly non-coding */
+  let bases = prediction.posteriors.unwrap();
+  let spacer_coding: Vec<f64> = spacers
+    .iter()
+    .flat_map(|&(start, end)| start..end)
+    .map(|t| bases.forward[t] + bases.reverse[t])
+    .collect();
+  assert!(mean(&spacer_coding) < mean(&coding));
+}
+
+#[test]
+fn windowed_posteriors_match_single_pass() {
+  let (sequence, _) = chromosome(200);
+  let single = predict("complete", base_config(true), &sequence);
+  let windowed = predict("complete", base_config(true).with_windows(Some(10000), 4000).unwrap(), &sequence);
+  let (single_bases, windowed_bases) = (single.posteriors.unwrap(), windowed.posteriors.unwrap());
+  assert!(max_difference(&single_bases.forward, &windowed_bases.forward) < 1e-6);
+  assert!(max_difference(&single_bases.reverse, &windowed_bases.reverse) < 1e-6);
+
+  assert_eq!(single.outs.len(), windowed.outs.len());
+  for (out, windowed_out) in single.outs.iter().zip(&windowed.outs) {
+    let (posterior, windowed_posterior) = (out.posterior.unwrap(), windowed_out.posterior.unwrap());
+    assert!((posterior.coding - windowed_posterior.coding).abs() < 1e-6);
+    assert!((posterior.start - windowed_posterior.start).abs() < 1e-6);
+    assert!((posterior.stop - windowed_posterior.stop).abs() < 1e-6);
+  }
+}
+
+#[test]
+fn circular_posteriors_match_single_pass_away_from_the_origin() {
+  let (sequence, _) = chromosome(200);
+  let overlap = 5000;
+  let linear = predict("complete", base_config(true), &sequence);
+  let config = base_config(true).with_windows(None, overlap).unwrap().with_circular(true);
+  let circular = predict("complete", config, &sequence);
+  let (linear_bases, circular_bases) = (linear.posteriors.unwrap(), circular.posteriors.unwrap());
+  assert_eq!(circular_bases.forward.len(), sequence.len());
+
+  let middle = overlap..sequence.len() - overlap;
+  assert!(max_difference(&linear_bases.forward[middle.clone()], &circular_bases.forward[middle.clone()]) < 1e-6);
+  assert!(max_difference(&linear_bases.reverse[middle.clone()], &circular_bases.reverse[middle]) < 1e-6);
+}
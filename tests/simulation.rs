@@ -0,0 +1,97 @@
+use fgsrs::annotation::CdsFeature;
+use fgsrs::simulation::{ErrorProfile, Platform, ReadSimulator, SimulatedRead, SimulationConfig};
+
+mod common;
+use common::{gene_fragments, reverse_complement};
+
+/*
+ * simulation.rs
+ * =============
+ * Tests of the read simulator on a genome built from the coding fragments of example/NC_000913-454-fgs.ffn, every
+ * fragment is annotated as a gene on alternating strands.
+ */
+
+fn genome() -> (Vec<(String, String)>, Vec<CdsFeature>) {
+  let mut sequence = String::new();
+  let mut features = Vec::new();
+  for (i, fragment) in gene_fragments().iter().take(300).enumerate() {
+    sequence.push_str(&"TTAGGCTAAC"[..3 + i % 8]);
+    let forward = i % 2 == 0;
+    features.push(CdsFeature {
+      seqid: String::from("chromosome"),
+      start: sequence.len() + 1,
+      end: sequence.len() + fragment.len(),
+      forward,
+    });
+    match forward {
+      true => sequence.push_str(fragment),
+      false => sequence.push_str(&reverse_complement(fragment)),
+    }
+  }
+  (vec![(String::from("chromosome"), sequence)], features)
+}
+
+fn simulate(model: &str, reads: usize) -> Vec<SimulatedRead> {
+  let (genome, features) = genome();
+  let config = SimulationConfig::new(ErrorProfile::from_name(model).unwrap())
+    .with_reads(reads)
+    .with_length(150, 20.0)
+    .unwrap()
+    .with_seed(7);
+  ReadSimulator::new(&genome, &features, config).unwrap().collect()
+}
+
+#[test]
+fn error_models_are_named_like_the_models() {
+  assert_eq!(ErrorProfile::from_name("454_10").unwrap().platform, Platform::Roche454);
+  assert_eq!(ErrorProfile::from_name("454_10").unwrap().error_rate, 0.01);
+  assert_eq!(ErrorProfile::from_name("illumina_0.5").unwrap().error_rate, 0.0005);
+  assert_eq!(ErrorProfile::from_name("complete").unwrap().error_rate, 0.0);
+  assert!(ErrorProfile::from_name("nanopore_10").is_err());
+  assert!(ErrorProfile::from_name("sanger").is_err());
+}
+
+#[test]
+fn error_free_reads_match_the_genome_and_its_genes() {
+  let (genome, features) = genome();
+  let sequence = &genome[0].1;
+  for read in simulate("complete", 500) {
+    assert_eq!(read.errors, 0);
+    let (position, strand) = read.source.strip_prefix("chromosome:").unwrap().split_once(':').unwrap();
+    let (start, end) = position.split_once('-').unwrap();
+    let (start, end): (usize, usize) = (start.parse().unwrap(), end.parse().unwrap());
+    let forward = strand == "+";
+    let fragment = &sequence[start - 1..end];
+    assert_eq!(read.seq, if forward { fragment.to_string() } else { reverse_complement(fragment) });
+
+    for gene in &read.genes {
+      /* back to the positions on the genome */
+      let (from, to) = match forward {
+        true => (start + gene.feature.start - 1, start + gene.feature.end - 1),
+        false => (end + 1 - gene.feature.end, end + 1 - gene.feature.start),
+      };
+      let annotated = features
+        .iter()
+        .find(|feature| feature.start <= from && to <= feature.end)
+        .unwrap();
+      assert_eq!(annotated.forward, gene.feature.forward == forward);
+      assert_eq!(gene.partial_left || gene.partial_right, annotated.start < from || to < annotated.end);
+    }
+  }
+}
+
+#[test]
+fn reads_have_the_error_rate_of_the_model() {
+  for model in &["sanger_5", "454_10", "illumina_1"] {
+    let reads = simulate(model, 2000);
+    let errors: usize = reads.iter().map(|read| read.errors).sum();
+    let bases: usize = reads.iter().map(|read| read.seq.len()).sum();
+    let rate = ErrorProfile::from_name(model).unwrap().error_rate;
+    assert!((errors as f64 / bases as f64 - rate).abs() < rate * 0.1, "{}", model);
+  }
+}
+
+#[test]
+fn same_seed_gives_same_reads() {
+  assert_eq!(simulate("454_10", 100), simulate("454_10", 100));
+}
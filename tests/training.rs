@@ -0,0 +1,95 @@
+use fgsrs::{GenePredictor, PredictionConfig, Train, HMM};
+use std::fs;
+use std::process::Command;
+
+mod common;
+use common::{crate_dir, reverse_complement, temp_path, Random};
+
+/*
+ * training.rs
+ * ===========
+ * Tests the train subcommand end to end: the model files trained on a small annotated genome are loaded like the
+ * bundled model files and predict the annotated genes of that genome.
+ */
+
+/* the codons of the synthetic genes, with a codon usage unlike the intergenic regions */
+const CODONS: &[&str] = &["GCC", "GAA", "AAA", "CTG", "GGC", "CGT", "GAT", "ATC", "CAG", "ACC", "GTG", "TTC"];
+const MODEL_FILES: &[&str] = &["gene", "rgene", "noncoding", "start", "stop", "start1", "stop1", "pwm"];
+
+/**
+ * Builds a genome of synthetic genes on alternating strands separated by pseudo-random intergenic regions, returns
+ * the sequence and the annotated genes as (start, end, forward) with 1-based inclusive coordinates.
+ */
+fn genome() -> (String, Vec<(usize, usize, bool)>) {
+  let mut random = Random::new(7);
+
+  let mut sequence = String::new();
+  let mut genes = Vec::new();
+  for i in 0..80 {
+    let spacer = 80 + random.below(120);
+    sequence.push_str(&random.bases(spacer));
+    let mut gene = String::from(["ATG", "ATG", "GTG", "TTG"][random.below(4)]);
+    for _ in 0..100 + random.below(200) {
+      gene.push_str(CODONS[random.below(CODONS.len())]);
+    }
+    gene.push_str(["TAA", "TGA", "TAG"][random.below(3)]);
+    let forward = i % 2 == 0;
+    genes.push((sequence.len() + 1, sequence.len() + gene.len(), forward));
+    match forward {
+      true => sequence.push_str(&gene),
+      false => sequence.push_str(&reverse_complement(&gene)),
+    }
+  }
+  sequence.push_str(&random.bases(100));
+  (sequence, genes)
+}
+
+#[test]
+fn trained_model_predicts_the_training_genes() {
+  let (sequence, genes) = genome();
+  let dir = temp_path("training");
+  let model_dir = dir.join("model");
+  fs::create_dir_all(&dir).unwrap();
+  let (fasta, gff) = (dir.join("genome.fna"), dir.join("genome.gff"));
+  fs::write(&fasta, format!(">chromosome\n{}\n", sequence)).unwrap();
+  let mut annotation = String::from("##gff-version 3\n");
+  for (i, (start, end, forward)) in genes.iter().enumerate() {
+    let strand = if *forward { '+' } else { '-' };
+    annotation.push_str(&format!("chromosome\ttest\tCDS\t{}\t{}\t.\t{}\t0\tID=gene{}\n", start, end, strand, i));
+  }
+  fs::write(&gff, annotation).unwrap();
+
+  let output = Command::new(env!("CARGO_BIN_EXE_fgsrs"))
+    .arg("train")
+    .arg("-g")
+    .arg(&fasta)
+    .arg("-a")
+    .arg(&gff)
+    .arg("-o")
+    .arg(&model_dir)
+    .output()
+    .unwrap();
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  for file in MODEL_FILES {
+    assert!(model_dir.join(file).exists(), "{} was not written", file);
+  }
+
+  /* the trained global files are used with the bundled whole genome model */
+  let train = Train::from_dir(&model_dir).unwrap();
+  let hmm = HMM::from_file(&crate_dir().join("train/complete").to_string_lossy()).unwrap();
+  let predictor = GenePredictor::new(train, hmm, true).with_config(PredictionConfig::new(true));
+  let prediction = predictor.predict("chromosome", &sequence);
+
+  /* genes are matched by their stop codon and strand */
+  let found = genes
+    .iter()
+    .filter(|&&(start, end, forward)| {
+      prediction.outs.iter().any(|out| {
+        out.forward == forward && if forward { out.dna_end_t == end } else { out.dna_start_t == start }
+      })
+    })
+    .count();
+  assert!(found * 10 >= genes.len() * 9, "only {} of {} genes were predicted", found, genes.len());
+
+  fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,34 @@
+use fgsrs::dna_helpers::get_protein;
+use fgsrs::PredictionConfig;
+
+/*
+ * translation.rs
+ * ==============
+ * Tests of the translation of predicted genes, which follows FragGeneScan 1.31: alternative start codons are
+ * translated as M for whole genome predictions only, and an incomplete codon at the end of a forward gene is
+ * translated as X.
+ */
+
+fn translate(dna: &str, forward: bool, wholegenome: bool) -> String {
+  let dna: Vec<char> = dna.chars().collect();
+  get_protein(&dna, forward, &PredictionConfig::new(wholegenome)).into_iter().collect()
+}
+
+#[test]
+fn alternative_start_codons_are_translated_as_m_for_whole_genomes() {
+  assert_eq!(translate("GTGAAACCCTAA", true, true), "MKP");
+  assert_eq!(translate("TTGAAACCCTAA", true, true), "MKP");
+  assert_eq!(translate("GTGAAACCCTAA", true, false), "VKP");
+  assert_eq!(translate("TTGAAACCCTAA", true, false), "LKP");
+
+  /* reverse genes are given on the forward strand, GTGAAACCCTAA is the reverse complement of TTAGGGTTTCAC */
+  assert_eq!(translate("TTAGGGTTTCAC", false, true), "MKP");
+  assert_eq!(translate("TTAGGGTTTCAC", false, false), "VKP");
+}
+
+#[test]
+fn incomplete_codon_at_the_end_of_a_forward_gene_is_translated_as_x() {
+  assert_eq!(translate("ATGAAACCCGG", true, false), "MKPX");
+  assert_eq!(translate("ATGAAACCCGG", true, true), "MKPX");
+  assert_eq!(translate("ATGAAACCC", true, false), "MKP");
+}
@@ -0,0 +1,236 @@
+use fgsrs::dna_helpers::{get_cg_bin, get_cg_content};
+use fgsrs::input::{header_cg_content, read_input_files_with_cg_content, with_file_cg_content, Record};
+use fgsrs::{CgBin, Prediction, PredictionConfig};
+use std::fs;
+
+mod common;
+use common::{chromosome, predictor, temp_path};
+
+/*
+ * viterbi.rs
+ * ==========
+ * Tests that decoding a sequence with checkpoints gives the same predictions as decoding it with the full viterbi
+ * matrices, that predicting a sequence in overlapping windows gives the same genes as a single pass, that genes
+ * spanning the origin of a circular sequence are predicted, that genes running off the sequence are partial and that
+ * the CG bin of the model is chosen as configured. Sequences are encoded per character, whatever its case.
+ */
+
+fn assert_identical(model: &str, wholegenome: bool, sequence: &str) {
+  let full = predictor(model, PredictionConfig::new(wholegenome).with_max_matrix_length(usize::MAX));
+  let checkpointed = predictor(model, PredictionConfig::new(wholegenome).with_max_matrix_length(0));
+  assert_eq!(
+    full.predict("seq", sequence),
+    checkpointed.predict("seq", sequence),
+    "predictions of a sequence of length {} differ",
+    sequence.len()
+  );
+}
+
+#[test]
+fn checkpointed_genome_matches_full_matrix() {
+  let sequence = chromosome(400).0;
+  let checkpointed = predictor("complete", PredictionConfig::new(true).with_max_matrix_length(0));
+  let prediction = checkpointed.predict("seq", &sequence);
+  assert!(prediction.outs.len() > 10);
+  assert_identical("complete", true, &sequence);
+}
+
+#[test]
+fn checkpointed_reads_match_full_matrix() {
+  let sequence = chromosome(400).0;
+  for length in [7, 8, 9, 64, 65, 100, 250, 1000, 4097] {
+    assert_identical("454_10", false, &sequence[..length]);
+    assert_identical("complete", true, &sequence[sequence.len() - length..]);
+  }
+  assert_identical("illumina_5", false, &sequence[..20000]);
+}
+
+#[test]
+fn windowed_genome_matches_single_pass() {
+  let sequence = chromosome(400).0;
+  let single = predictor("complete", PredictionConfig::new(true)).predict("seq", &sequence);
+  let config = PredictionConfig::new(true).with_windows(Some(20000), 5000).unwrap();
+  let windowed = predictor("complete", config).predict("seq", &sequence);
+
+  // the scores are differences of the path costs, which may differ in the last bits between windows
+  let genes = |prediction: &Prediction| -> Vec<_> {
+    prediction
+      .outs
+      .iter()
+      .map(|out| {
+        let positions = (out.dna_start_t, out.dna_end_t, out.frame, out.forward);
+        (positions, out.insert.clone(), out.delete.clone(), out.protein.clone())
+      })
+      .collect()
+  };
+  assert_eq!(genes(&single), genes(&windowed));
+}
+
+#[test]
+fn windows_longer_than_overlap_are_required() {
+  assert!(PredictionConfig::new(true).with_windows(Some(5000), 5000).is_err());
+  assert!(PredictionConfig::new(true).with_windows(None, 5000).is_ok());
+}
+
+#[test]
+fn circular_genome_predicts_gene_across_origin() {
+  let sequence = chromosome(400).0;
+  let predictor = predictor("complete", PredictionConfig::new(true));
+  let linear = predictor.predict("seq", &sequence);
+  let gene = &linear.outs[linear.outs.len() / 2];
+
+  // rotate the sequence, so the origin lies in the middle of the gene
+  let origin = (gene.dna_start_t + gene.dna_end_t) / 2;
+  let rotated = [&sequence[origin..], &sequence[..origin]].concat();
+  let circular = predictor.predict_circular("seq", &rotated, None);
+  assert_eq!(circular.circular_length, Some(sequence.len()));
+
+  let spanning = circular
+    .outs
+    .iter()
+    .find(|out| out.dna_end_t > sequence.len())
+    .expect("no gene spans the origin");
+  assert_eq!(spanning.dna_start_t, gene.dna_start_t + sequence.len() - origin);
+  assert_eq!(spanning.dna_end_t, gene.dna_end_t + sequence.len() - origin);
+  assert_eq!(spanning.protein, gene.protein);
+  assert!(circular.outs.iter().all(|out| out.dna_start_t <= sequence.len()));
+}
+
+#[test]
+fn partial_genes_lack_start_or_stop_codons() {
+  let sequence = chromosome(400).0;
+  let prediction = predictor("454_10", PredictionConfig::new(false)).predict("seq", &sequence[..20000]);
+  assert!(prediction.outs.iter().any(|out| !out.partial_start && !out.partial_end));
+  for out in &prediction.outs {
+    if !out.partial_start {
+      assert!(["ATG", "GTG", "TTG"].contains(&&out.dna[..3]), "{:?}", out);
+    }
+    if !out.partial_end {
+      assert!(["TAA", "TAG", "TGA"].contains(&&out.dna[out.dna.len() - 3..]), "{:?}", out);
+    }
+  }
+
+  // a read from the middle of a gene runs off both ends
+  let gene = prediction.outs.iter().find(|out| out.dna.len() > 400).unwrap();
+  let read = &sequence[gene.dna_start_t + 100..gene.dna_start_t + 300];
+  let read_prediction = predictor("454_10", PredictionConfig::new(false)).predict("read", read);
+  assert_eq!(read_prediction.outs.len(), 1);
+  assert!(read_prediction.outs[0].partial_start && read_prediction.outs[0].partial_end);
+}
+
+#[test]
+fn cg_bin_is_chosen_by_the_config() {
+  let sequence = chromosome(400).0;
+  let read = Record {
+    id: String::from("read"),
+    seq: sequence[1000..1300].to_string(),
+    quality: None,
+    circular: false,
+    cg_content: Some(60.5),
+  };
+  let cg_bin = |cg_bin: CgBin| {
+    let config = PredictionConfig::new(false).with_cg_bin(cg_bin).unwrap();
+    predictor("454_10", config).predict_record(&read).cg
+  };
+  assert_eq!(cg_bin(CgBin::Sequence), get_cg_bin(get_cg_content(&read.seq)));
+  assert_eq!(cg_bin(CgBin::Record), 34);
+  assert_eq!(cg_bin(CgBin::Fixed(10)), 10);
+  assert!(PredictionConfig::new(false).with_cg_bin(CgBin::Fixed(44)).is_err());
+
+  assert_eq!(get_cg_bin(12.0), 0);
+  assert_eq!(get_cg_bin(52.9), 26);
+  assert_eq!(get_cg_bin(80.0), 43);
+}
+
+#[test]
+fn cg_content_from_header_or_file() {
+  assert_eq!(header_cg_content("contig_1 gc=52.3"), Some(52.3));
+  assert_eq!(header_cg_content("contig_1 [organism=E. coli] [GC=50.8%]"), Some(50.8));
+  assert_eq!(header_cg_content("gc=52.3"), None);
+  assert_eq!(header_cg_content("contig_1 gc=high"), None);
+
+  let records = vec![("a", "GGGGCCCCAT"), ("b", "ATATATATAT")].into_iter().map(|(id, seq)| {
+    Ok(Record {
+      id: id.to_string(),
+      seq: seq.to_string(),
+      quality: None,
+      circular: false,
+      cg_content: None,
+    })
+  });
+  let records = with_file_cg_content(records).unwrap();
+  assert!(records.iter().all(|record| record.cg_content == Some(40.0)));
+
+  /* files given with -i are streamed twice, every file gets its own CG content */
+  let paths: Vec<String> = [">a\nGGGGCCCCAT\n>b\nATATATATAT\n", ">c gc=10\nGGCCGGCCAT\n"]
+    .iter()
+    .enumerate()
+    .map(|(i, fasta)| {
+      let path = temp_path(&format!("cg-{}.fna", i));
+      fs::write(&path, fasta).unwrap();
+      path.to_str().unwrap().to_string()
+    })
+    .collect();
+  let records = read_input_files_with_cg_content(paths.clone()).collect::<Result<Vec<Record>, _>>().unwrap();
+  let cg_contents: Vec<(&str, Option<f64>)> =
+    records.iter().map(|record| (record.id.as_str(), record.cg_content)).collect();
+  assert_eq!(cg_contents, vec![("a", Some(40.0)), ("b", Some(40.0)), ("c", Some(80.0))]);
+  for path in paths {
+    fs::remove_file(path).unwrap();
+  }
+}
+
+#[test]
+fn start_codons_are_only_refined_on_request() {
+  let sequence = chromosome(400).0;
+  let predict = |config: PredictionConfig| predictor("complete", config).predict("seq", &sequence);
+  let default = predict(PredictionConfig::new(true));
+  let zero_window = predict(PredictionConfig::new(true).with_refine_window(Some(0)));
+  let refined = predict(PredictionConfig::new(true).with_refine_window(Some(usize::MAX)));
+  assert_eq!(default, zero_window);
+  assert_eq!(default.outs.len(), refined.outs.len());
+
+  /* refinement only moves start codons upstream, the stop codons stay in place */
+  let mut moved = 0;
+  for (out, refined_out) in default.outs.iter().zip(&refined.outs) {
+    match out.forward {
+      true => {
+        assert_eq!(out.dna_end_t, refined_out.dna_end_t);
+        assert!(refined_out.dna_start_t <= out.dna_start_t);
+      }
+      false => {
+        assert_eq!(out.dna_start_t, refined_out.dna_start_t);
+        assert!(refined_out.dna_end_t >= out.dna_end_t);
+      }
+    }
+    moved += (out != refined_out) as usize;
+  }
+  assert!(moved > 0);
+}
+
+#[test]
+fn sequences_are_encoded_per_character() {
+  let (sequence, _) = chromosome(40);
+  let predictor = predictor("complete", PredictionConfig::new(true));
+  let prediction = predictor.predict("seq", &sequence);
+  assert!(!prediction.outs.is_empty());
+
+  /* lowercase bases give the same genes, the DNA output of forward genes keeps the case of the input */
+  let lowercase = predictor.predict("seq", &sequence.to_lowercase());
+  assert_eq!(lowercase.outs.len(), prediction.outs.len());
+  for (out, lowercase_out) in prediction.outs.iter().zip(&lowercase.outs) {
+    assert_eq!((out.dna_start_t, out.dna_end_t), (lowercase_out.dna_start_t, lowercase_out.dna_end_t));
+    assert_eq!(out.protein, lowercase_out.protein);
+    if out.forward {
+      assert_eq!(out.dna.to_lowercase(), lowercase_out.dna);
+    }
+  }
+
+  /* a character outside ASCII is a single position like N, and is written as N */
+  let gene = prediction.outs.iter().find(|out| out.forward).unwrap();
+  let position = gene.dna_start_t + 30;
+  let replace = |base: &str| format!("{}{}{}", &sequence[..position - 1], base, &sequence[position..]);
+  let unknown = predictor.predict("seq", &replace("N"));
+  let non_ascii = predictor.predict("seq", &replace("\u{e9}"));
+  assert_eq!(non_ascii, unknown);
+}